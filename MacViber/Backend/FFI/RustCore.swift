@@ -27,7 +27,11 @@ public final class RustCore {
     public func initialize() -> Bool {
         guard handle == nil else { return true }
         
-        handle = core_init()
+        if let historyDir = Self.notificationHistoryDirectory() {
+            handle = core_init_with_history(historyDir.path, Self.historyMaxAgeSecs, Self.historyMaxCount)
+        } else {
+            handle = core_init()
+        }
         
         if handle == nil {
             print("[RustCore] Failed to initialize")
@@ -38,6 +42,18 @@ public final class RustCore {
         return true
     }
     
+    private static let historyMaxAgeSecs: UInt64 = 30 * 24 * 60 * 60
+    private static let historyMaxCount: UInt32 = 1000
+    
+    /// Application Support/MacViber/notifications; the core keeps history in
+    /// memory only unless given a directory.
+    private static func notificationHistoryDirectory() -> URL? {
+        FileManager.default.urls(for: .applicationSupportDirectory, in: .userDomainMask)
+            .first?
+            .appendingPathComponent("MacViber")
+            .appendingPathComponent("notifications")
+    }
+    
     public func shutdown() {
         guard let h = handle else { return }
         core_destroy(h)
@@ -200,6 +216,101 @@ public final class RustCore {
             UUID(uuid: idBuffer[i])
         }
     }
+    
+    static let supportedSessionSchemaVersion = 1
+    
+    struct SessionSnapshot: Decodable {
+        let id: UUID
+        let name: String
+        let alias: String?
+        let workingDirectory: String
+        let status: SessionStatus
+        let createdAt: Date
+        let lastActivity: Date
+        let hasUnreadNotification: Bool
+        let isLocked: Bool
+        let isReadOnly: Bool
+        
+        private enum CodingKeys: String, CodingKey {
+            case id, name, alias, status
+            case workingDirectory = "working_directory"
+            case createdAt = "created_at"
+            case lastActivity = "last_activity"
+            case hasUnreadNotification = "has_unread_notification"
+            case isLocked = "is_locked"
+            case isReadOnly = "is_read_only"
+        }
+        
+        private struct Timestamp: Decodable {
+            let secs_since_epoch: UInt64
+            let nanos_since_epoch: UInt32
+            
+            var date: Date {
+                Date(timeIntervalSince1970: Double(secs_since_epoch) + Double(nanos_since_epoch) / 1e9)
+            }
+        }
+        
+        init(from decoder: Decoder) throws {
+            let container = try decoder.container(keyedBy: CodingKeys.self)
+            id = try container.decode(UUID.self, forKey: .id)
+            name = try container.decode(String.self, forKey: .name)
+            alias = try container.decodeIfPresent(String.self, forKey: .alias)
+            workingDirectory = try container.decode(String.self, forKey: .workingDirectory)
+            switch try container.decode(String.self, forKey: .status) {
+            case "Running": status = .running
+            case "WaitingForInput": status = .waitingForInput
+            case "Terminated": status = .terminated
+            default: status = .idle
+            }
+            createdAt = try container.decode(Timestamp.self, forKey: .createdAt).date
+            lastActivity = try container.decode(Timestamp.self, forKey: .lastActivity).date
+            hasUnreadNotification = try container.decode(Bool.self, forKey: .hasUnreadNotification)
+            isLocked = try container.decode(Bool.self, forKey: .isLocked)
+            isReadOnly = try container.decode(Bool.self, forKey: .isReadOnly)
+        }
+    }
+    
+    private struct SessionDocument: Decodable {
+        let schema_version: Int
+        let session: SessionSnapshot
+    }
+    
+    private struct SessionListDocument: Decodable {
+        let schema_version: Int
+        let sessions: [SessionSnapshot]
+    }
+    
+    func getSessionSnapshot(_ sessionId: UUID) -> SessionSnapshot? {
+        guard let h = handle else { return nil }
+        var bytes = sessionId.uuid
+        
+        let json = withUnsafePointer(to: &bytes) { ptr in
+            takeJSONString(core_get_session_json(h, ptr))
+        }
+        guard let data = json?.data(using: .utf8),
+              let document = try? JSONDecoder().decode(SessionDocument.self, from: data),
+              document.schema_version == Self.supportedSessionSchemaVersion else {
+            return nil
+        }
+        return document.session
+    }
+    
+    var allSessionSnapshots: [SessionSnapshot] {
+        guard let h = handle else { return [] }
+        
+        guard let data = takeJSONString(core_list_sessions_json(h))?.data(using: .utf8),
+              let document = try? JSONDecoder().decode(SessionListDocument.self, from: data),
+              document.schema_version == Self.supportedSessionSchemaVersion else {
+            return []
+        }
+        return document.sessions
+    }
+    
+    private func takeJSONString(_ ptr: UnsafeMutablePointer<CChar>?) -> String? {
+        guard let ptr = ptr else { return nil }
+        defer { free_string(ptr) }
+        return String(cString: ptr)
+    }
 }
 
 private struct SessionInfoFFI {
@@ -208,6 +319,7 @@ private struct SessionInfoFFI {
     var status: Int32 = -1
     var is_locked: Bool = false
     var has_unread_notification: Bool = false
+    var is_read_only: Bool = false
 }
 
 // MARK: - Dynamic Library Loading
@@ -267,6 +379,20 @@ private func core_init() -> OpaquePointer? {
     return fn()
 }
 
+private func core_init_with_history(_ historyDir: String, _ maxAgeSecs: UInt64, _ maxCount: UInt32) -> OpaquePointer? {
+    typealias InitWithHistoryFunc = @convention(c) (UnsafePointer<CChar>?, UInt64, UInt32) -> OpaquePointer?
+    guard let handle = loadLibrary() else {
+        print("[RustCore] Failed to load library")
+        return nil
+    }
+    guard let sym = dlsym(handle, "core_init_with_history") else {
+        print("[RustCore] Symbol core_init_with_history not found")
+        return nil
+    }
+    let fn = unsafeBitCast(sym, to: InitWithHistoryFunc.self)
+    return historyDir.withCString { fn($0, maxAgeSecs, maxCount) }
+}
+
 private func core_destroy(_ handle: OpaquePointer) {
     typealias DestroyFunc = @convention(c) (OpaquePointer) -> Void
     guard let dl = loadLibrary() else { return }
@@ -358,6 +484,20 @@ private func core_get_all_session_ids(_ handle: OpaquePointer, _ outIds: UnsafeM
     return unsafeBitCast(sym, to: GetAllIdsFunc.self)(handle, outIds, maxCount)
 }
 
+private func core_get_session_json(_ handle: OpaquePointer, _ sessionId: UnsafeRawPointer) -> UnsafeMutablePointer<CChar>? {
+    typealias GetSessionJSONFunc = @convention(c) (OpaquePointer, UnsafeRawPointer) -> UnsafeMutablePointer<CChar>?
+    guard let dl = loadLibrary() else { return nil }
+    guard let sym = dlsym(dl, "core_get_session_json") else { return nil }
+    return unsafeBitCast(sym, to: GetSessionJSONFunc.self)(handle, sessionId)
+}
+
+private func core_list_sessions_json(_ handle: OpaquePointer) -> UnsafeMutablePointer<CChar>? {
+    typealias ListSessionsJSONFunc = @convention(c) (OpaquePointer) -> UnsafeMutablePointer<CChar>?
+    guard let dl = loadLibrary() else { return nil }
+    guard let sym = dlsym(dl, "core_list_sessions_json") else { return nil }
+    return unsafeBitCast(sym, to: ListSessionsJSONFunc.self)(handle)
+}
+
 public final class RustPatternMatcher {
     private var handle: OpaquePointer?
     
@@ -371,6 +511,8 @@ public final class RustPatternMatcher {
         }
     }
     
+    /// Returns false if the pattern was rejected; `validate` says why.
+    @discardableResult
     public func addPattern(
         id: UUID,
         name: String,
@@ -378,17 +520,48 @@ public final class RustPatternMatcher {
         isRegex: Bool,
         isEnabled: Bool,
         autoPin: Bool
-    ) {
-        guard let h = handle else { return }
+    ) -> Bool {
+        guard let h = handle else { return false }
         var idBytes = id.uuid
         
-        name.withCString { nameCStr in
+        let status = name.withCString { nameCStr in
             pattern.withCString { patternCStr in
                 withUnsafePointer(to: &idBytes) { idPtr in
-                    _ = pattern_matcher_add_pattern(h, idPtr, nameCStr, patternCStr, isRegex, isEnabled, autoPin)
+                    pattern_matcher_add_pattern(h, idPtr, nameCStr, patternCStr, isRegex, isEnabled, autoPin)
                 }
             }
         }
+        return status == 0
+    }
+    
+    public struct ValidationError: Error {
+        public let message: String
+        /// The offending part of the pattern, when the error points at one.
+        public let range: Range<String.Index>?
+    }
+    
+    /// Checks a pattern with the same regex engine that will match it.
+    public static func validate(pattern: String, isRegex: Bool) -> ValidationError? {
+        var error = PatternErrorFFI()
+        let status = pattern.withCString { cstr in
+            pattern_validate(cstr, isRegex, &error)
+        }
+        guard status == -3 else { return nil }
+        
+        var message = ""
+        if let msgPtr = error.message {
+            message = String(cString: msgPtr)
+            free_string(msgPtr)
+        }
+        
+        var range: Range<String.Index>?
+        let utf8 = pattern.utf8
+        if error.has_span,
+           let start = utf8.index(utf8.startIndex, offsetBy: Int(error.span_start), limitedBy: utf8.endIndex),
+           let end = utf8.index(utf8.startIndex, offsetBy: Int(error.span_end), limitedBy: utf8.endIndex) {
+            range = start..<end
+        }
+        return ValidationError(message: message, range: range)
     }
     
     public func removePattern(id: UUID) {
@@ -462,6 +635,11 @@ public final class RustNotificationDetector {
         let notificationType: NotificationType
         public let message: String
         public let notificationId: UUID
+        public let context: String
+        public let isPinned: Bool
+        /// Set when a custom pattern raised the notification.
+        public let matchedPatternId: UUID?
+        public let matchedPatternName: String?
     }
     
     public func detect(text: String, sessionId: UUID) -> DetectionResult? {
@@ -486,6 +664,20 @@ public final class RustNotificationDetector {
             message = ""
         }
         
+        let context: String
+        if let contextPtr = result.context {
+            context = String(cString: contextPtr)
+            free_string(contextPtr)
+        } else {
+            context = ""
+        }
+        
+        var matchedPatternName: String?
+        if let namePtr = result.matched_pattern_name {
+            matchedPatternName = String(cString: namePtr)
+            free_string(namePtr)
+        }
+        
         guard let rustType = RustNotificationType(rawValue: result.notification_type) else {
             return nil
         }
@@ -493,7 +685,11 @@ public final class RustNotificationDetector {
         return DetectionResult(
             notificationType: rustType.toSwiftType(),
             message: message,
-            notificationId: UUID(uuid: result.notification_id)
+            notificationId: UUID(uuid: result.notification_id),
+            context: context,
+            isPinned: result.is_pinned,
+            matchedPatternId: matchedPatternName == nil ? nil : UUID(uuid: result.matched_pattern_id),
+            matchedPatternName: matchedPatternName
         )
     }
     
@@ -510,12 +706,24 @@ private struct PatternMatchResultFFI {
     var auto_pin: Bool = false
 }
 
+private struct PatternErrorFFI {
+    var message: UnsafeMutablePointer<CChar>? = nil
+    var has_span: Bool = false
+    var span_start: UInt32 = 0
+    var span_end: UInt32 = 0
+}
+
 private struct DetectionResultFFI {
     var detected: Bool = false
     var notification_type: Int32 = -1
     var message: UnsafeMutablePointer<CChar>? = nil
     var notification_id: (UInt8, UInt8, UInt8, UInt8, UInt8, UInt8, UInt8, UInt8,
                           UInt8, UInt8, UInt8, UInt8, UInt8, UInt8, UInt8, UInt8) = (0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0)
+    var context: UnsafeMutablePointer<CChar>? = nil
+    var is_pinned: Bool = false
+    var matched_pattern_id: (UInt8, UInt8, UInt8, UInt8, UInt8, UInt8, UInt8, UInt8,
+                             UInt8, UInt8, UInt8, UInt8, UInt8, UInt8, UInt8, UInt8) = (0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0)
+    var matched_pattern_name: UnsafeMutablePointer<CChar>? = nil
 }
 
 private func pattern_matcher_create() -> OpaquePointer? {
@@ -547,6 +755,17 @@ private func pattern_matcher_add_pattern(
     return unsafeBitCast(sym, to: AddFunc.self)(handle, patternId, name, pattern, isRegex, isEnabled, autoPin)
 }
 
+private func pattern_validate(
+    _ pattern: UnsafePointer<CChar>,
+    _ isRegex: Bool,
+    _ outError: UnsafeMutablePointer<PatternErrorFFI>
+) -> Int32 {
+    typealias ValidateFunc = @convention(c) (UnsafePointer<CChar>, Bool, UnsafeMutablePointer<PatternErrorFFI>) -> Int32
+    guard let dl = loadLibrary() else { return -1 }
+    guard let sym = dlsym(dl, "pattern_validate") else { return -1 }
+    return unsafeBitCast(sym, to: ValidateFunc.self)(pattern, isRegex, outError)
+}
+
 private func pattern_matcher_remove_pattern(
     _ handle: OpaquePointer,
     _ patternId: UnsafeRawPointer
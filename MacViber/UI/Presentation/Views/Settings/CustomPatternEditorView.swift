@@ -11,6 +11,7 @@ struct CustomPatternEditorView: View {
     @State private var autoPin: Bool
     @State private var testText: String = ""
     @State private var validationError: String?
+    @State private var validationErrorRange: Range<String.Index>?
 
     init(pattern: CustomPattern, onSave: @escaping (CustomPattern) -> Void, onCancel: @escaping () -> Void) {
         self.pattern = pattern
@@ -75,6 +76,10 @@ struct CustomPatternEditorView: View {
                                 .font(.caption)
                                 .foregroundColor(.red)
                         }
+
+                        if let range = validationErrorRange {
+                            errorHighlight(range)
+                        }
                     }
 
                     Toggle("Auto-pin when matched", isOn: $autoPin)
@@ -148,17 +153,33 @@ struct CustomPatternEditorView: View {
         .padding()
     }
 
+    /// The pattern with the part the regex error points at underlined.
+    private func errorHighlight(_ range: Range<String.Index>) -> some View {
+        var text = AttributedString(patternText)
+        if let lower = AttributedString.Index(range.lowerBound, within: text),
+           let upper = AttributedString.Index(range.upperBound, within: text),
+           lower < text.endIndex {
+            // An empty span still marks the character it points at.
+            let highlighted = lower..<(lower == upper ? text.index(afterCharacter: lower) : upper)
+            text[highlighted].foregroundColor = .red
+            text[highlighted].underlineStyle = .single
+        }
+        return Text(text)
+            .font(.system(.caption, design: .monospaced))
+            .foregroundColor(.secondary)
+    }
+
+    /// Validates with the Rust engine that will run the pattern, so the
+    /// editor accepts exactly what the matcher does.
     private func validatePattern() {
-        if matchMode == .regex && !patternText.isEmpty {
-            do {
-                _ = try NSRegularExpression(pattern: patternText)
-                validationError = nil
-            } catch {
-                validationError = "Invalid regex: \(error.localizedDescription)"
-            }
-        } else {
+        guard matchMode == .regex && !patternText.isEmpty,
+              let error = RustPatternMatcher.validate(pattern: patternText, isRegex: true) else {
             validationError = nil
+            validationErrorRange = nil
+            return
         }
+        validationError = "Invalid regex: \(error.message)"
+        validationErrorRange = error.range
     }
 
     private func savePattern() {
@@ -60,6 +60,11 @@ struct DetectionResult {
     var message: UnsafeMutablePointer<CChar>?
     var notification_id: (UInt8, UInt8, UInt8, UInt8, UInt8, UInt8, UInt8, UInt8,
                           UInt8, UInt8, UInt8, UInt8, UInt8, UInt8, UInt8, UInt8)
+    var context: UnsafeMutablePointer<CChar>?
+    var is_pinned: Bool
+    var matched_pattern_id: (UInt8, UInt8, UInt8, UInt8, UInt8, UInt8, UInt8, UInt8,
+                             UInt8, UInt8, UInt8, UInt8, UInt8, UInt8, UInt8, UInt8)
+    var matched_pattern_name: UnsafeMutablePointer<CChar>?
 }
 
 struct SessionInfoFFI {
@@ -68,6 +73,7 @@ struct SessionInfoFFI {
     var status: Int32
     var is_locked: Bool
     var has_unread_notification: Bool
+    var is_read_only: Bool
 }
 
 var libHandle: UnsafeMutableRawPointer!
@@ -151,7 +157,8 @@ func testCore() {
         id: (0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0),
         status: -1,
         is_locked: false,
-        has_unread_notification: false
+        has_unread_notification: false,
+        is_read_only: false
     )
     let infoResult = withUnsafePointer(to: &sessionId) { ptr in
         getInfoFn(core, ptr, &info)
@@ -259,12 +266,25 @@ func testNotificationDetector() {
     let detectFn: NotificationDetectorDetectFunc = loadSymbol("notification_detector_detect")
     let freeStringFn: FreeStringFunc = loadSymbol("free_string")
     
+    // Must match DetectionResult in macviber_core.h, or detect writes past the struct.
+    guard MemoryLayout<DetectionResult>.size == 72,
+          MemoryLayout<DetectionResult>.offset(of: \DetectionResult.context) == 32,
+          MemoryLayout<DetectionResult>.offset(of: \DetectionResult.matched_pattern_name) == 64 else {
+        print("FAIL: DetectionResult layout does not match macviber_core.h")
+        exit(1)
+    }
+    print("OK: DetectionResult layout matches macviber_core.h")
+    
     var sessionId = UUID().uuid
     var detectionResult = DetectionResult(
         detected: false,
         notification_type: -1,
         message: nil,
-        notification_id: (0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0)
+        notification_id: (0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0),
+        context: nil,
+        is_pinned: false,
+        matched_pattern_id: (0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0),
+        matched_pattern_name: nil
     )
     
     let detectStatus = "Do you want to proceed? (y/n)".withCString { text in
@@ -284,6 +304,12 @@ func testNotificationDetector() {
             print("OK: Message = \(String(cString: msg))")
             freeStringFn(msg)
         }
+        if let context = detectionResult.context {
+            freeStringFn(context)
+        }
+        if let patternName = detectionResult.matched_pattern_name {
+            freeStringFn(patternName)
+        }
     } else {
         print("FAIL: notification_detector_detect() did not detect, status=\(detectStatus)")
         exit(1)
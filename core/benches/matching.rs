@@ -0,0 +1,270 @@
+//! Compares one-pass pattern matching against checking each pattern in turn,
+//! on the kind of build and test output a terminal session produces.
+//!
+//! Run with `cargo bench --bench matching`.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use macviber_core::services::{CustomPattern, DetectionRules, PatternMatcher, RuleFormat};
+use regex::{Regex, RegexBuilder};
+use uuid::Uuid;
+
+/// Keywords users watch for, matched case-insensitively.
+const KEYWORDS: &[&str] = &[
+    "deploy finished",
+    "deployment failed",
+    "build succeeded",
+    "BUILD FAILED",
+    "connection refused",
+    "permission denied",
+    "out of memory",
+    "segmentation fault",
+    "timed out",
+    "rate limit",
+    "migration complete",
+    "server listening",
+    "all checks passed",
+    "merge conflict",
+    "authentication failed",
+    "disk full",
+    "core dumped",
+    "address already in use",
+    "certificate expired",
+    "waiting for approval",
+];
+
+/// Regexes users watch for.
+const REGEXES: &[&str] = &[
+    r"error\[E\d{4}\]",
+    r"test result: (?P<result>FAILED)\. \d+ passed",
+    r"thread '(?P<thread>[^']+)' panicked at",
+    r"(?P<count>\d+) warnings? emitted",
+    r"npm ERR! code (?P<code>E[A-Z]+)",
+    r"exit (?:code|status) (?P<status>[1-9]\d*)",
+    r"FAIL\s+(?P<file>\S+\.test\.[jt]s)",
+    r"Traceback \(most recent call last\)",
+    r"(?P<env>staging|production) deploy",
+    r"HTTP/1\.1 5\d\d",
+    r"Killed\s+\S+",
+    r"coverage: (?P<pct>[0-4]?\d)\.\d+%",
+    r"(?i)fatal: .*",
+    r"\bOOMKilled\b",
+    r"(?m)^\s*at .+ \(.+:\d+:\d+\)$",
+    r"ld: symbol\(s\) not found",
+    r"Compiling (?P<krate>macviber-core) v",
+    r"tests? (?P<failed>\d+) failed",
+    r"docker: Error response from daemon",
+    r"Finished `release` profile",
+];
+
+/// Main patterns of the rule pack the detection benchmarks load.
+const RULE_PATTERNS: &[&str] = &[
+    r"\?\s*$",
+    r"\(y/n\)",
+    r"\[Y/n\]",
+    r"\[y/N\]",
+    r"Do you want to",
+    r"Allow .* to",
+    r"Would you like",
+    r"Press Enter",
+    r"permission",
+    r"approve",
+    r"\bTask completed\b",
+    r"\bDone\b",
+    r"Successfully",
+    r"✓",
+    r"\berror:",
+    r"\bError:",
+    r"\bfailed\b",
+    r"\bFAILED\b",
+    r"Exception",
+    r"panicked at",
+    r"command not found",
+    r"No such file or directory",
+    r"Segmentation fault",
+    r"Traceback",
+    r"npm ERR!",
+    r"fatal:",
+    r"CONFLICT \(content\)",
+    r"Aborting",
+    r"Cannot find module",
+    r"SyntaxError",
+];
+
+/// About 4 KB of `cargo test` output, the size of one PTY read.
+fn build_log() -> String {
+    let mut log = String::new();
+    for (i, krate) in [
+        "libc",
+        "serde",
+        "serde_json",
+        "tokio",
+        "regex",
+        "uuid",
+        "log",
+        "thiserror",
+    ]
+    .iter()
+    .cycle()
+    .take(40)
+    .enumerate()
+    {
+        log.push_str(&format!("   Compiling {} v1.{}.{}\n", krate, i % 7, i));
+    }
+    log.push_str("warning: unused variable: `session`\n");
+    log.push_str("  --> src/lib.rs:412:13\n   |\n412 |         let session = sessions.get(&id);\n");
+    log.push_str("    Finished `test` profile [unoptimized + debuginfo] target(s) in 41.07s\n");
+    log.push_str("     Running unittests src/lib.rs (target/debug/deps/macviber_core-1f2e)\n\n");
+    log.push_str("running 24 tests\n");
+    for i in 0..24 {
+        log.push_str(&format!(
+            "test services::notification_detector::tests::case_{:02} ... ok\n",
+            i
+        ));
+    }
+    log.push_str("\ntest result: ok. 24 passed; 0 failed; 0 ignored; 0 measured\n\n");
+    log
+}
+
+/// `count` patterns, alternating keywords and regexes. Past the base lists
+/// they repeat with a numbered suffix, as users tend to add near-duplicates.
+fn custom_patterns(count: usize) -> Vec<CustomPattern> {
+    let keywords = KEYWORDS.iter().map(|k| (*k, false));
+    let regexes = REGEXES.iter().map(|r| (*r, true));
+    let base: Vec<(&str, bool)> = keywords.zip(regexes).flat_map(|(k, r)| [k, r]).collect();
+    (0..count)
+        .map(|i| {
+            let (pattern, is_regex) = base[i % base.len()];
+            let round = i / base.len();
+            let pattern = if round == 0 {
+                pattern.to_string()
+            } else {
+                format!("{}-{}", pattern, round)
+            };
+            CustomPattern {
+                id: Uuid::new_v4(),
+                name: pattern.clone(),
+                pattern,
+                is_regex,
+                is_enabled: true,
+                auto_pin: false,
+            }
+        })
+        .collect()
+}
+
+/// The per-pattern loop `PatternMatcher::match_text` used to run: one regex
+/// search per pattern, lowercasing the whole text for every keyword.
+struct NaiveMatcher {
+    patterns: Vec<(CustomPattern, Option<Regex>)>,
+}
+
+impl NaiveMatcher {
+    fn new(patterns: &[CustomPattern]) -> Self {
+        let patterns = patterns
+            .iter()
+            .map(|p| {
+                let regex = p.is_regex.then(|| Regex::new(&p.pattern).unwrap());
+                (p.clone(), regex)
+            })
+            .collect();
+        Self { patterns }
+    }
+
+    fn match_text(&self, text: &str) -> Option<&CustomPattern> {
+        self.patterns.iter().find_map(|(pattern, regex)| {
+            let matched = match regex {
+                Some(regex) => regex.is_match(text),
+                None => text
+                    .to_lowercase()
+                    .contains(&pattern.pattern.to_lowercase()),
+            };
+            matched.then_some(pattern)
+        })
+    }
+}
+
+fn rule_pack() -> String {
+    RULE_PATTERNS
+        .iter()
+        .enumerate()
+        .map(|(i, pattern)| {
+            format!(
+                "[[rules]]\nid = \"rule-{}\"\nregex = '{}'\ntype = \"Custom\"\n\n",
+                i, pattern
+            )
+        })
+        .collect()
+}
+
+/// The rule loop `DetectionRules::find` used to run: a capture search per
+/// rule until one matches.
+fn naive_rules() -> Vec<Regex> {
+    RULE_PATTERNS
+        .iter()
+        .map(|p| RegexBuilder::new(p).case_insensitive(true).build().unwrap())
+        .collect()
+}
+
+fn matchers(count: usize) -> (NaiveMatcher, PatternMatcher) {
+    let patterns = custom_patterns(count);
+    let mut matcher = PatternMatcher::new();
+    for pattern in &patterns {
+        matcher.add_pattern(pattern.clone()).unwrap();
+    }
+    (NaiveMatcher::new(&patterns), matcher)
+}
+
+fn bench_pattern_matcher(c: &mut Criterion) {
+    let miss = build_log();
+    // Only the last base pattern matches, so the loop checks every pattern.
+    let hit = format!("{}    Finished `release` profile [optimized]\n", miss);
+
+    let mut group = c.benchmark_group("custom_patterns");
+    for count in [10, 40, 100] {
+        let (naive, matcher) = matchers(count);
+        assert!(naive.match_text(&miss).is_none());
+        assert!(matcher.match_text(&miss).is_none());
+        group.bench_with_input(BenchmarkId::new("per_pattern", count), &miss, |b, text| {
+            b.iter(|| naive.match_text(black_box(text)))
+        });
+        group.bench_with_input(BenchmarkId::new("single_pass", count), &miss, |b, text| {
+            b.iter(|| matcher.match_text(black_box(text)))
+        });
+    }
+
+    let (naive, matcher) = matchers(KEYWORDS.len() + REGEXES.len());
+    assert_eq!(
+        naive.match_text(&hit).map(|p| p.id),
+        matcher.match_text(&hit).map(|p| p.id)
+    );
+    group.bench_function("per_pattern/late_match", |b| {
+        b.iter(|| naive.match_text(black_box(&hit)))
+    });
+    group.bench_function("single_pass/late_match", |b| {
+        b.iter(|| matcher.match_text(black_box(&hit)))
+    });
+    group.bench_function("match_all", |b| {
+        b.iter(|| matcher.match_all(black_box(&hit)))
+    });
+    group.finish();
+}
+
+fn bench_detection_rules(c: &mut Criterion) {
+    let rules = DetectionRules::parse(&rule_pack(), RuleFormat::Toml).unwrap();
+    let naive = naive_rules();
+    // Build output with no prompt in it: the common case, where every rule
+    // has to be ruled out.
+    let text = build_log().replace("; 0 failed", "");
+    assert!(naive.iter().all(|r| !r.is_match(&text)));
+    assert!(rules.find(&text).is_none());
+
+    let mut group = c.benchmark_group("detection_rules");
+    group.bench_function("per_rule", |b| {
+        b.iter(|| naive.iter().find_map(|r| r.captures(black_box(&text))))
+    });
+    group.bench_function("single_pass", |b| b.iter(|| rules.find(black_box(&text))));
+    group.finish();
+}
+
+criterion_group!(benches, bench_pattern_matcher, bench_detection_rules);
+criterion_main!(benches);
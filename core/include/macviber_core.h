@@ -10,31 +10,279 @@
 #include <stdint.h>
 #include <stdlib.h>
 
+/**
+ * Version of the session JSON documents. Bumped when a field is removed or
+ * changes meaning; new fields may appear without a bump.
+ */
+#define SESSION_JSON_SCHEMA_VERSION 1
+
+#define CELL_FLAG_BOLD (1 << 0)
+
+#define CELL_FLAG_DIM (1 << 1)
+
+#define CELL_FLAG_ITALIC (1 << 2)
+
+#define CELL_FLAG_UNDERLINE (1 << 3)
+
+#define CELL_FLAG_INVERSE (1 << 4)
+
+#define CELL_FLAG_HIDDEN (1 << 5)
+
+#define CELL_FLAG_STRIKETHROUGH (1 << 6)
+
+#define SEARCH_FLAG_REGEX (1 << 0)
+
+#define SEARCH_FLAG_IGNORE_CASE (1 << 1)
+
+#define SEARCH_FLAG_WHOLE_WORD (1 << 2)
+
 #define PaneSize_MINIMUM_WIDTH 300.0
 
 #define PaneSize_MINIMUM_HEIGHT 200.0
 
+#define EventFilter_ALL_KINDS ((1 << 9) - 1)
+
+#define NotificationDetector_DEFAULT_BUFFER_SIZE 10000
+
+#define NotificationDetector_DEFAULT_CONTEXT_LINES 10
+
+#define RetentionPolicy_DEFAULT_MAX_COUNT 1000
+
+#define ASCIICAST_VERSION 2
+
+#define ScrollbackConfig_DEFAULT_MAX_LINES 10000
+
+#define ScrollbackConfig_DEFAULT_MAX_BYTES ((4 * 1024) * 1024)
+
+#define SpawnSpec_DEFAULT_COLS 80
+
+#define SpawnSpec_DEFAULT_ROWS 24
+
 typedef void *CoreHandle;
 
 typedef uint8_t SessionId[16];
 
+typedef struct SpawnSpecFFI {
+  const char *workingDir;
+  /**
+   * Null runs the user's shell.
+   */
+  const char *program;
+  const char *const *args;
+  uintptr_t argsCount;
+  /**
+   * `KEY=VALUE` entries.
+   */
+  const char *const *env;
+  uintptr_t envCount;
+  const char *const *envRemove;
+  uintptr_t envRemoveCount;
+  bool loginShell;
+  uint16_t cols;
+  uint16_t rows;
+} SpawnSpecFFI;
+
 typedef struct SessionInfoFFI {
   SessionId id;
   int32_t status;
   bool isLocked;
   bool hasUnreadNotification;
+  bool isReadOnly;
 } SessionInfoFFI;
 
 typedef void (*OutputCallback)(const uint8_t*, uintptr_t, void*);
 
+typedef struct ScreenInfoFFI {
+  uint16_t cols;
+  uint16_t rows;
+  uint16_t cursorRow;
+  uint16_t cursorCol;
+  bool cursorVisible;
+  bool alternateScreen;
+} ScreenInfoFFI;
+
+/**
+ * Colors are packed as `0` for the default color, `0x01000000 | index` for
+ * palette colors and `0x02RRGGBB` for true color.
+ */
+typedef struct CellFFI {
+  uint32_t ch;
+  uint32_t fg;
+  uint32_t bg;
+  uint16_t flags;
+  uint8_t width;
+} CellFFI;
+
+/**
+ * Scrollback line indices are absolute: the oldest retained line is
+ * `first_index` and the newest is `first_index + lines - 1`.
+ */
+typedef struct ScrollbackStatsFFI {
+  uint64_t lines;
+  uint64_t bytes;
+  uint64_t firstIndex;
+  uint64_t evictedLines;
+  uint64_t evictedBytes;
+} ScrollbackStatsFFI;
+
+typedef void *SearchResultsHandle;
+
+typedef struct SearchHitFFI {
+  SessionId sessionId;
+  uint64_t line;
+  uint32_t startCol;
+  uint32_t endCol;
+} SearchHitFFI;
+
+/**
+ * Called once per session when its child process exits. `exit_code` is -1
+ * when the process was killed by a signal; `signal` is 0 otherwise.
+ */
+typedef void (*ExitCallback)(const SessionId*, int32_t, int32_t, void*);
+
+/**
+ * Called whenever a session's status changes, with the previous and new
+ * `SessionStatus` values. Runs on a background thread.
+ */
+typedef void (*StatusCallback)(const SessionId*, int32_t, int32_t, void*);
+
+/**
+ * One `Core` event. Everything it points to is valid only during the
+ * callback.
+ */
+typedef struct CoreEventFFI {
+  /**
+   * An `EventKind` value.
+   */
+  int32_t kind;
+  SessionId sessionId;
+  /**
+   * The event as a JSON object tagged with its `kind`.
+   */
+  const char *json;
+  /**
+   * The bytes of an `Output` event, which the JSON leaves out; null for
+   * other kinds.
+   */
+  const uint8_t *data;
+  uintptr_t dataLen;
+} CoreEventFFI;
+
+/**
+ * Runs on the event dispatch thread, one event at a time and in the order
+ * the events happened.
+ */
+typedef void (*EventCallback)(const struct CoreEventFFI*, void*);
+
+/**
+ * Notification passed to `NotificationCallback`. The strings are only valid
+ * for the duration of the callback; copy them to keep them.
+ */
+typedef struct NotificationFFI {
+  SessionId id;
+  SessionId sessionId;
+  int32_t notificationType;
+  const char *message;
+  const char *context;
+  bool isPinned;
+  /**
+   * Seconds since the Unix epoch.
+   */
+  double createdAt;
+  /**
+   * All zero unless a custom pattern raised the notification.
+   */
+  SessionId matchedPatternId;
+  /**
+   * Null unless a custom pattern raised the notification.
+   */
+  const char *matchedPatternName;
+  /**
+   * Bit `1 << response` is set for each reply the prompt accepts.
+   */
+  uint32_t responses;
+} NotificationFFI;
+
+typedef void (*NotificationCallback)(const struct NotificationFFI*, void*);
+
+typedef struct NotificationInfoFFI {
+  SessionId id;
+  SessionId sessionId;
+  int32_t notificationType;
+  bool isRead;
+  bool isPinned;
+  /**
+   * Seconds since the Unix epoch.
+   */
+  double createdAt;
+  /**
+   * All zero unless a custom pattern raised the notification.
+   */
+  SessionId matchedPatternId;
+  /**
+   * Bit `1 << response` is set for each reply the prompt accepts.
+   */
+  uint32_t responses;
+} NotificationInfoFFI;
+
+typedef struct PlaybackStatusFFI {
+  double position;
+  double duration;
+  double speed;
+  bool paused;
+  bool finished;
+} PlaybackStatusFFI;
+
 typedef void *PatternMatcherHandle;
 
+/**
+ * Why a pattern does not compile. `message` is freed with `free_string`;
+ * the span is a UTF-8 byte range into the pattern.
+ */
+typedef struct PatternErrorFFI {
+  char *message;
+  bool hasSpan;
+  uint32_t spanStart;
+  uint32_t spanEnd;
+} PatternErrorFFI;
+
 typedef struct PatternMatchResult {
   bool matched;
   SessionId patternId;
   bool autoPin;
 } PatternMatchResult;
 
+typedef struct PatternMatchFFI {
+  SessionId patternId;
+  /**
+   * Byte range into the matched text.
+   */
+  uint64_t start;
+  uint64_t end;
+  bool autoPin;
+  /**
+   * This match's named captures in the captures array.
+   */
+  uint32_t firstCapture;
+  uint32_t captureCount;
+  /**
+   * Named captures of this match that did not fit in the captures array.
+   */
+  uint32_t droppedCaptures;
+} PatternMatchFFI;
+
+typedef struct PatternCaptureFFI {
+  /**
+   * Free with `pattern_matcher_free_captures`.
+   */
+  char *name;
+  /**
+   * Byte range into the matched text.
+   */
+  uint64_t start;
+  uint64_t end;
+} PatternCaptureFFI;
+
 typedef void *NotificationDetectorHandle;
 
 typedef struct DetectionResult {
@@ -42,6 +290,19 @@ typedef struct DetectionResult {
   int32_t notificationType;
   char *message;
   SessionId notificationId;
+  /**
+   * Recent output lines; free with `free_string`.
+   */
+  char *context;
+  bool isPinned;
+  /**
+   * All zero unless a custom pattern matched.
+   */
+  SessionId matchedPatternId;
+  /**
+   * Null unless a custom pattern matched; free with `free_string`.
+   */
+  char *matchedPatternName;
 } DetectionResult;
 
 typedef void *SplitViewStateHandle;
@@ -50,12 +311,23 @@ typedef void *PtyHandlePtr;
 
 CoreHandle core_init(void);
 
+/**
+ * Like `core_init`, but keeps notification history in `history_dir` (null
+ * keeps it in memory only). A zero `max_age_secs` or `max_count` means no
+ * limit of that kind.
+ */
+CoreHandle core_init_with_history(const char *history_dir, uint64_t max_age_secs, uint32_t max_count);
+
 void core_destroy(CoreHandle handle);
 
 int32_t core_create_session(CoreHandle handle, const char *working_dir, SessionId *out_session_id);
 
+int32_t core_create_session_with_spec(CoreHandle handle, const struct SpawnSpecFFI *spec, SessionId *out_session_id);
+
 int32_t core_close_session(CoreHandle handle, const SessionId *session_id);
 
+int32_t core_close_session_with_policy(CoreHandle handle, const SessionId *session_id, int32_t signal, uint32_t grace_period_ms, bool force);
+
 int32_t core_session_count(CoreHandle handle);
 
 const char *core_version(void);
@@ -70,11 +342,223 @@ int32_t core_set_session_status(CoreHandle handle, const SessionId *session_id,
 
 int32_t core_get_session_info(CoreHandle handle, const SessionId *session_id, struct SessionInfoFFI *out_info);
 
+/**
+ * The whole session as `{"schema_version": 1, "session": {...}}`, or null
+ * if there is no such session. Free with `free_string`.
+ */
+char *core_get_session_json(CoreHandle handle, const SessionId *session_id);
+
+/**
+ * Every session, oldest first, as `{"schema_version": 1, "sessions": [...]}`.
+ * Free with `free_string`.
+ */
+char *core_list_sessions_json(CoreHandle handle);
+
 int32_t core_get_all_session_ids(CoreHandle handle, SessionId *out_ids, int32_t max_count);
 
-int32_t core_set_output_callback(CoreHandle _handle, const SessionId *_session_id, OutputCallback _callback, void *_context);
+int32_t core_set_output_callback(CoreHandle handle, const SessionId *session_id, OutputCallback callback, void *context);
+
+int32_t core_send_input(CoreHandle handle, const SessionId *session_id, const uint8_t *input, uintptr_t len);
+
+int32_t core_resize_session(CoreHandle handle, const SessionId *session_id, uint16_t cols, uint16_t rows);
+
+int32_t core_get_screen_info(CoreHandle handle, const SessionId *session_id, struct ScreenInfoFFI *out_info);
+
+/**
+ * Returns the visible screen as newline-separated text. Free with `free_string`.
+ */
+char *core_get_screen_text(CoreHandle handle, const SessionId *session_id);
+
+/**
+ * Copies the visible grid row by row into `out_cells`. Returns the number of
+ * cells written, at most `max_count`.
+ */
+int32_t core_get_screen_cells(CoreHandle handle, const SessionId *session_id, struct CellFFI *out_cells, int32_t max_count);
+
+int32_t core_set_scrollback_limits(CoreHandle handle, const SessionId *session_id, uint32_t max_lines, uint64_t max_bytes);
 
-int32_t core_send_input(CoreHandle _handle, const SessionId *_session_id, const uint8_t *_input, uintptr_t _len);
+int32_t core_get_scrollback_stats(CoreHandle handle, const SessionId *session_id, struct ScrollbackStatsFFI *out_stats);
+
+/**
+ * Returns scrollback lines `start..start + count` separated by newlines.
+ * With `join_wrapped`, rows split by auto-wrap are rejoined into one line.
+ * Free with `free_string`.
+ */
+char *core_get_scrollback_lines(CoreHandle handle, const SessionId *session_id, uint64_t start, uint32_t count, bool join_wrapped);
+
+/**
+ * Returns the last `count` rows of output, scrollback followed by the visible
+ * screen. Free with `free_string`.
+ */
+char *core_get_recent_lines(CoreHandle handle, const SessionId *session_id, uint32_t count, bool join_wrapped);
+
+/**
+ * Searches one session's scrollback and screen. Returns null if the session
+ * does not exist or the query is invalid. Free with `search_results_destroy`.
+ */
+SearchResultsHandle core_search_session(CoreHandle handle, const SessionId *session_id, const char *query, uint32_t flags);
+
+/**
+ * Searches every session. Hits are grouped by session, oldest session first,
+ * and navigation moves across all of them.
+ */
+SearchResultsHandle core_search_all_sessions(CoreHandle handle, const char *query, uint32_t flags);
+
+void search_results_destroy(SearchResultsHandle handle);
+
+int32_t search_results_count(SearchResultsHandle handle);
+
+int32_t search_results_get(SearchResultsHandle handle, int32_t index, struct SearchHitFFI *out_hit);
+
+/**
+ * Advances to the next hit, wrapping around. Returns the new index, or -2
+ * when there are no hits.
+ */
+int32_t search_results_next(SearchResultsHandle handle, struct SearchHitFFI *out_hit);
+
+/**
+ * Steps back to the previous hit, wrapping around; the first call selects
+ * the newest hit. Returns the new index, or -2 when there are no hits.
+ */
+int32_t search_results_previous(SearchResultsHandle handle, struct SearchHitFFI *out_hit);
+
+int32_t core_set_exit_callback(CoreHandle handle, ExitCallback callback, void *context);
+
+int32_t core_set_status_callback(CoreHandle handle, StatusCallback callback, void *context);
+
+/**
+ * Subscribes to the events whose `1 << kind` bit is set in `mask`, from
+ * `session_id` only, or from every session when it is null. Returns the id
+ * to pass to `core_unsubscribe_events`, or 0 on bad arguments.
+ */
+uint64_t core_subscribe_events(CoreHandle handle, EventCallback callback, void *context, uint32_t mask, const SessionId *session_id);
+
+/**
+ * Ends a subscription. Once this returns, its callback is no longer running
+ * and is not called again. Returns -2 if there is no such subscription.
+ */
+int32_t core_unsubscribe_events(CoreHandle handle, uint64_t subscription_id);
+
+/**
+ * How long a session sits quietly at a shell prompt before it turns idle.
+ */
+int32_t core_set_idle_timeout(CoreHandle handle, uint64_t idle_timeout_ms);
+
+int32_t core_get_exit_status(CoreHandle handle, const SessionId *session_id, int32_t *out_exit_code, int32_t *out_signal);
+
+int32_t core_set_notification_callback(CoreHandle handle, NotificationCallback callback, void *context);
+
+/**
+ * Lists notifications oldest first. `session_id` may be null to match all
+ * sessions; `notification_type`, `read_state` and `pinned_state` take -1 to
+ * match anything, and 0/1 for false/true on the states. Returns the number
+ * of entries written, at most `max_count`.
+ */
+int32_t core_list_notifications(CoreHandle handle, const SessionId *session_id, int32_t notification_type, int32_t read_state, int32_t pinned_state, struct NotificationInfoFFI *out_notifications, int32_t max_count);
+
+/**
+ * Returns the notification's message. Free with `free_string`.
+ */
+char *core_get_notification_message(CoreHandle handle, const SessionId *notification_id);
+
+/**
+ * Returns the output surrounding the notification. Free with `free_string`.
+ */
+char *core_get_notification_context(CoreHandle handle, const SessionId *notification_id);
+
+int32_t core_mark_notification_read(CoreHandle handle, const SessionId *notification_id);
+
+/**
+ * Answers a prompt with `response` (0 yes, 1 no, 2 approve, 3 deny, 4
+ * enter). Returns -3 if the prompt does not accept that reply and -4 if the
+ * session is no longer waiting on it.
+ */
+int32_t core_respond_to_notification(CoreHandle handle, const SessionId *notification_id, int32_t response);
+
+/**
+ * Marks all of a session's notifications read. Returns how many were unread.
+ */
+int32_t core_mark_session_notifications_read(CoreHandle handle, const SessionId *session_id);
+
+/**
+ * Returns 1 if the notification is now pinned, 0 if unpinned.
+ */
+int32_t core_toggle_notification_pin(CoreHandle handle, const SessionId *notification_id);
+
+/**
+ * Removes a notification. Returns -3 if it is pinned.
+ */
+int32_t core_dismiss_notification(CoreHandle handle, const SessionId *notification_id);
+
+/**
+ * Removes a session's unpinned notifications. Returns how many were removed.
+ */
+int32_t core_clear_session_notifications(CoreHandle handle, const SessionId *session_id);
+
+int32_t core_get_unread_notification_count(CoreHandle handle);
+
+/**
+ * Adds a custom pattern to every session's detector, raising `Custom`
+ * notifications that carry the pattern's id and name.
+ */
+int32_t core_add_custom_pattern(CoreHandle handle, const SessionId *pattern_id, const char *name, const char *pattern, bool is_regex, bool is_enabled, bool auto_pin);
+
+/**
+ * Sets how long repeats of a notification type are held back in each
+ * session. `notification_type` -1 sets the window for every type without
+ * its own.
+ */
+int32_t core_set_debounce_window(CoreHandle handle, int32_t notification_type, uint64_t window_ms);
+
+int32_t core_remove_custom_pattern(CoreHandle handle, const SessionId *pattern_id);
+
+/**
+ * Loads a TOML or JSON rule pack for every session and reloads it when the
+ * file changes. On -3 or -4, `out_error` (nullable) receives a message with
+ * line numbers; free it with `free_string`.
+ */
+int32_t core_load_detection_rules(CoreHandle handle, const char *path, char **out_error);
+
+/**
+ * Checks a rule pack without loading it. Same codes as
+ * `core_load_detection_rules`.
+ */
+int32_t core_validate_detection_rules(const char *path, char **out_error);
+
+void core_reset_detection_rules(CoreHandle handle);
+
+/**
+ * Records the session to an asciicast v2 file. Returns -3 if a recording is
+ * already running and -4 if the file cannot be created.
+ */
+int32_t core_start_recording(CoreHandle handle, const SessionId *session_id, const char *path);
+
+/**
+ * Stops and flushes the session's recording. Returns -3 if none is running.
+ */
+int32_t core_stop_recording(CoreHandle handle, const SessionId *session_id);
+
+bool core_is_recording(CoreHandle handle, const SessionId *session_id);
+
+/**
+ * Replays an asciicast file into a new read-only session. `idle_time_limit`
+ * of 0 or less keeps the limit stored in the file, if any. Returns -3 if the
+ * file cannot be read or parsed.
+ */
+int32_t core_open_playback(CoreHandle handle, const char *path, double speed, double idle_time_limit, SessionId *out_session_id);
+
+int32_t core_playback_pause(CoreHandle handle, const SessionId *session_id);
+
+int32_t core_playback_resume(CoreHandle handle, const SessionId *session_id);
+
+/**
+ * Jumps to `position` seconds of playback time, redrawing the screen.
+ */
+int32_t core_playback_seek(CoreHandle handle, const SessionId *session_id, double position);
+
+int32_t core_playback_set_speed(CoreHandle handle, const SessionId *session_id, double speed);
+
+int32_t core_get_playback_status(CoreHandle handle, const SessionId *session_id, struct PlaybackStatusFFI *out_status);
 
 PatternMatcherHandle pattern_matcher_create(void);
 
@@ -82,10 +566,31 @@ void pattern_matcher_destroy(PatternMatcherHandle handle);
 
 int32_t pattern_matcher_add_pattern(PatternMatcherHandle handle, const SessionId *pattern_id, const char *name, const char *pattern, bool is_regex, bool is_enabled, bool auto_pin);
 
+/**
+ * Checks a pattern before it is added, as `pattern_matcher_add_pattern` and
+ * `core_add_custom_pattern` would, which return -3 for the same patterns.
+ * Returns 0 if it compiles, or -3 and fills `out_error` (nullable).
+ */
+int32_t pattern_validate(const char *pattern, bool is_regex, struct PatternErrorFFI *out_error);
+
 int32_t pattern_matcher_remove_pattern(PatternMatcherHandle handle, const SessionId *pattern_id);
 
 int32_t pattern_matcher_match(PatternMatcherHandle handle, const char *text, struct PatternMatchResult *out_result);
 
+/**
+ * Fills `out_matches` with up to `max_matches` matches of every enabled
+ * pattern, in text order, and `out_captures` (nullable) with up to
+ * `max_captures` of their named captures, in match order. Once the captures
+ * array is full, later matches count theirs in `dropped_captures`. Returns
+ * the total number of matches, which may exceed `max_matches`.
+ */
+int32_t pattern_matcher_match_all(PatternMatcherHandle handle, const char *text, struct PatternMatchFFI *out_matches, int32_t max_matches, struct PatternCaptureFFI *out_captures, int32_t max_captures);
+
+/**
+ * Frees the capture names `pattern_matcher_match_all` wrote.
+ */
+void pattern_matcher_free_captures(struct PatternCaptureFFI *captures, int32_t count);
+
 void pattern_matcher_invalidate_cache(PatternMatcherHandle handle);
 
 NotificationDetectorHandle notification_detector_create(void);
@@ -96,6 +601,31 @@ int32_t notification_detector_detect(NotificationDetectorHandle handle, const ch
 
 void notification_detector_reset(NotificationDetectorHandle handle);
 
+/**
+ * Same as `core_set_debounce_window`, for a standalone detector.
+ */
+int32_t notification_detector_set_debounce_window(NotificationDetectorHandle handle, int32_t notification_type, uint64_t window_ms);
+
+/**
+ * Tells the detector the user typed into a session, so the next prompt
+ * there notifies even if it repeats the last one. `core_send_input` does
+ * this for core sessions.
+ */
+void notification_detector_note_input(NotificationDetectorHandle handle, const SessionId *session_id);
+
+/**
+ * The detector's own custom patterns, for use with the `pattern_matcher_*`
+ * functions. The detector owns it; do not destroy it.
+ */
+PatternMatcherHandle notification_detector_pattern_matcher(NotificationDetectorHandle handle);
+
+/**
+ * Caps the detector's rolling output buffer at `buffer_size` bytes.
+ */
+void notification_detector_set_buffer_size(NotificationDetectorHandle handle, uint32_t buffer_size);
+
+void notification_detector_set_context_lines(NotificationDetectorHandle handle, uint32_t context_lines);
+
 void free_string(char *s);
 
 SplitViewStateHandle split_view_state_create(void);
@@ -130,12 +660,28 @@ int32_t split_view_state_get_all_pane_ids(SplitViewStateHandle handle, SessionId
 
 PtyHandlePtr pty_spawn(const char *working_dir, uint16_t cols, uint16_t rows);
 
+/**
+ * Terminates with the default policy, blocking for up to its grace period.
+ */
 void pty_destroy(PtyHandlePtr handle);
 
+int32_t pty_destroy_with_policy(PtyHandlePtr handle, int32_t signal, uint32_t grace_period_ms);
+
 int32_t pty_write(PtyHandlePtr handle, const uint8_t *data, uintptr_t len);
 
 int32_t pty_read(PtyHandlePtr handle, uint8_t *buf, uintptr_t buf_len);
 
+/**
+ * Returns the number of bytes read, 0 when no output is ready yet, or -3
+ * once the PTY has closed.
+ */
+int32_t pty_try_read(PtyHandlePtr handle, uint8_t *buf, uintptr_t buf_len);
+
+/**
+ * Same return values as `pty_try_read`, waiting up to `timeout_ms` for output.
+ */
+int32_t pty_read_timeout(PtyHandlePtr handle, uint8_t *buf, uintptr_t buf_len, uint32_t timeout_ms);
+
 int32_t pty_resize(PtyHandlePtr handle, uint16_t cols, uint16_t rows);
 
 bool pty_is_alive(PtyHandlePtr handle);
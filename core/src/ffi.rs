@@ -1,8 +1,20 @@
-use crate::models::NotificationType;
-use crate::services::{NotificationDetector, PatternMatcher};
-use crate::Core;
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+use crate::models::{Notification, NotificationResponse, NotificationType, Session};
+use crate::services::{
+    validate_regex, CoreEvent, CustomPattern, DetectionRules, EventFilter, NotificationDetector,
+    NotificationFilter, PatternMatcher, RetentionPolicy, SearchHit, SearchQuery, SearchResults,
+};
+use crate::terminal::{
+    join_lines, PlaybackCommand, PlaybackOptions, ScrollbackConfig, ScrollbackLine, ShutdownPolicy,
+    ShutdownSignal, SpawnSpec,
+};
+use crate::{Core, CoreConfig};
+use serde::Serialize;
 use std::ffi::{c_char, c_void, CStr, CString};
 use std::ptr;
+use std::sync::Arc;
+use std::time::Duration;
 use uuid::Uuid;
 
 pub type CoreHandle = *mut c_void;
@@ -29,6 +41,41 @@ pub extern "C" fn core_init() -> CoreHandle {
     }
 }
 
+/// Like `core_init`, but keeps notification history in `history_dir` (null
+/// keeps it in memory only). A zero `max_age_secs` or `max_count` means no
+/// limit of that kind.
+#[no_mangle]
+pub extern "C" fn core_init_with_history(
+    history_dir: *const c_char,
+    max_age_secs: u64,
+    max_count: u32,
+) -> CoreHandle {
+    let history_dir = if history_dir.is_null() {
+        None
+    } else {
+        match unsafe { CStr::from_ptr(history_dir) }.to_str() {
+            Ok(s) => Some(s.into()),
+            Err(_) => return ptr::null_mut(),
+        }
+    };
+    let config = CoreConfig {
+        history_dir,
+        retention: RetentionPolicy::new(
+            (max_age_secs > 0).then(|| Duration::from_secs(max_age_secs)),
+            (max_count > 0).then_some(max_count as usize),
+        ),
+        idle_timeout: CoreConfig::DEFAULT_IDLE_TIMEOUT,
+    };
+
+    match Core::with_config(config) {
+        Ok(core) => Box::into_raw(Box::new(core)) as CoreHandle,
+        Err(e) => {
+            eprintln!("Failed to initialize core: {}", e);
+            ptr::null_mut()
+        }
+    }
+}
+
 #[no_mangle]
 pub extern "C" fn core_destroy(handle: CoreHandle) {
     if !handle.is_null() {
@@ -70,6 +117,118 @@ pub extern "C" fn core_create_session(
     }
 }
 
+#[repr(C)]
+pub struct SpawnSpecFFI {
+    pub working_dir: *const c_char,
+    /// Null runs the user's shell.
+    pub program: *const c_char,
+    pub args: *const *const c_char,
+    pub args_count: usize,
+    /// `KEY=VALUE` entries.
+    pub env: *const *const c_char,
+    pub env_count: usize,
+    pub env_remove: *const *const c_char,
+    pub env_remove_count: usize,
+    pub login_shell: bool,
+    pub cols: u16,
+    pub rows: u16,
+}
+
+unsafe fn c_string_array(items: *const *const c_char, count: usize) -> Option<Vec<String>> {
+    if count == 0 {
+        return Some(Vec::new());
+    }
+    if items.is_null() {
+        return None;
+    }
+
+    std::slice::from_raw_parts(items, count)
+        .iter()
+        .map(|&item| {
+            if item.is_null() {
+                None
+            } else {
+                Some(CStr::from_ptr(item).to_string_lossy().to_string())
+            }
+        })
+        .collect()
+}
+
+#[no_mangle]
+pub extern "C" fn core_create_session_with_spec(
+    handle: CoreHandle,
+    spec: *const SpawnSpecFFI,
+    out_session_id: *mut SessionId,
+) -> i32 {
+    if handle.is_null() || spec.is_null() || out_session_id.is_null() {
+        return -1;
+    }
+
+    let core = unsafe { &*(handle as *const Core) };
+    let spec = unsafe { &*spec };
+
+    if spec.working_dir.is_null() {
+        return -1;
+    }
+
+    let working_dir = unsafe {
+        CStr::from_ptr(spec.working_dir)
+            .to_string_lossy()
+            .to_string()
+    };
+    let program = if spec.program.is_null() {
+        None
+    } else {
+        Some(unsafe { CStr::from_ptr(spec.program).to_string_lossy().to_string() })
+    };
+
+    let (Some(args), Some(env), Some(env_remove)) = (unsafe {
+        (
+            c_string_array(spec.args, spec.args_count),
+            c_string_array(spec.env, spec.env_count),
+            c_string_array(spec.env_remove, spec.env_remove_count),
+        )
+    }) else {
+        return -2;
+    };
+
+    let mut env_pairs = Vec::with_capacity(env.len());
+    for entry in env {
+        match entry.split_once('=') {
+            Some((key, value)) if !key.is_empty() => {
+                env_pairs.push((key.to_string(), value.to_string()))
+            }
+            _ => return -2,
+        }
+    }
+
+    let mut spawn_spec = SpawnSpec {
+        working_dir,
+        program,
+        args,
+        env: env_pairs,
+        env_remove,
+        login_shell: spec.login_shell,
+        ..SpawnSpec::default()
+    };
+    if spec.cols > 0 && spec.rows > 0 {
+        spawn_spec = spawn_spec.with_size(spec.cols, spec.rows);
+    }
+
+    match core.create_session_with_spec(&spawn_spec) {
+        Ok(session_id) => {
+            unsafe {
+                *out_session_id = uuid_to_bytes(session_id);
+            }
+            0
+        }
+        Err(e) => {
+            eprintln!("Failed to create session: {}", e);
+            -3
+        }
+    }
+}
+
 #[no_mangle]
 pub extern "C" fn core_close_session(handle: CoreHandle, session_id: *const SessionId) -> i32 {
     if handle.is_null() || session_id.is_null() {
@@ -81,6 +240,47 @@ pub extern "C" fn core_close_session(handle: CoreHandle, session_id: *const Sess
 
     match core.close_session(uuid) {
         Ok(()) => 0,
+        Err(crate::CoreError::SessionLocked(_)) => -3,
+        Err(e) => {
+            eprintln!("Failed to close session: {}", e);
+            -2
+        }
+    }
+}
+
+fn shutdown_policy_from_ffi(signal: i32, grace_period_ms: u32) -> Option<ShutdownPolicy> {
+    let signal = match signal {
+        0 => ShutdownSignal::Hangup,
+        1 => ShutdownSignal::Terminate,
+        _ => return None,
+    };
+    Some(ShutdownPolicy::new(
+        signal,
+        Duration::from_millis(grace_period_ms as u64),
+    ))
+}
+
+#[no_mangle]
+pub extern "C" fn core_close_session_with_policy(
+    handle: CoreHandle,
+    session_id: *const SessionId,
+    signal: i32,
+    grace_period_ms: u32,
+    force: bool,
+) -> i32 {
+    if handle.is_null() || session_id.is_null() {
+        return -1;
+    }
+
+    let core = unsafe { &*(handle as *const Core) };
+    let uuid = bytes_to_uuid(unsafe { &*session_id });
+    let Some(policy) = shutdown_policy_from_ffi(signal, grace_period_ms) else {
+        return -4;
+    };
+
+    match core.close_session_with_policy(uuid, &policy, force) {
+        Ok(()) => 0,
+        Err(crate::CoreError::SessionLocked(_)) => -3,
         Err(e) => {
             eprintln!("Failed to close session: {}", e);
             -2
@@ -186,103 +386,1506 @@ pub extern "C" fn core_set_session_status(
 
     let core = unsafe { &*(handle as *const Core) };
     let uuid = bytes_to_uuid(unsafe { &*session_id });
-    let session_status = match status {
-        0 => crate::models::SessionStatus::Idle,
-        1 => crate::models::SessionStatus::Running,
-        2 => crate::models::SessionStatus::WaitingForInput,
-        3 => crate::models::SessionStatus::Terminated,
-        _ => return -3,
-    };
+    let session_status = match status {
+        0 => crate::models::SessionStatus::Idle,
+        1 => crate::models::SessionStatus::Running,
+        2 => crate::models::SessionStatus::WaitingForInput,
+        3 => crate::models::SessionStatus::Terminated,
+        _ => return -3,
+    };
+
+    if core.set_session_status(uuid, session_status).is_ok() {
+        0
+    } else {
+        -2
+    }
+}
+
+#[repr(C)]
+pub struct SessionInfoFFI {
+    pub id: SessionId,
+    pub status: i32,
+    pub is_locked: bool,
+    pub has_unread_notification: bool,
+    pub is_read_only: bool,
+}
+
+#[no_mangle]
+pub extern "C" fn core_get_session_info(
+    handle: CoreHandle,
+    session_id: *const SessionId,
+    out_info: *mut SessionInfoFFI,
+) -> i32 {
+    if handle.is_null() || session_id.is_null() || out_info.is_null() {
+        return -1;
+    }
+
+    let core = unsafe { &*(handle as *const Core) };
+    let uuid = bytes_to_uuid(unsafe { &*session_id });
+
+    if let Some(session) = core.get_session(uuid) {
+        unsafe {
+            (*out_info).id = uuid_to_bytes(session.id);
+            (*out_info).status = match session.status {
+                crate::models::SessionStatus::Idle => 0,
+                crate::models::SessionStatus::Running => 1,
+                crate::models::SessionStatus::WaitingForInput => 2,
+                crate::models::SessionStatus::Terminated => 3,
+            };
+            (*out_info).is_locked = session.is_locked;
+            (*out_info).has_unread_notification = session.has_unread_notification;
+            (*out_info).is_read_only = session.is_read_only;
+        }
+        0
+    } else {
+        -2
+    }
+}
+
+/// Version of the session JSON documents. Bumped when a field is removed or
+/// changes meaning; new fields may appear without a bump.
+pub const SESSION_JSON_SCHEMA_VERSION: u32 = 1;
+
+#[derive(Serialize)]
+struct SessionJson<'a> {
+    schema_version: u32,
+    session: &'a Session,
+}
+
+#[derive(Serialize)]
+struct SessionListJson<'a> {
+    schema_version: u32,
+    sessions: &'a [Session],
+}
+
+fn to_json_c_string(value: &impl Serialize) -> *mut c_char {
+    match serde_json::to_string(value) {
+        Ok(json) => lossy_c_string(&json).into_raw(),
+        Err(e) => {
+            eprintln!("Failed to serialize to JSON: {}", e);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// The whole session as `{"schema_version": 1, "session": {...}}`, or null
+/// if there is no such session. Free with `free_string`.
+#[no_mangle]
+pub extern "C" fn core_get_session_json(
+    handle: CoreHandle,
+    session_id: *const SessionId,
+) -> *mut c_char {
+    if handle.is_null() || session_id.is_null() {
+        return ptr::null_mut();
+    }
+
+    let core = unsafe { &*(handle as *const Core) };
+    let uuid = bytes_to_uuid(unsafe { &*session_id });
+
+    match core.get_session(uuid) {
+        Some(session) => to_json_c_string(&SessionJson {
+            schema_version: SESSION_JSON_SCHEMA_VERSION,
+            session: &session,
+        }),
+        None => ptr::null_mut(),
+    }
+}
+
+/// Every session, oldest first, as `{"schema_version": 1, "sessions": [...]}`.
+/// Free with `free_string`.
+#[no_mangle]
+pub extern "C" fn core_list_sessions_json(handle: CoreHandle) -> *mut c_char {
+    if handle.is_null() {
+        return ptr::null_mut();
+    }
+
+    let core = unsafe { &*(handle as *const Core) };
+    to_json_c_string(&SessionListJson {
+        schema_version: SESSION_JSON_SCHEMA_VERSION,
+        sessions: &core.list_sessions(),
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn core_get_all_session_ids(
+    handle: CoreHandle,
+    out_ids: *mut SessionId,
+    max_count: i32,
+) -> i32 {
+    if handle.is_null() || out_ids.is_null() || max_count <= 0 {
+        return -1;
+    }
+
+    let core = unsafe { &*(handle as *const Core) };
+    let session_ids = core.get_all_session_ids();
+    let count = session_ids.len().min(max_count as usize);
+
+    for (i, id) in session_ids.iter().take(count).enumerate() {
+        unsafe {
+            *out_ids.add(i) = uuid_to_bytes(*id);
+        }
+    }
+
+    count as i32
+}
+
+pub type OutputCallback = extern "C" fn(*const u8, usize, *mut c_void);
+
+/// Caller-owned context pointer handed back to C callbacks. The caller is
+/// responsible for keeping it valid and thread-safe until the callback is
+/// replaced or the session is closed.
+#[derive(Clone, Copy)]
+struct CallbackContext(*mut c_void);
+
+unsafe impl Send for CallbackContext {}
+unsafe impl Sync for CallbackContext {}
+
+impl CallbackContext {
+    fn get(self) -> *mut c_void {
+        self.0
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn core_set_output_callback(
+    handle: CoreHandle,
+    session_id: *const SessionId,
+    callback: OutputCallback,
+    context: *mut c_void,
+) -> i32 {
+    if handle.is_null() || session_id.is_null() {
+        return -1;
+    }
+
+    let core = unsafe { &*(handle as *const Core) };
+    let uuid = bytes_to_uuid(unsafe { &*session_id });
+    let context = CallbackContext(context);
+
+    let handler: crate::OutputHandler =
+        Arc::new(move |data: &[u8]| callback(data.as_ptr(), data.len(), context.get()));
+
+    match core.set_output_handler(uuid, Some(handler)) {
+        Ok(()) => 0,
+        Err(_) => -2,
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn core_send_input(
+    handle: CoreHandle,
+    session_id: *const SessionId,
+    input: *const u8,
+    len: usize,
+) -> i32 {
+    if handle.is_null() || session_id.is_null() || input.is_null() {
+        return -1;
+    }
+
+    let core = unsafe { &*(handle as *const Core) };
+    let uuid = bytes_to_uuid(unsafe { &*session_id });
+    let data = unsafe { std::slice::from_raw_parts(input, len) };
+
+    match core.send_input(uuid, data) {
+        Ok(()) => 0,
+        Err(crate::CoreError::SessionNotFound(_)) => -2,
+        Err(e) => {
+            eprintln!("Failed to send input: {}", e);
+            -3
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn core_resize_session(
+    handle: CoreHandle,
+    session_id: *const SessionId,
+    cols: u16,
+    rows: u16,
+) -> i32 {
+    if handle.is_null() || session_id.is_null() {
+        return -1;
+    }
+
+    let core = unsafe { &*(handle as *const Core) };
+    let uuid = bytes_to_uuid(unsafe { &*session_id });
+
+    match core.resize_session(uuid, cols, rows) {
+        Ok(()) => 0,
+        Err(_) => -2,
+    }
+}
+
+#[repr(C)]
+pub struct ScreenInfoFFI {
+    pub cols: u16,
+    pub rows: u16,
+    pub cursor_row: u16,
+    pub cursor_col: u16,
+    pub cursor_visible: bool,
+    pub alternate_screen: bool,
+}
+
+#[no_mangle]
+pub extern "C" fn core_get_screen_info(
+    handle: CoreHandle,
+    session_id: *const SessionId,
+    out_info: *mut ScreenInfoFFI,
+) -> i32 {
+    if handle.is_null() || session_id.is_null() || out_info.is_null() {
+        return -1;
+    }
+
+    let core = unsafe { &*(handle as *const Core) };
+    let uuid = bytes_to_uuid(unsafe { &*session_id });
+
+    let info = core.with_screen(uuid, |screen| {
+        let cursor = screen.cursor();
+        ScreenInfoFFI {
+            cols: screen.cols() as u16,
+            rows: screen.rows() as u16,
+            cursor_row: cursor.row as u16,
+            cursor_col: cursor.col as u16,
+            cursor_visible: cursor.visible,
+            alternate_screen: screen.is_alternate_screen(),
+        }
+    });
+
+    match info {
+        Ok(info) => {
+            unsafe { *out_info = info };
+            0
+        }
+        Err(_) => -2,
+    }
+}
+
+/// Returns the visible screen as newline-separated text. Free with `free_string`.
+#[no_mangle]
+pub extern "C" fn core_get_screen_text(
+    handle: CoreHandle,
+    session_id: *const SessionId,
+) -> *mut c_char {
+    if handle.is_null() || session_id.is_null() {
+        return ptr::null_mut();
+    }
+
+    let core = unsafe { &*(handle as *const Core) };
+    let uuid = bytes_to_uuid(unsafe { &*session_id });
+
+    match core.with_screen(uuid, |screen| screen.screen_text()) {
+        Ok(text) => CString::new(text)
+            .map(|s| s.into_raw())
+            .unwrap_or(ptr::null_mut()),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+pub const CELL_FLAG_BOLD: u16 = 1 << 0;
+pub const CELL_FLAG_DIM: u16 = 1 << 1;
+pub const CELL_FLAG_ITALIC: u16 = 1 << 2;
+pub const CELL_FLAG_UNDERLINE: u16 = 1 << 3;
+pub const CELL_FLAG_INVERSE: u16 = 1 << 4;
+pub const CELL_FLAG_HIDDEN: u16 = 1 << 5;
+pub const CELL_FLAG_STRIKETHROUGH: u16 = 1 << 6;
+
+/// Colors are packed as `0` for the default color, `0x01000000 | index` for
+/// palette colors and `0x02RRGGBB` for true color.
+#[repr(C)]
+pub struct CellFFI {
+    pub ch: u32,
+    pub fg: u32,
+    pub bg: u32,
+    pub flags: u16,
+    pub width: u8,
+}
+
+fn color_to_ffi(color: crate::terminal::Color) -> u32 {
+    match color {
+        crate::terminal::Color::Default => 0,
+        crate::terminal::Color::Indexed(n) => 0x0100_0000 | n as u32,
+        crate::terminal::Color::Rgb(r, g, b) => {
+            0x0200_0000 | (r as u32) << 16 | (g as u32) << 8 | b as u32
+        }
+    }
+}
+
+fn cell_to_ffi(cell: &crate::terminal::Cell) -> CellFFI {
+    let attrs = &cell.attrs;
+    let mut flags = 0;
+    for (set, flag) in [
+        (attrs.bold, CELL_FLAG_BOLD),
+        (attrs.dim, CELL_FLAG_DIM),
+        (attrs.italic, CELL_FLAG_ITALIC),
+        (attrs.underline, CELL_FLAG_UNDERLINE),
+        (attrs.inverse, CELL_FLAG_INVERSE),
+        (attrs.hidden, CELL_FLAG_HIDDEN),
+        (attrs.strikethrough, CELL_FLAG_STRIKETHROUGH),
+    ] {
+        if set {
+            flags |= flag;
+        }
+    }
+
+    CellFFI {
+        ch: cell.ch as u32,
+        fg: color_to_ffi(attrs.fg),
+        bg: color_to_ffi(attrs.bg),
+        flags,
+        width: cell.width,
+    }
+}
+
+/// Copies the visible grid row by row into `out_cells`. Returns the number of
+/// cells written, at most `max_count`.
+#[no_mangle]
+pub extern "C" fn core_get_screen_cells(
+    handle: CoreHandle,
+    session_id: *const SessionId,
+    out_cells: *mut CellFFI,
+    max_count: i32,
+) -> i32 {
+    if handle.is_null() || session_id.is_null() || out_cells.is_null() || max_count <= 0 {
+        return -1;
+    }
+
+    let core = unsafe { &*(handle as *const Core) };
+    let uuid = bytes_to_uuid(unsafe { &*session_id });
+
+    let written = core.with_screen(uuid, |screen| {
+        let cells = screen
+            .visible_rows()
+            .iter()
+            .flat_map(|row| row.cells.iter())
+            .take(max_count as usize);
+        let mut count = 0;
+        for (i, cell) in cells.enumerate() {
+            unsafe { out_cells.add(i).write(cell_to_ffi(cell)) };
+            count += 1;
+        }
+        count
+    });
+
+    written.unwrap_or(-2)
+}
+
+#[no_mangle]
+pub extern "C" fn core_set_scrollback_limits(
+    handle: CoreHandle,
+    session_id: *const SessionId,
+    max_lines: u32,
+    max_bytes: u64,
+) -> i32 {
+    if handle.is_null() || session_id.is_null() {
+        return -1;
+    }
+
+    let core = unsafe { &*(handle as *const Core) };
+    let uuid = bytes_to_uuid(unsafe { &*session_id });
+    let config = ScrollbackConfig::new(max_lines as usize, max_bytes as usize);
+
+    match core.set_scrollback_config(uuid, config) {
+        Ok(()) => 0,
+        Err(_) => -2,
+    }
+}
+
+/// Scrollback line indices are absolute: the oldest retained line is
+/// `first_index` and the newest is `first_index + lines - 1`.
+#[repr(C)]
+pub struct ScrollbackStatsFFI {
+    pub lines: u64,
+    pub bytes: u64,
+    pub first_index: u64,
+    pub evicted_lines: u64,
+    pub evicted_bytes: u64,
+}
+
+#[no_mangle]
+pub extern "C" fn core_get_scrollback_stats(
+    handle: CoreHandle,
+    session_id: *const SessionId,
+    out_stats: *mut ScrollbackStatsFFI,
+) -> i32 {
+    if handle.is_null() || session_id.is_null() || out_stats.is_null() {
+        return -1;
+    }
+
+    let core = unsafe { &*(handle as *const Core) };
+    let uuid = bytes_to_uuid(unsafe { &*session_id });
+
+    let stats = core.with_screen(uuid, |screen| {
+        let scrollback = screen.scrollback();
+        let stats = scrollback.stats();
+        ScrollbackStatsFFI {
+            lines: stats.lines as u64,
+            bytes: stats.bytes as u64,
+            first_index: scrollback.first_index(),
+            evicted_lines: stats.evicted_lines,
+            evicted_bytes: stats.evicted_bytes,
+        }
+    });
+
+    match stats {
+        Ok(stats) => {
+            unsafe { *out_stats = stats };
+            0
+        }
+        Err(_) => -2,
+    }
+}
+
+fn lines_to_c_string(lines: &[ScrollbackLine], join_wrapped: bool) -> *mut c_char {
+    let text = if join_wrapped {
+        join_lines(lines)
+    } else {
+        lines
+            .iter()
+            .map(|l| l.text.as_str())
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+    CString::new(text)
+        .map(|s| s.into_raw())
+        .unwrap_or(ptr::null_mut())
+}
+
+/// Returns scrollback lines `start..start + count` separated by newlines.
+/// With `join_wrapped`, rows split by auto-wrap are rejoined into one line.
+/// Free with `free_string`.
+#[no_mangle]
+pub extern "C" fn core_get_scrollback_lines(
+    handle: CoreHandle,
+    session_id: *const SessionId,
+    start: u64,
+    count: u32,
+    join_wrapped: bool,
+) -> *mut c_char {
+    if handle.is_null() || session_id.is_null() {
+        return ptr::null_mut();
+    }
+
+    let core = unsafe { &*(handle as *const Core) };
+    let uuid = bytes_to_uuid(unsafe { &*session_id });
+
+    core.with_screen(uuid, |screen| {
+        let lines: Vec<ScrollbackLine> = screen
+            .scrollback()
+            .range(start, count as usize)
+            .cloned()
+            .collect();
+        lines_to_c_string(&lines, join_wrapped)
+    })
+    .unwrap_or(ptr::null_mut())
+}
+
+/// Returns the last `count` rows of output, scrollback followed by the visible
+/// screen. Free with `free_string`.
+#[no_mangle]
+pub extern "C" fn core_get_recent_lines(
+    handle: CoreHandle,
+    session_id: *const SessionId,
+    count: u32,
+    join_wrapped: bool,
+) -> *mut c_char {
+    if handle.is_null() || session_id.is_null() {
+        return ptr::null_mut();
+    }
+
+    let core = unsafe { &*(handle as *const Core) };
+    let uuid = bytes_to_uuid(unsafe { &*session_id });
+
+    core.with_screen(uuid, |screen| {
+        lines_to_c_string(&screen.recent_lines(count as usize), join_wrapped)
+    })
+    .unwrap_or(ptr::null_mut())
+}
+
+pub type SearchResultsHandle = *mut c_void;
+
+pub const SEARCH_FLAG_REGEX: u32 = 1 << 0;
+pub const SEARCH_FLAG_IGNORE_CASE: u32 = 1 << 1;
+pub const SEARCH_FLAG_WHOLE_WORD: u32 = 1 << 2;
+
+#[repr(C)]
+pub struct SearchHitFFI {
+    pub session_id: SessionId,
+    pub line: u64,
+    pub start_col: u32,
+    pub end_col: u32,
+}
+
+impl From<&SearchHit> for SearchHitFFI {
+    fn from(hit: &SearchHit) -> Self {
+        Self {
+            session_id: uuid_to_bytes(hit.session_id),
+            line: hit.line,
+            start_col: hit.start_col as u32,
+            end_col: hit.end_col as u32,
+        }
+    }
+}
+
+fn search_query_from_ffi(query: *const c_char, flags: u32) -> Option<SearchQuery> {
+    let text = unsafe { CStr::from_ptr(query) }.to_str().ok()?;
+    Some(SearchQuery {
+        text: text.to_string(),
+        is_regex: flags & SEARCH_FLAG_REGEX != 0,
+        case_insensitive: flags & SEARCH_FLAG_IGNORE_CASE != 0,
+        whole_word: flags & SEARCH_FLAG_WHOLE_WORD != 0,
+    })
+}
+
+/// Searches one session's scrollback and screen. Returns null if the session
+/// does not exist or the query is invalid. Free with `search_results_destroy`.
+#[no_mangle]
+pub extern "C" fn core_search_session(
+    handle: CoreHandle,
+    session_id: *const SessionId,
+    query: *const c_char,
+    flags: u32,
+) -> SearchResultsHandle {
+    if handle.is_null() || session_id.is_null() || query.is_null() {
+        return ptr::null_mut();
+    }
+
+    let core = unsafe { &*(handle as *const Core) };
+    let uuid = bytes_to_uuid(unsafe { &*session_id });
+    let Some(query) = search_query_from_ffi(query, flags) else {
+        return ptr::null_mut();
+    };
+
+    match core.search_session(uuid, &query) {
+        Ok(results) => Box::into_raw(Box::new(results)) as SearchResultsHandle,
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// Searches every session. Hits are grouped by session, oldest session first,
+/// and navigation moves across all of them.
+#[no_mangle]
+pub extern "C" fn core_search_all_sessions(
+    handle: CoreHandle,
+    query: *const c_char,
+    flags: u32,
+) -> SearchResultsHandle {
+    if handle.is_null() || query.is_null() {
+        return ptr::null_mut();
+    }
+
+    let core = unsafe { &*(handle as *const Core) };
+    let Some(query) = search_query_from_ffi(query, flags) else {
+        return ptr::null_mut();
+    };
+
+    let Ok(grouped) = core.search_all_sessions(&query) else {
+        return ptr::null_mut();
+    };
+
+    let mut groups: Vec<_> = grouped.into_iter().collect();
+    groups.sort_by_key(|(id, _)| core.get_session(*id).map(|s| s.created_at));
+
+    let mut results = SearchResults::default();
+    for (_, group) in groups {
+        results.extend(group);
+    }
+    Box::into_raw(Box::new(results)) as SearchResultsHandle
+}
+
+#[no_mangle]
+pub extern "C" fn search_results_destroy(handle: SearchResultsHandle) {
+    if !handle.is_null() {
+        unsafe {
+            drop(Box::from_raw(handle as *mut SearchResults));
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn search_results_count(handle: SearchResultsHandle) -> i32 {
+    if handle.is_null() {
+        return 0;
+    }
+
+    let results = unsafe { &*(handle as *const SearchResults) };
+    results.len() as i32
+}
+
+#[no_mangle]
+pub extern "C" fn search_results_get(
+    handle: SearchResultsHandle,
+    index: i32,
+    out_hit: *mut SearchHitFFI,
+) -> i32 {
+    if handle.is_null() || out_hit.is_null() || index < 0 {
+        return -1;
+    }
+
+    let results = unsafe { &*(handle as *const SearchResults) };
+    match results.hits().get(index as usize) {
+        Some(hit) => {
+            unsafe { *out_hit = hit.into() };
+            0
+        }
+        None => -2,
+    }
+}
+
+/// Advances to the next hit, wrapping around. Returns the new index, or -2
+/// when there are no hits.
+#[no_mangle]
+pub extern "C" fn search_results_next(
+    handle: SearchResultsHandle,
+    out_hit: *mut SearchHitFFI,
+) -> i32 {
+    if handle.is_null() || out_hit.is_null() {
+        return -1;
+    }
+
+    let results = unsafe { &mut *(handle as *mut SearchResults) };
+    match results.next_hit() {
+        Some(hit) => {
+            unsafe { *out_hit = hit.into() };
+            results.current_index().map(|i| i as i32).unwrap_or(-2)
+        }
+        None => -2,
+    }
+}
+
+/// Steps back to the previous hit, wrapping around; the first call selects
+/// the newest hit. Returns the new index, or -2 when there are no hits.
+#[no_mangle]
+pub extern "C" fn search_results_previous(
+    handle: SearchResultsHandle,
+    out_hit: *mut SearchHitFFI,
+) -> i32 {
+    if handle.is_null() || out_hit.is_null() {
+        return -1;
+    }
+
+    let results = unsafe { &mut *(handle as *mut SearchResults) };
+    match results.previous_hit() {
+        Some(hit) => {
+            unsafe { *out_hit = hit.into() };
+            results.current_index().map(|i| i as i32).unwrap_or(-2)
+        }
+        None => -2,
+    }
+}
+
+/// Called once per session when its child process exits. `exit_code` is -1
+/// when the process was killed by a signal; `signal` is 0 otherwise.
+pub type ExitCallback = extern "C" fn(*const SessionId, i32, i32, *mut c_void);
+
+fn exit_status_to_ffi(status: &crate::ExitStatus) -> (i32, i32) {
+    (status.code.unwrap_or(-1), status.signal.unwrap_or(0))
+}
+
+#[no_mangle]
+pub extern "C" fn core_set_exit_callback(
+    handle: CoreHandle,
+    callback: ExitCallback,
+    context: *mut c_void,
+) -> i32 {
+    if handle.is_null() {
+        return -1;
+    }
+
+    let core = unsafe { &*(handle as *const Core) };
+    let context = CallbackContext(context);
+
+    core.set_exit_handler(Some(Arc::new(move |session_id, status| {
+        let id = uuid_to_bytes(session_id);
+        let (code, signal) = exit_status_to_ffi(status);
+        callback(&id, code, signal, context.get());
+    })));
+
+    0
+}
+
+/// Called whenever a session's status changes, with the previous and new
+/// `SessionStatus` values. Runs on a background thread.
+pub type StatusCallback = extern "C" fn(*const SessionId, i32, i32, *mut c_void);
+
+#[no_mangle]
+pub extern "C" fn core_set_status_callback(
+    handle: CoreHandle,
+    callback: StatusCallback,
+    context: *mut c_void,
+) -> i32 {
+    if handle.is_null() {
+        return -1;
+    }
+
+    let core = unsafe { &*(handle as *const Core) };
+    let context = CallbackContext(context);
+
+    core.set_status_handler(Some(Arc::new(move |session_id, previous, status| {
+        let id = uuid_to_bytes(session_id);
+        callback(&id, previous as i32, status as i32, context.get());
+    })));
+
+    0
+}
+
+/// One `Core` event. Everything it points to is valid only during the
+/// callback.
+#[repr(C)]
+pub struct CoreEventFFI {
+    /// An `EventKind` value.
+    pub kind: i32,
+    pub session_id: SessionId,
+    /// The event as a JSON object tagged with its `kind`.
+    pub json: *const c_char,
+    /// The bytes of an `Output` event, which the JSON leaves out; null for
+    /// other kinds.
+    pub data: *const u8,
+    pub data_len: usize,
+}
+
+/// Runs on the event dispatch thread, one event at a time and in the order
+/// the events happened.
+pub type EventCallback = extern "C" fn(*const CoreEventFFI, *mut c_void);
+
+/// Subscribes to the events whose `1 << kind` bit is set in `mask`, from
+/// `session_id` only, or from every session when it is null. Returns the id
+/// to pass to `core_unsubscribe_events`, or 0 on bad arguments.
+#[no_mangle]
+pub extern "C" fn core_subscribe_events(
+    handle: CoreHandle,
+    callback: EventCallback,
+    context: *mut c_void,
+    mask: u32,
+    session_id: *const SessionId,
+) -> u64 {
+    if handle.is_null() {
+        return 0;
+    }
+
+    let core = unsafe { &*(handle as *const Core) };
+    let context = CallbackContext(context);
+    let session_id = (!session_id.is_null()).then(|| bytes_to_uuid(unsafe { &*session_id }));
+    let filter = EventFilter {
+        kinds: mask,
+        session_id,
+    };
+
+    core.subscribe_events(
+        filter,
+        Arc::new(move |event| {
+            let json = serde_json::to_string(event)
+                .map(|json| lossy_c_string(&json))
+                .unwrap_or_default();
+            let data: &[u8] = match event {
+                CoreEvent::Output { data, .. } => data,
+                _ => &[],
+            };
+            let ffi = CoreEventFFI {
+                kind: event.kind() as i32,
+                session_id: uuid_to_bytes(event.session_id()),
+                json: json.as_ptr(),
+                data: if data.is_empty() {
+                    ptr::null()
+                } else {
+                    data.as_ptr()
+                },
+                data_len: data.len(),
+            };
+            callback(&ffi, context.get());
+        }),
+    )
+}
+
+/// Ends a subscription. Once this returns, its callback is no longer running
+/// and is not called again. Returns -2 if there is no such subscription.
+#[no_mangle]
+pub extern "C" fn core_unsubscribe_events(handle: CoreHandle, subscription_id: u64) -> i32 {
+    if handle.is_null() {
+        return -1;
+    }
+
+    let core = unsafe { &*(handle as *const Core) };
+    if core.unsubscribe_events(subscription_id) {
+        0
+    } else {
+        -2
+    }
+}
+
+/// How long a session sits quietly at a shell prompt before it turns idle.
+#[no_mangle]
+pub extern "C" fn core_set_idle_timeout(handle: CoreHandle, idle_timeout_ms: u64) -> i32 {
+    if handle.is_null() {
+        return -1;
+    }
+
+    let core = unsafe { &*(handle as *const Core) };
+    core.set_idle_timeout(Duration::from_millis(idle_timeout_ms));
+    0
+}
+
+#[no_mangle]
+pub extern "C" fn core_get_exit_status(
+    handle: CoreHandle,
+    session_id: *const SessionId,
+    out_exit_code: *mut i32,
+    out_signal: *mut i32,
+) -> i32 {
+    if handle.is_null() || session_id.is_null() || out_exit_code.is_null() || out_signal.is_null() {
+        return -1;
+    }
+
+    let core = unsafe { &*(handle as *const Core) };
+    let uuid = bytes_to_uuid(unsafe { &*session_id });
+
+    match core.exit_status(uuid) {
+        Ok(Some(status)) => {
+            let (code, signal) = exit_status_to_ffi(&status);
+            unsafe {
+                *out_exit_code = code;
+                *out_signal = signal;
+            }
+            0
+        }
+        Ok(None) => -3,
+        Err(_) => -2,
+    }
+}
+
+/// Notification passed to `NotificationCallback`. The strings are only valid
+/// for the duration of the callback; copy them to keep them.
+#[repr(C)]
+pub struct NotificationFFI {
+    pub id: SessionId,
+    pub session_id: SessionId,
+    pub notification_type: i32,
+    pub message: *const c_char,
+    pub context: *const c_char,
+    pub is_pinned: bool,
+    /// Seconds since the Unix epoch.
+    pub created_at: f64,
+    /// All zero unless a custom pattern raised the notification.
+    pub matched_pattern_id: SessionId,
+    /// Null unless a custom pattern raised the notification.
+    pub matched_pattern_name: *const c_char,
+    /// Bit `1 << response` is set for each reply the prompt accepts.
+    pub responses: u32,
+}
+
+pub type NotificationCallback = extern "C" fn(*const NotificationFFI, *mut c_void);
+
+fn response_mask(notification: &Notification) -> u32 {
+    notification
+        .responses
+        .keys()
+        .fold(0, |mask, response| mask | 1 << *response as u32)
+}
+
+fn system_time_to_ffi(time: std::time::SystemTime) -> f64 {
+    time.duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs_f64())
+        .unwrap_or(0.0)
+}
+
+/// Interior NULs cannot cross the C boundary; drop them rather than the text.
+fn lossy_c_string(s: &str) -> CString {
+    CString::new(s.replace('\0', "")).unwrap_or_default()
+}
+
+#[no_mangle]
+pub extern "C" fn core_set_notification_callback(
+    handle: CoreHandle,
+    callback: NotificationCallback,
+    context: *mut c_void,
+) -> i32 {
+    if handle.is_null() {
+        return -1;
+    }
+
+    let core = unsafe { &*(handle as *const Core) };
+    let context = CallbackContext(context);
+
+    core.set_notification_handler(Some(Arc::new(move |notification| {
+        let message = lossy_c_string(&notification.message);
+        let notification_context = lossy_c_string(&notification.context);
+        let pattern_name = notification
+            .matched_pattern_name
+            .as_deref()
+            .map(lossy_c_string);
+        let ffi = NotificationFFI {
+            id: uuid_to_bytes(notification.id),
+            session_id: uuid_to_bytes(notification.session_id),
+            notification_type: notification.notification_type as i32,
+            message: message.as_ptr(),
+            context: notification_context.as_ptr(),
+            is_pinned: notification.is_pinned,
+            created_at: system_time_to_ffi(notification.created_at),
+            matched_pattern_id: uuid_to_bytes(notification.matched_pattern_id.unwrap_or_default()),
+            matched_pattern_name: pattern_name.as_ref().map_or(ptr::null(), |n| n.as_ptr()),
+            responses: response_mask(notification),
+        };
+        callback(&ffi, context.get());
+    })));
+
+    0
+}
+
+#[repr(C)]
+pub struct NotificationInfoFFI {
+    pub id: SessionId,
+    pub session_id: SessionId,
+    pub notification_type: i32,
+    pub is_read: bool,
+    pub is_pinned: bool,
+    /// Seconds since the Unix epoch.
+    pub created_at: f64,
+    /// All zero unless a custom pattern raised the notification.
+    pub matched_pattern_id: SessionId,
+    /// Bit `1 << response` is set for each reply the prompt accepts.
+    pub responses: u32,
+}
+
+fn tri_state_from_ffi(value: i32) -> Option<bool> {
+    match value {
+        0 => Some(false),
+        1 => Some(true),
+        _ => None,
+    }
+}
+
+/// Lists notifications oldest first. `session_id` may be null to match all
+/// sessions; `notification_type`, `read_state` and `pinned_state` take -1 to
+/// match anything, and 0/1 for false/true on the states. Returns the number
+/// of entries written, at most `max_count`.
+#[no_mangle]
+pub extern "C" fn core_list_notifications(
+    handle: CoreHandle,
+    session_id: *const SessionId,
+    notification_type: i32,
+    read_state: i32,
+    pinned_state: i32,
+    out_notifications: *mut NotificationInfoFFI,
+    max_count: i32,
+) -> i32 {
+    if handle.is_null() || out_notifications.is_null() || max_count <= 0 {
+        return -1;
+    }
+
+    let core = unsafe { &*(handle as *const Core) };
+    let filter = NotificationFilter {
+        session_id: (!session_id.is_null()).then(|| bytes_to_uuid(unsafe { &*session_id })),
+        notification_type: notification_type_from_ffi(notification_type),
+        is_read: tri_state_from_ffi(read_state),
+        is_pinned: tri_state_from_ffi(pinned_state),
+    };
+
+    let notifications = core.list_notifications(&filter);
+    let count = notifications.len().min(max_count as usize);
+
+    for (i, notification) in notifications.iter().take(count).enumerate() {
+        unsafe {
+            *out_notifications.add(i) = NotificationInfoFFI {
+                id: uuid_to_bytes(notification.id),
+                session_id: uuid_to_bytes(notification.session_id),
+                notification_type: notification.notification_type as i32,
+                is_read: notification.is_read,
+                is_pinned: notification.is_pinned,
+                created_at: system_time_to_ffi(notification.created_at),
+                matched_pattern_id: uuid_to_bytes(
+                    notification.matched_pattern_id.unwrap_or_default(),
+                ),
+                responses: response_mask(notification),
+            };
+        }
+    }
+
+    count as i32
+}
+
+/// Returns the notification's message. Free with `free_string`.
+#[no_mangle]
+pub extern "C" fn core_get_notification_message(
+    handle: CoreHandle,
+    notification_id: *const SessionId,
+) -> *mut c_char {
+    if handle.is_null() || notification_id.is_null() {
+        return ptr::null_mut();
+    }
+
+    let core = unsafe { &*(handle as *const Core) };
+    let uuid = bytes_to_uuid(unsafe { &*notification_id });
+
+    match core.get_notification(uuid) {
+        Some(notification) => lossy_c_string(&notification.message).into_raw(),
+        None => ptr::null_mut(),
+    }
+}
+
+/// Returns the output surrounding the notification. Free with `free_string`.
+#[no_mangle]
+pub extern "C" fn core_get_notification_context(
+    handle: CoreHandle,
+    notification_id: *const SessionId,
+) -> *mut c_char {
+    if handle.is_null() || notification_id.is_null() {
+        return ptr::null_mut();
+    }
+
+    let core = unsafe { &*(handle as *const Core) };
+    let uuid = bytes_to_uuid(unsafe { &*notification_id });
+
+    match core.get_notification(uuid) {
+        Some(notification) => lossy_c_string(&notification.context).into_raw(),
+        None => ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn core_mark_notification_read(
+    handle: CoreHandle,
+    notification_id: *const SessionId,
+) -> i32 {
+    if handle.is_null() || notification_id.is_null() {
+        return -1;
+    }
+
+    let core = unsafe { &*(handle as *const Core) };
+    let uuid = bytes_to_uuid(unsafe { &*notification_id });
+
+    match core.mark_notification_read(uuid) {
+        Ok(()) => 0,
+        Err(_) => -2,
+    }
+}
+
+/// Answers a prompt with `response` (0 yes, 1 no, 2 approve, 3 deny, 4
+/// enter). Returns -3 if the prompt does not accept that reply and -4 if the
+/// session is no longer waiting on it.
+#[no_mangle]
+pub extern "C" fn core_respond_to_notification(
+    handle: CoreHandle,
+    notification_id: *const SessionId,
+    response: i32,
+) -> i32 {
+    if handle.is_null() || notification_id.is_null() {
+        return -1;
+    }
+    let Some(response) = notification_response_from_ffi(response) else {
+        return -1;
+    };
+
+    let core = unsafe { &*(handle as *const Core) };
+    let uuid = bytes_to_uuid(unsafe { &*notification_id });
+
+    match core.respond_to_notification(uuid, response) {
+        Ok(()) => 0,
+        Err(crate::CoreError::InvalidResponse(..)) => -3,
+        Err(crate::CoreError::StaleNotification(_)) => -4,
+        Err(e) => {
+            eprintln!("Failed to respond to notification: {}", e);
+            -2
+        }
+    }
+}
+
+/// Marks all of a session's notifications read. Returns how many were unread.
+#[no_mangle]
+pub extern "C" fn core_mark_session_notifications_read(
+    handle: CoreHandle,
+    session_id: *const SessionId,
+) -> i32 {
+    if handle.is_null() || session_id.is_null() {
+        return -1;
+    }
+
+    let core = unsafe { &*(handle as *const Core) };
+    let uuid = bytes_to_uuid(unsafe { &*session_id });
+    core.mark_session_notifications_read(uuid) as i32
+}
+
+/// Returns 1 if the notification is now pinned, 0 if unpinned.
+#[no_mangle]
+pub extern "C" fn core_toggle_notification_pin(
+    handle: CoreHandle,
+    notification_id: *const SessionId,
+) -> i32 {
+    if handle.is_null() || notification_id.is_null() {
+        return -1;
+    }
+
+    let core = unsafe { &*(handle as *const Core) };
+    let uuid = bytes_to_uuid(unsafe { &*notification_id });
+
+    match core.toggle_notification_pin(uuid) {
+        Ok(pinned) => pinned as i32,
+        Err(_) => -2,
+    }
+}
+
+/// Removes a notification. Returns -3 if it is pinned.
+#[no_mangle]
+pub extern "C" fn core_dismiss_notification(
+    handle: CoreHandle,
+    notification_id: *const SessionId,
+) -> i32 {
+    if handle.is_null() || notification_id.is_null() {
+        return -1;
+    }
+
+    let core = unsafe { &*(handle as *const Core) };
+    let uuid = bytes_to_uuid(unsafe { &*notification_id });
+
+    match core.dismiss_notification(uuid) {
+        Ok(()) => 0,
+        Err(crate::CoreError::NotificationPinned(_)) => -3,
+        Err(_) => -2,
+    }
+}
+
+/// Removes a session's unpinned notifications. Returns how many were removed.
+#[no_mangle]
+pub extern "C" fn core_clear_session_notifications(
+    handle: CoreHandle,
+    session_id: *const SessionId,
+) -> i32 {
+    if handle.is_null() || session_id.is_null() {
+        return -1;
+    }
+
+    let core = unsafe { &*(handle as *const Core) };
+    let uuid = bytes_to_uuid(unsafe { &*session_id });
+    core.clear_session_notifications(uuid) as i32
+}
+
+#[no_mangle]
+pub extern "C" fn core_get_unread_notification_count(handle: CoreHandle) -> i32 {
+    if handle.is_null() {
+        return -1;
+    }
+
+    let core = unsafe { &*(handle as *const Core) };
+    core.unread_notification_count() as i32
+}
+
+/// Adds a custom pattern to every session's detector, raising `Custom`
+/// notifications that carry the pattern's id and name.
+#[no_mangle]
+pub extern "C" fn core_add_custom_pattern(
+    handle: CoreHandle,
+    pattern_id: *const SessionId,
+    name: *const c_char,
+    pattern: *const c_char,
+    is_regex: bool,
+    is_enabled: bool,
+    auto_pin: bool,
+) -> i32 {
+    if handle.is_null() || pattern_id.is_null() || name.is_null() || pattern.is_null() {
+        return -1;
+    }
+
+    let core = unsafe { &*(handle as *const Core) };
+    let custom_pattern =
+        custom_pattern_from_ffi(pattern_id, name, pattern, is_regex, is_enabled, auto_pin);
+    match core.add_custom_pattern(custom_pattern) {
+        Ok(()) => 0,
+        Err(_) => -3,
+    }
+}
+
+/// Sets how long repeats of a notification type are held back in each
+/// session. `notification_type` -1 sets the window for every type without
+/// its own.
+#[no_mangle]
+pub extern "C" fn core_set_debounce_window(
+    handle: CoreHandle,
+    notification_type: i32,
+    window_ms: u64,
+) -> i32 {
+    if handle.is_null() {
+        return -1;
+    }
+    let notification_type = match notification_type {
+        -1 => None,
+        value => match notification_type_from_ffi(value) {
+            Some(notification_type) => Some(notification_type),
+            None => return -1,
+        },
+    };
+
+    let core = unsafe { &*(handle as *const Core) };
+    core.set_debounce_window(notification_type, Duration::from_millis(window_ms));
+    0
+}
+
+#[no_mangle]
+pub extern "C" fn core_remove_custom_pattern(
+    handle: CoreHandle,
+    pattern_id: *const SessionId,
+) -> i32 {
+    if handle.is_null() || pattern_id.is_null() {
+        return -1;
+    }
+
+    let core = unsafe { &*(handle as *const Core) };
+    core.remove_custom_pattern(bytes_to_uuid(unsafe { &*pattern_id }));
+    0
+}
+
+/// Maps a rule loading result to 0, -3 (invalid rules) or -4 (unreadable
+/// file), describing any failure through `out_error` when it is non-null.
+fn rules_result_code(result: Result<(), crate::CoreError>, out_error: *mut *mut c_char) -> i32 {
+    let (code, message) = match result {
+        Ok(()) => return 0,
+        Err(e @ crate::CoreError::InvalidRules(_)) => (-3, e.to_string()),
+        Err(e) => (-4, e.to_string()),
+    };
+    if !out_error.is_null() {
+        unsafe { *out_error = lossy_c_string(&message).into_raw() };
+    }
+    code
+}
+
+/// Loads a TOML or JSON rule pack for every session and reloads it when the
+/// file changes. On -3 or -4, `out_error` (nullable) receives a message with
+/// line numbers; free it with `free_string`.
+#[no_mangle]
+pub extern "C" fn core_load_detection_rules(
+    handle: CoreHandle,
+    path: *const c_char,
+    out_error: *mut *mut c_char,
+) -> i32 {
+    if handle.is_null() || path.is_null() {
+        return -1;
+    }
+
+    let core = unsafe { &*(handle as *const Core) };
+    let path = match unsafe { CStr::from_ptr(path) }.to_str() {
+        Ok(s) => s,
+        Err(_) => return -1,
+    };
+
+    rules_result_code(core.load_detection_rules(path), out_error)
+}
+
+/// Checks a rule pack without loading it. Same codes as
+/// `core_load_detection_rules`.
+#[no_mangle]
+pub extern "C" fn core_validate_detection_rules(
+    path: *const c_char,
+    out_error: *mut *mut c_char,
+) -> i32 {
+    if path.is_null() {
+        return -1;
+    }
+
+    let path = match unsafe { CStr::from_ptr(path) }.to_str() {
+        Ok(s) => s,
+        Err(_) => return -1,
+    };
+
+    rules_result_code(DetectionRules::load(path).map(|_| ()), out_error)
+}
+
+#[no_mangle]
+pub extern "C" fn core_reset_detection_rules(handle: CoreHandle) {
+    if !handle.is_null() {
+        let core = unsafe { &*(handle as *const Core) };
+        core.reset_detection_rules();
+    }
+}
+
+/// Records the session to an asciicast v2 file. Returns -3 if a recording is
+/// already running and -4 if the file cannot be created.
+#[no_mangle]
+pub extern "C" fn core_start_recording(
+    handle: CoreHandle,
+    session_id: *const SessionId,
+    path: *const c_char,
+) -> i32 {
+    if handle.is_null() || session_id.is_null() || path.is_null() {
+        return -1;
+    }
+
+    let core = unsafe { &*(handle as *const Core) };
+    let uuid = bytes_to_uuid(unsafe { &*session_id });
+    let path = match unsafe { CStr::from_ptr(path) }.to_str() {
+        Ok(s) => s,
+        Err(_) => return -1,
+    };
+
+    match core.start_recording(uuid, path) {
+        Ok(()) => 0,
+        Err(crate::CoreError::SessionNotFound(_)) => -2,
+        Err(crate::CoreError::AlreadyRecording(_)) => -3,
+        Err(e) => {
+            eprintln!("Failed to start recording: {}", e);
+            -4
+        }
+    }
+}
+
+/// Stops and flushes the session's recording. Returns -3 if none is running.
+#[no_mangle]
+pub extern "C" fn core_stop_recording(handle: CoreHandle, session_id: *const SessionId) -> i32 {
+    if handle.is_null() || session_id.is_null() {
+        return -1;
+    }
+
+    let core = unsafe { &*(handle as *const Core) };
+    let uuid = bytes_to_uuid(unsafe { &*session_id });
+
+    match core.stop_recording(uuid) {
+        Ok(()) => 0,
+        Err(crate::CoreError::SessionNotFound(_)) => -2,
+        Err(crate::CoreError::NotRecording(_)) => -3,
+        Err(e) => {
+            eprintln!("Failed to finish recording: {}", e);
+            -4
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn core_is_recording(handle: CoreHandle, session_id: *const SessionId) -> bool {
+    if handle.is_null() || session_id.is_null() {
+        return false;
+    }
+
+    let core = unsafe { &*(handle as *const Core) };
+    let uuid = bytes_to_uuid(unsafe { &*session_id });
+    core.is_recording(uuid)
+}
+
+/// Replays an asciicast file into a new read-only session. `idle_time_limit`
+/// of 0 or less keeps the limit stored in the file, if any. Returns -3 if the
+/// file cannot be read or parsed.
+#[no_mangle]
+pub extern "C" fn core_open_playback(
+    handle: CoreHandle,
+    path: *const c_char,
+    speed: f64,
+    idle_time_limit: f64,
+    out_session_id: *mut SessionId,
+) -> i32 {
+    if handle.is_null() || path.is_null() || out_session_id.is_null() {
+        return -1;
+    }
+
+    let core = unsafe { &*(handle as *const Core) };
+    let path = match unsafe { CStr::from_ptr(path) }.to_str() {
+        Ok(s) => s,
+        Err(_) => return -2,
+    };
+    let options = PlaybackOptions {
+        speed,
+        idle_time_limit: (idle_time_limit > 0.0).then_some(idle_time_limit),
+    };
+
+    match core.open_playback(path, &options) {
+        Ok(session_id) => {
+            unsafe {
+                *out_session_id = uuid_to_bytes(session_id);
+            }
+            0
+        }
+        Err(e) => {
+            eprintln!("Failed to open playback: {}", e);
+            -3
+        }
+    }
+}
+
+fn control_playback_ffi(
+    handle: CoreHandle,
+    session_id: *const SessionId,
+    command: PlaybackCommand,
+) -> i32 {
+    if handle.is_null() || session_id.is_null() {
+        return -1;
+    }
+
+    let core = unsafe { &*(handle as *const Core) };
+    let uuid = bytes_to_uuid(unsafe { &*session_id });
 
-    if core.set_session_status(uuid, session_status).is_ok() {
-        0
-    } else {
-        -2
+    match core.control_playback(uuid, command) {
+        Ok(()) => 0,
+        Err(crate::CoreError::SessionNotFound(_)) => -2,
+        Err(_) => -3,
     }
 }
 
-#[repr(C)]
-pub struct SessionInfoFFI {
-    pub id: SessionId,
-    pub status: i32,
-    pub is_locked: bool,
-    pub has_unread_notification: bool,
+#[no_mangle]
+pub extern "C" fn core_playback_pause(handle: CoreHandle, session_id: *const SessionId) -> i32 {
+    control_playback_ffi(handle, session_id, PlaybackCommand::Pause)
 }
 
 #[no_mangle]
-pub extern "C" fn core_get_session_info(
+pub extern "C" fn core_playback_resume(handle: CoreHandle, session_id: *const SessionId) -> i32 {
+    control_playback_ffi(handle, session_id, PlaybackCommand::Resume)
+}
+
+/// Jumps to `position` seconds of playback time, redrawing the screen.
+#[no_mangle]
+pub extern "C" fn core_playback_seek(
     handle: CoreHandle,
     session_id: *const SessionId,
-    out_info: *mut SessionInfoFFI,
+    position: f64,
 ) -> i32 {
-    if handle.is_null() || session_id.is_null() || out_info.is_null() {
+    if !position.is_finite() {
         return -1;
     }
+    control_playback_ffi(handle, session_id, PlaybackCommand::Seek(position))
+}
 
-    let core = unsafe { &*(handle as *const Core) };
-    let uuid = bytes_to_uuid(unsafe { &*session_id });
-
-    if let Some(session) = core.get_session(uuid) {
-        unsafe {
-            (*out_info).id = uuid_to_bytes(session.id);
-            (*out_info).status = match session.status {
-                crate::models::SessionStatus::Idle => 0,
-                crate::models::SessionStatus::Running => 1,
-                crate::models::SessionStatus::WaitingForInput => 2,
-                crate::models::SessionStatus::Terminated => 3,
-            };
-            (*out_info).is_locked = session.is_locked;
-            (*out_info).has_unread_notification = session.has_unread_notification;
-        }
-        0
-    } else {
-        -2
+#[no_mangle]
+pub extern "C" fn core_playback_set_speed(
+    handle: CoreHandle,
+    session_id: *const SessionId,
+    speed: f64,
+) -> i32 {
+    if !(speed.is_finite() && speed > 0.0) {
+        return -1;
     }
+    control_playback_ffi(handle, session_id, PlaybackCommand::SetSpeed(speed))
+}
+
+#[repr(C)]
+pub struct PlaybackStatusFFI {
+    pub position: f64,
+    pub duration: f64,
+    pub speed: f64,
+    pub paused: bool,
+    pub finished: bool,
 }
 
 #[no_mangle]
-pub extern "C" fn core_get_all_session_ids(
+pub extern "C" fn core_get_playback_status(
     handle: CoreHandle,
-    out_ids: *mut SessionId,
-    max_count: i32,
+    session_id: *const SessionId,
+    out_status: *mut PlaybackStatusFFI,
 ) -> i32 {
-    if handle.is_null() || out_ids.is_null() || max_count <= 0 {
+    if handle.is_null() || session_id.is_null() || out_status.is_null() {
         return -1;
     }
 
     let core = unsafe { &*(handle as *const Core) };
-    let session_ids = core.get_all_session_ids();
-    let count = session_ids.len().min(max_count as usize);
+    let uuid = bytes_to_uuid(unsafe { &*session_id });
 
-    for (i, id) in session_ids.iter().take(count).enumerate() {
-        unsafe {
-            *out_ids.add(i) = uuid_to_bytes(*id);
+    match core.playback_status(uuid) {
+        Ok(status) => {
+            unsafe {
+                *out_status = PlaybackStatusFFI {
+                    position: status.position,
+                    duration: status.duration,
+                    speed: status.speed,
+                    paused: status.paused,
+                    finished: status.finished,
+                };
+            }
+            0
         }
+        Err(crate::CoreError::SessionNotFound(_)) => -2,
+        Err(_) => -3,
     }
-
-    count as i32
-}
-
-pub type OutputCallback = extern "C" fn(*const u8, usize, *mut c_void);
-
-#[no_mangle]
-pub extern "C" fn core_set_output_callback(
-    _handle: CoreHandle,
-    _session_id: *const SessionId,
-    _callback: OutputCallback,
-    _context: *mut c_void,
-) -> i32 {
-    0
-}
-
-#[no_mangle]
-pub extern "C" fn core_send_input(
-    _handle: CoreHandle,
-    _session_id: *const SessionId,
-    _input: *const u8,
-    _len: usize,
-) -> i32 {
-    0
 }
 
 #[no_mangle]
@@ -299,6 +1902,47 @@ pub extern "C" fn pattern_matcher_destroy(handle: PatternMatcherHandle) {
     }
 }
 
+fn notification_type_from_ffi(value: i32) -> Option<NotificationType> {
+    match value {
+        0 => Some(NotificationType::Question),
+        1 => Some(NotificationType::PermissionRequest),
+        2 => Some(NotificationType::Completion),
+        3 => Some(NotificationType::Error),
+        4 => Some(NotificationType::Custom),
+        _ => None,
+    }
+}
+
+fn notification_response_from_ffi(value: i32) -> Option<NotificationResponse> {
+    match value {
+        0 => Some(NotificationResponse::Yes),
+        1 => Some(NotificationResponse::No),
+        2 => Some(NotificationResponse::Approve),
+        3 => Some(NotificationResponse::Deny),
+        4 => Some(NotificationResponse::Enter),
+        _ => None,
+    }
+}
+
+/// Builds a pattern from non-null FFI arguments.
+fn custom_pattern_from_ffi(
+    pattern_id: *const SessionId,
+    name: *const c_char,
+    pattern: *const c_char,
+    is_regex: bool,
+    is_enabled: bool,
+    auto_pin: bool,
+) -> CustomPattern {
+    CustomPattern {
+        id: bytes_to_uuid(unsafe { &*pattern_id }),
+        name: unsafe { CStr::from_ptr(name).to_string_lossy().to_string() },
+        pattern: unsafe { CStr::from_ptr(pattern).to_string_lossy().to_string() },
+        is_regex,
+        is_enabled,
+        auto_pin,
+    }
+}
+
 #[no_mangle]
 pub extern "C" fn pattern_matcher_add_pattern(
     handle: PatternMatcherHandle,
@@ -314,21 +1958,57 @@ pub extern "C" fn pattern_matcher_add_pattern(
     }
 
     let matcher = unsafe { &mut *(handle as *mut PatternMatcher) };
-    let id = bytes_to_uuid(unsafe { &*pattern_id });
-    let name_str = unsafe { CStr::from_ptr(name).to_string_lossy().to_string() };
-    let pattern_str = unsafe { CStr::from_ptr(pattern).to_string_lossy().to_string() };
+    let custom_pattern =
+        custom_pattern_from_ffi(pattern_id, name, pattern, is_regex, is_enabled, auto_pin);
+    match matcher.add_pattern(custom_pattern) {
+        Ok(()) => 0,
+        Err(_) => -3,
+    }
+}
 
-    let custom_pattern = crate::services::CustomPattern {
-        id,
-        name: name_str,
-        pattern: pattern_str,
-        is_regex,
-        is_enabled,
-        auto_pin,
+/// Why a pattern does not compile. `message` is freed with `free_string`;
+/// the span is a UTF-8 byte range into the pattern.
+#[repr(C)]
+pub struct PatternErrorFFI {
+    pub message: *mut c_char,
+    pub has_span: bool,
+    pub span_start: u32,
+    pub span_end: u32,
+}
+
+/// Checks a pattern before it is added, as `pattern_matcher_add_pattern` and
+/// `core_add_custom_pattern` would, which return -3 for the same patterns.
+/// Returns 0 if it compiles, or -3 and fills `out_error` (nullable).
+#[no_mangle]
+pub extern "C" fn pattern_validate(
+    pattern: *const c_char,
+    is_regex: bool,
+    out_error: *mut PatternErrorFFI,
+) -> i32 {
+    if pattern.is_null() {
+        return -1;
+    }
+
+    let pattern = unsafe { CStr::from_ptr(pattern).to_string_lossy() };
+    if !is_regex {
+        return 0;
+    }
+    let Err(error) = validate_regex(&pattern) else {
+        return 0;
     };
 
-    matcher.add_pattern(custom_pattern);
-    0
+    if !out_error.is_null() {
+        let span = error.span.clone().unwrap_or_default();
+        unsafe {
+            *out_error = PatternErrorFFI {
+                message: lossy_c_string(&error.message).into_raw(),
+                has_span: error.span.is_some(),
+                span_start: span.start as u32,
+                span_end: span.end as u32,
+            };
+        }
+    }
+    -3
 }
 
 #[no_mangle]
@@ -381,11 +2061,108 @@ pub extern "C" fn pattern_matcher_match(
     0
 }
 
+#[repr(C)]
+pub struct PatternMatchFFI {
+    pub pattern_id: SessionId,
+    /// Byte range into the matched text.
+    pub start: u64,
+    pub end: u64,
+    pub auto_pin: bool,
+    /// This match's named captures in the captures array.
+    pub first_capture: u32,
+    pub capture_count: u32,
+    /// Named captures of this match that did not fit in the captures array.
+    pub dropped_captures: u32,
+}
+
+#[repr(C)]
+pub struct PatternCaptureFFI {
+    /// Free with `pattern_matcher_free_captures`.
+    pub name: *mut c_char,
+    /// Byte range into the matched text.
+    pub start: u64,
+    pub end: u64,
+}
+
+/// Fills `out_matches` with up to `max_matches` matches of every enabled
+/// pattern, in text order, and `out_captures` (nullable) with up to
+/// `max_captures` of their named captures, in match order. Once the captures
+/// array is full, later matches count theirs in `dropped_captures`. Returns
+/// the total number of matches, which may exceed `max_matches`.
+#[no_mangle]
+pub extern "C" fn pattern_matcher_match_all(
+    handle: PatternMatcherHandle,
+    text: *const c_char,
+    out_matches: *mut PatternMatchFFI,
+    max_matches: i32,
+    out_captures: *mut PatternCaptureFFI,
+    max_captures: i32,
+) -> i32 {
+    if handle.is_null() || text.is_null() || (out_matches.is_null() && max_matches > 0) {
+        return -1;
+    }
+
+    let matcher = unsafe { &*(handle as *const PatternMatcher) };
+    let text_str = unsafe { CStr::from_ptr(text).to_string_lossy() };
+    let matches = matcher.match_all(&text_str);
+
+    let max_captures = if out_captures.is_null() {
+        0
+    } else {
+        max_captures.max(0) as usize
+    };
+    let mut written_captures = 0;
+    for (i, m) in matches.iter().take(max_matches.max(0) as usize).enumerate() {
+        let first_capture = written_captures;
+        for capture in &m.captures {
+            if written_captures == max_captures {
+                break;
+            }
+            unsafe {
+                *out_captures.add(written_captures) = PatternCaptureFFI {
+                    name: lossy_c_string(&capture.name).into_raw(),
+                    start: capture.range.start as u64,
+                    end: capture.range.end as u64,
+                };
+            }
+            written_captures += 1;
+        }
+        unsafe {
+            *out_matches.add(i) = PatternMatchFFI {
+                pattern_id: uuid_to_bytes(m.pattern.id),
+                start: m.range.start as u64,
+                end: m.range.end as u64,
+                auto_pin: m.pattern.auto_pin,
+                first_capture: first_capture as u32,
+                capture_count: (written_captures - first_capture) as u32,
+                dropped_captures: (m.captures.len() - (written_captures - first_capture)) as u32,
+            };
+        }
+    }
+
+    matches.len() as i32
+}
+
+/// Frees the capture names `pattern_matcher_match_all` wrote.
+#[no_mangle]
+pub extern "C" fn pattern_matcher_free_captures(captures: *mut PatternCaptureFFI, count: i32) {
+    if captures.is_null() {
+        return;
+    }
+    for i in 0..count.max(0) as usize {
+        let capture = unsafe { &mut *captures.add(i) };
+        free_string(capture.name);
+        capture.name = ptr::null_mut();
+    }
+}
+
 #[no_mangle]
 pub extern "C" fn pattern_matcher_invalidate_cache(handle: PatternMatcherHandle) {
     if !handle.is_null() {
         let matcher = unsafe { &mut *(handle as *mut PatternMatcher) };
-        matcher.invalidate_cache();
+        for (pattern_id, e) in matcher.invalidate_cache() {
+            log::warn!("Custom pattern {} no longer compiles: {}", pattern_id, e);
+        }
     }
 }
 
@@ -409,6 +2186,13 @@ pub struct DetectionResult {
     pub notification_type: i32,
     pub message: *mut c_char,
     pub notification_id: SessionId,
+    /// Recent output lines; free with `free_string`.
+    pub context: *mut c_char,
+    pub is_pinned: bool,
+    /// All zero unless a custom pattern matched.
+    pub matched_pattern_id: SessionId,
+    /// Null unless a custom pattern matched; free with `free_string`.
+    pub matched_pattern_name: *mut c_char,
 }
 
 impl Default for DetectionResult {
@@ -418,6 +2202,10 @@ impl Default for DetectionResult {
             notification_type: -1,
             message: ptr::null_mut(),
             notification_id: [0u8; 16],
+            context: ptr::null_mut(),
+            is_pinned: false,
+            matched_pattern_id: [0u8; 16],
+            matched_pattern_name: ptr::null_mut(),
         }
     }
 }
@@ -454,6 +2242,14 @@ pub extern "C" fn notification_detector_detect(
             } else {
                 (*out_result).message = ptr::null_mut();
             }
+            (*out_result).context = lossy_c_string(&notification.context).into_raw();
+            (*out_result).is_pinned = notification.is_pinned;
+            (*out_result).matched_pattern_id =
+                uuid_to_bytes(notification.matched_pattern_id.unwrap_or_default());
+            (*out_result).matched_pattern_name = notification
+                .matched_pattern_name
+                .as_deref()
+                .map_or(ptr::null_mut(), |name| lossy_c_string(name).into_raw());
         } else {
             *out_result = DetectionResult::default();
         }
@@ -470,6 +2266,81 @@ pub extern "C" fn notification_detector_reset(handle: NotificationDetectorHandle
     }
 }
 
+/// Same as `core_set_debounce_window`, for a standalone detector.
+#[no_mangle]
+pub extern "C" fn notification_detector_set_debounce_window(
+    handle: NotificationDetectorHandle,
+    notification_type: i32,
+    window_ms: u64,
+) -> i32 {
+    if handle.is_null() {
+        return -1;
+    }
+    let detector = unsafe { &mut *(handle as *mut NotificationDetector) };
+    let window = Duration::from_millis(window_ms);
+    let mut windows = detector.debounce_windows().clone();
+    match notification_type {
+        -1 => windows.default = window,
+        value => match notification_type_from_ffi(value) {
+            Some(notification_type) => windows.set_window(notification_type, window),
+            None => return -1,
+        },
+    }
+    detector.set_debounce_windows(windows);
+    0
+}
+
+/// Tells the detector the user typed into a session, so the next prompt
+/// there notifies even if it repeats the last one. `core_send_input` does
+/// this for core sessions.
+#[no_mangle]
+pub extern "C" fn notification_detector_note_input(
+    handle: NotificationDetectorHandle,
+    session_id: *const SessionId,
+) {
+    if handle.is_null() || session_id.is_null() {
+        return;
+    }
+    let detector = unsafe { &mut *(handle as *mut NotificationDetector) };
+    detector.note_input(bytes_to_uuid(unsafe { &*session_id }));
+}
+
+/// The detector's own custom patterns, for use with the `pattern_matcher_*`
+/// functions. The detector owns it; do not destroy it.
+#[no_mangle]
+pub extern "C" fn notification_detector_pattern_matcher(
+    handle: NotificationDetectorHandle,
+) -> PatternMatcherHandle {
+    if handle.is_null() {
+        return ptr::null_mut();
+    }
+    let detector = unsafe { &mut *(handle as *mut NotificationDetector) };
+    detector.pattern_matcher_mut() as *mut PatternMatcher as PatternMatcherHandle
+}
+
+/// Caps the detector's rolling output buffer at `buffer_size` bytes.
+#[no_mangle]
+pub extern "C" fn notification_detector_set_buffer_size(
+    handle: NotificationDetectorHandle,
+    buffer_size: u32,
+) {
+    if !handle.is_null() {
+        let detector = unsafe { &mut *(handle as *mut NotificationDetector) };
+        detector.set_buffer_size(buffer_size as usize);
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn notification_detector_set_context_lines(
+    handle: NotificationDetectorHandle,
+    context_lines: u32,
+) {
+    if !handle.is_null() {
+        let detector = unsafe { &mut *(handle as *mut NotificationDetector) };
+        detector.set_context_lines(context_lines as usize);
+    }
+}
+
 #[no_mangle]
 pub extern "C" fn free_string(s: *mut c_char) {
     if !s.is_null() {
@@ -783,6 +2654,7 @@ pub extern "C" fn pty_spawn(working_dir: *const c_char, cols: u16, rows: u16) ->
     }
 }
 
+/// Terminates with the default policy, blocking for up to its grace period.
 #[no_mangle]
 pub extern "C" fn pty_destroy(handle: PtyHandlePtr) {
     if !handle.is_null() {
@@ -793,6 +2665,27 @@ pub extern "C" fn pty_destroy(handle: PtyHandlePtr) {
     }
 }
 
+#[no_mangle]
+pub extern "C" fn pty_destroy_with_policy(
+    handle: PtyHandlePtr,
+    signal: i32,
+    grace_period_ms: u32,
+) -> i32 {
+    if handle.is_null() {
+        return -1;
+    }
+
+    let Some(policy) = shutdown_policy_from_ffi(signal, grace_period_ms) else {
+        return -3;
+    };
+
+    let mut pty = unsafe { Box::from_raw(handle as *mut PtyHandle) };
+    match pty.terminate_with_policy(&policy) {
+        Ok(_) => 0,
+        Err(_) => -2,
+    }
+}
+
 #[no_mangle]
 pub extern "C" fn pty_write(handle: PtyHandlePtr, data: *const u8, len: usize) -> i32 {
     if handle.is_null() || data.is_null() {
@@ -823,6 +2716,48 @@ pub extern "C" fn pty_read(handle: PtyHandlePtr, buf: *mut u8, buf_len: usize) -
     }
 }
 
+/// Returns the number of bytes read, 0 when no output is ready yet, or -3
+/// once the PTY has closed.
+#[no_mangle]
+pub extern "C" fn pty_try_read(handle: PtyHandlePtr, buf: *mut u8, buf_len: usize) -> i32 {
+    if handle.is_null() || buf.is_null() {
+        return -1;
+    }
+
+    let pty = unsafe { &*(handle as *const PtyHandle) };
+    let slice = unsafe { std::slice::from_raw_parts_mut(buf, buf_len) };
+
+    match pty.try_read(slice) {
+        Ok(Some(0)) => -3,
+        Ok(Some(read)) => read as i32,
+        Ok(None) => 0,
+        Err(_) => -2,
+    }
+}
+
+/// Same return values as `pty_try_read`, waiting up to `timeout_ms` for output.
+#[no_mangle]
+pub extern "C" fn pty_read_timeout(
+    handle: PtyHandlePtr,
+    buf: *mut u8,
+    buf_len: usize,
+    timeout_ms: u32,
+) -> i32 {
+    if handle.is_null() || buf.is_null() {
+        return -1;
+    }
+
+    let pty = unsafe { &*(handle as *const PtyHandle) };
+    let slice = unsafe { std::slice::from_raw_parts_mut(buf, buf_len) };
+
+    match pty.read_timeout(slice, Duration::from_millis(timeout_ms as u64)) {
+        Ok(Some(0)) => -3,
+        Ok(Some(read)) => read as i32,
+        Ok(None) => 0,
+        Err(_) => -2,
+    }
+}
+
 #[no_mangle]
 pub extern "C" fn pty_resize(handle: PtyHandlePtr, cols: u16, rows: u16) -> i32 {
     if handle.is_null() {
@@ -846,3 +2781,166 @@ pub extern "C" fn pty_is_alive(handle: PtyHandlePtr) -> bool {
     let pty = unsafe { &mut *(handle as *mut PtyHandle) };
     pty.is_alive()
 }
+
+#[cfg(all(test, target_pointer_width = "64"))]
+mod tests {
+    use super::*;
+    use std::mem::{self, offset_of, size_of};
+
+    /// Field names of a struct in the generated header, in order.
+    fn header_fields(name: &str) -> Vec<&'static str> {
+        let header = include_str!("../include/macviber_core.h");
+        let start = header
+            .find(&format!("typedef struct {} {{", name))
+            .expect("struct missing from header");
+        let end = start + header[start..].find(&format!("}} {};", name)).unwrap();
+        header[start..end]
+            .lines()
+            .skip(1)
+            .map(str::trim)
+            .filter(|line| !line.starts_with("/*") && !line.starts_with('*'))
+            .filter_map(|line| line.strip_suffix(';')?.rsplit([' ', '*']).next())
+            .collect()
+    }
+
+    #[test]
+    fn detection_result_matches_the_header_layout() {
+        assert_eq!(
+            header_fields("DetectionResult"),
+            [
+                "detected",
+                "notificationType",
+                "message",
+                "notificationId",
+                "context",
+                "isPinned",
+                "matchedPatternId",
+                "matchedPatternName",
+            ]
+        );
+        // The Swift mirrors in RustCore.swift and FFITest.swift assume these.
+        assert_eq!(offset_of!(DetectionResult, notification_type), 4);
+        assert_eq!(offset_of!(DetectionResult, message), 8);
+        assert_eq!(offset_of!(DetectionResult, notification_id), 16);
+        assert_eq!(offset_of!(DetectionResult, context), 32);
+        assert_eq!(offset_of!(DetectionResult, is_pinned), 40);
+        assert_eq!(offset_of!(DetectionResult, matched_pattern_id), 41);
+        assert_eq!(offset_of!(DetectionResult, matched_pattern_name), 64);
+        assert_eq!(size_of::<DetectionResult>(), 72);
+    }
+
+    fn add_pattern(handle: PatternMatcherHandle, pattern: &str, is_regex: bool) -> SessionId {
+        let id = uuid_to_bytes(Uuid::new_v4());
+        let name = CString::new("test").unwrap();
+        let pattern = CString::new(pattern).unwrap();
+        let result = pattern_matcher_add_pattern(
+            handle,
+            &id,
+            name.as_ptr(),
+            pattern.as_ptr(),
+            is_regex,
+            true,
+            false,
+        );
+        assert_eq!(result, 0);
+        id
+    }
+
+    #[test]
+    fn match_all_truncates_to_the_buffers_and_counts_dropped_captures() {
+        let handle = pattern_matcher_create();
+        let full = add_pattern(handle, r"disk (?P<what>\w+)", true);
+        add_pattern(handle, "disk", false);
+        let exit = add_pattern(handle, r"exit (?P<code>\d)", true);
+        let text = CString::new("disk full: exit 2, exit 3").unwrap();
+
+        let total = pattern_matcher_match_all(
+            handle,
+            text.as_ptr(),
+            ptr::null_mut(),
+            0,
+            ptr::null_mut(),
+            0,
+        );
+        assert_eq!(total, 4);
+
+        let mut matches: Vec<PatternMatchFFI> = (0..4).map(|_| unsafe { mem::zeroed() }).collect();
+        let mut captures: Vec<PatternCaptureFFI> =
+            (0..2).map(|_| unsafe { mem::zeroed() }).collect();
+        let total = pattern_matcher_match_all(
+            handle,
+            text.as_ptr(),
+            matches.as_mut_ptr(),
+            3,
+            captures.as_mut_ptr(),
+            1,
+        );
+        assert_eq!(total, 4);
+
+        let written: Vec<_> = matches
+            .iter()
+            .map(|m| {
+                (
+                    m.start,
+                    m.end,
+                    m.first_capture,
+                    m.capture_count,
+                    m.dropped_captures,
+                )
+            })
+            .collect();
+        assert_eq!(
+            written,
+            [
+                (0, 9, 0, 1, 0),
+                (0, 4, 1, 0, 0),
+                (11, 17, 1, 0, 1),
+                (0, 0, 0, 0, 0)
+            ]
+        );
+        assert_eq!(matches[0].pattern_id, full);
+        assert_eq!(matches[2].pattern_id, exit);
+
+        let name = unsafe { CStr::from_ptr(captures[0].name) };
+        assert_eq!(name.to_str(), Ok("what"));
+        assert_eq!((captures[0].start, captures[0].end), (5, 9));
+        assert!(captures[1].name.is_null());
+
+        pattern_matcher_free_captures(captures.as_mut_ptr(), 1);
+        assert!(captures[0].name.is_null());
+        pattern_matcher_destroy(handle);
+    }
+
+    #[test]
+    fn pattern_validate_reports_the_error_message_and_span() {
+        let bad = CString::new("a(b").unwrap();
+        let mut error: PatternErrorFFI = unsafe { mem::zeroed() };
+        assert_eq!(pattern_validate(bad.as_ptr(), true, &mut error), -3);
+        let message = unsafe { CStr::from_ptr(error.message) };
+        assert_eq!(message.to_str(), Ok("unclosed group"));
+        assert!(error.has_span);
+        assert_eq!((error.span_start, error.span_end), (1, 2));
+        free_string(error.message);
+
+        assert_eq!(pattern_validate(bad.as_ptr(), true, ptr::null_mut()), -3);
+        assert_eq!(pattern_validate(bad.as_ptr(), false, ptr::null_mut()), 0);
+        let good = CString::new("a(b)").unwrap();
+        assert_eq!(pattern_validate(good.as_ptr(), true, ptr::null_mut()), 0);
+        assert_eq!(pattern_validate(ptr::null(), true, ptr::null_mut()), -1);
+
+        let handle = pattern_matcher_create();
+        let id = uuid_to_bytes(Uuid::new_v4());
+        let name = CString::new("bad").unwrap();
+        let result = pattern_matcher_add_pattern(
+            handle,
+            &id,
+            name.as_ptr(),
+            bad.as_ptr(),
+            true,
+            true,
+            false,
+        );
+        assert_eq!(result, -3);
+        pattern_matcher_destroy(handle);
+    }
+}
@@ -3,52 +3,977 @@ pub mod models;
 pub mod services;
 pub mod terminal;
 
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
 use std::collections::HashMap;
-use std::sync::Arc;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+use terminal::{
+    join_lines, Asciicast, AsciicastRecorder, Playback, PlaybackCommand, PlaybackEvent,
+    PlaybackHandle, PlaybackOptions, PlaybackStatus, PtyHandle, ScrollbackConfig, ShutdownPolicy,
+    SpawnSpec, TerminalEmulator, Utf8Decoder,
+};
 use uuid::Uuid;
 
 pub use models::*;
 pub use services::*;
 
+const EXIT_POLL_INTERVAL: Duration = Duration::from_millis(200);
+const RULES_POLL_INTERVAL: Duration = Duration::from_secs(1);
+const IDLE_POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// Lines of recent output attached to a notification as its `context`.
+const NOTIFICATION_CONTEXT_LINES: usize = 10;
+
+pub type OutputHandler = Arc<dyn Fn(&[u8]) + Send + Sync>;
+pub type ExitHandler = Arc<dyn Fn(Uuid, &ExitStatus) + Send + Sync>;
+pub type NotificationHandler = Arc<dyn Fn(&Notification) + Send + Sync>;
+/// Called with the session, its previous status and its new one.
+pub type StatusHandler = Arc<dyn Fn(Uuid, SessionStatus, SessionStatus) + Send + Sync>;
+
+/// Where a session's output comes from: a live PTY, or a recording being
+/// replayed into a read-only session.
+enum SessionSource {
+    Pty(Arc<Mutex<PtyHandle>>),
+    Playback(PlaybackHandle),
+}
+
+struct SessionTerminal {
+    source: SessionSource,
+    emulator: Arc<Mutex<TerminalEmulator>>,
+    detector: Arc<Mutex<NotificationDetector>>,
+    output_handler: Arc<RwLock<Option<OutputHandler>>>,
+    recorder: Arc<Mutex<Option<AsciicastRecorder>>>,
+}
+
+impl SessionTerminal {
+    fn new(source: SessionSource, cols: u16, rows: u16, detector: NotificationDetector) -> Self {
+        Self {
+            source,
+            emulator: Arc::new(Mutex::new(TerminalEmulator::new(cols, rows))),
+            detector: Arc::new(Mutex::new(detector)),
+            output_handler: Arc::new(RwLock::new(None)),
+            recorder: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    fn pty(&self) -> Option<&Arc<Mutex<PtyHandle>>> {
+        match &self.source {
+            SessionSource::Pty(pty) => Some(pty),
+            SessionSource::Playback(_) => None,
+        }
+    }
+
+    fn playback(&self) -> Option<&PlaybackHandle> {
+        match &self.source {
+            SessionSource::Playback(playback) => Some(playback),
+            SessionSource::Pty(_) => None,
+        }
+    }
+
+    fn record(&self, record: impl FnOnce(&mut AsciicastRecorder) -> Result<(), CoreError>) {
+        record_event(&self.recorder, record);
+    }
+
+    fn output_sink(&self, session_id: Uuid, core: &Core) -> OutputSink {
+        OutputSink {
+            session_id,
+            emulator: self.emulator.clone(),
+            detector: self.detector.clone(),
+            decoder: Utf8Decoder::default(),
+            output_handler: self.output_handler.clone(),
+            recorder: self.recorder.clone(),
+            sessions: core.sessions.clone(),
+            notifications: core.notifications.clone(),
+            notification_handler: core.notification_handler.clone(),
+            status_handler: core.status_handler.clone(),
+            events: core.events.clone(),
+            title: String::new(),
+            working_directory: None,
+        }
+    }
+}
+
+/// The path every chunk of session output takes, whether it was read from a
+/// PTY or replayed from a recording.
+struct OutputSink {
+    session_id: Uuid,
+    emulator: Arc<Mutex<TerminalEmulator>>,
+    detector: Arc<Mutex<NotificationDetector>>,
+    decoder: Utf8Decoder,
+    output_handler: Arc<RwLock<Option<OutputHandler>>>,
+    recorder: Arc<Mutex<Option<AsciicastRecorder>>>,
+    sessions: Arc<RwLock<HashMap<Uuid, Session>>>,
+    notifications: Arc<RwLock<NotificationStore>>,
+    notification_handler: Arc<RwLock<Option<NotificationHandler>>>,
+    status_handler: Arc<RwLock<Option<StatusHandler>>>,
+    events: Arc<EventBus>,
+    /// Title and directory last reported, to tell when the program changes them.
+    title: String,
+    working_directory: Option<String>,
+}
+
+impl OutputSink {
+    fn deliver(&mut self, chunk: &[u8]) {
+        self.restore(chunk);
+
+        let text = self.decoder.decode(chunk);
+        let detected = self.detector.lock().detect(&text, self.session_id);
+        let activity = match &detected {
+            Some(notification) => SessionActivity::Notified(notification.notification_type),
+            None => SessionActivity::Output,
+        };
+        // Status first, so a prompt can be answered as soon as it is seen.
+        apply_activity(
+            &self.sessions,
+            &self.status_handler,
+            &self.events,
+            self.session_id,
+            activity,
+        );
+        if let Some(mut notification) = detected {
+            // The rendered screen beats the detector's stripped output for
+            // programs that redraw in place.
+            let recent = self
+                .emulator
+                .lock()
+                .recent_lines(NOTIFICATION_CONTEXT_LINES);
+            notification.context = join_lines(&recent);
+            self.notify(notification);
+        }
+    }
+
+    /// Feeds output without running detection, for output that is being
+    /// redrawn rather than produced now.
+    fn restore(&mut self, chunk: &[u8]) {
+        let (title, working_directory) = {
+            let mut emulator = self.emulator.lock();
+            emulator.process(chunk);
+            let title = (emulator.title() != self.title).then(|| emulator.title().to_string());
+            let working_directory = emulator
+                .working_directory()
+                .filter(|cwd| self.working_directory.as_deref() != Some(*cwd))
+                .map(str::to_string);
+            (title, working_directory)
+        };
+        record_event(&self.recorder, |r| r.record_output(chunk));
+
+        // Published under the session lock, so nothing follows SessionClosed.
+        if let Some(session) = self.sessions.write().get_mut(&self.session_id) {
+            session.update_activity();
+            let session_id = self.session_id;
+            if self.events.wants(EventKind::Output) {
+                let data = chunk.to_vec();
+                self.events.publish(CoreEvent::Output { session_id, data });
+            }
+            if let Some(title) = title {
+                self.title = title.clone();
+                self.events
+                    .publish(CoreEvent::TitleChanged { session_id, title });
+            }
+            if let Some(cwd) = working_directory {
+                self.working_directory = Some(cwd.clone());
+                session.working_directory = cwd.clone();
+                self.events
+                    .publish(CoreEvent::CwdChanged { session_id, cwd });
+            }
+        }
+
+        if let Some(handler) = self.output_handler.read().as_ref() {
+            handler(chunk);
+        }
+    }
+
+    fn notify(&self, notification: Notification) {
+        self.notifications.write().insert(notification.clone());
+        if let Some(session) = self.sessions.write().get_mut(&self.session_id) {
+            session.has_unread_notification = true;
+            self.events.publish(CoreEvent::Notification {
+                session_id: self.session_id,
+                notification: Box::new(notification.clone()),
+            });
+        }
+
+        if let Some(handler) = self.notification_handler.read().as_ref() {
+            handler(&notification);
+        }
+    }
+
+    fn resize(&self, cols: u16, rows: u16) {
+        self.emulator.lock().resize(cols, rows);
+        record_event(&self.recorder, |r| r.record_resize(cols, rows));
+    }
+}
+
+/// Feeds an event to the session's recorder, if any. A recorder that fails to
+/// write is dropped so one bad disk does not spam the log on every chunk.
+fn record_event(
+    recorder: &Mutex<Option<AsciicastRecorder>>,
+    record: impl FnOnce(&mut AsciicastRecorder) -> Result<(), CoreError>,
+) {
+    let mut recorder = recorder.lock();
+    if let Some(active) = recorder.as_mut() {
+        if let Err(e) = record(active) {
+            log::warn!("Stopping recording after write failure: {}", e);
+            *recorder = None;
+        }
+    }
+}
+
+/// Settings fixed when a `Core` is created.
+#[derive(Debug, Clone)]
+pub struct CoreConfig {
+    /// Directory the notification history is kept in. `None`, the default,
+    /// keeps notifications in memory only; the app passes its own directory
+    /// so no other `Core` writes to the same log.
+    pub history_dir: Option<PathBuf>,
+    pub retention: RetentionPolicy,
+    /// How long a session sits quietly at a shell prompt before it is idle.
+    pub idle_timeout: Duration,
+}
+
+impl CoreConfig {
+    pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(5);
+}
+
+impl Default for CoreConfig {
+    fn default() -> Self {
+        Self {
+            history_dir: None,
+            retention: RetentionPolicy::default(),
+            idle_timeout: Self::DEFAULT_IDLE_TIMEOUT,
+        }
+    }
+}
+
 pub struct Core {
     sessions: Arc<RwLock<HashMap<Uuid, Session>>>,
-    #[allow(dead_code)]
+    terminals: Arc<RwLock<HashMap<Uuid, SessionTerminal>>>,
+    exit_handler: Arc<RwLock<Option<ExitHandler>>>,
+    notifications: Arc<RwLock<NotificationStore>>,
+    notification_handler: Arc<RwLock<Option<NotificationHandler>>>,
+    status_handler: Arc<RwLock<Option<StatusHandler>>>,
+    events: Arc<EventBus>,
+    /// How long a session sits quietly at a shell prompt before it is idle.
+    idle_timeout: Arc<RwLock<Duration>>,
+    detection_rules: Arc<RwLock<Arc<DetectionRules>>>,
+    rules_watcher: Mutex<Option<tokio::task::JoinHandle<()>>>,
+    /// Custom patterns every session's detector gets a copy of.
+    pattern_matcher: RwLock<PatternMatcher>,
+    debounce_windows: RwLock<DebounceWindows>,
     runtime: tokio::runtime::Runtime,
 }
 
 impl Core {
+    /// Creates a core that keeps notifications in memory only, pruned by the
+    /// default retention policy. Use `with_config` to keep a history.
     pub fn new() -> Result<Self, CoreError> {
+        Self::with_config(CoreConfig::default())
+    }
+
+    pub fn with_config(config: CoreConfig) -> Result<Self, CoreError> {
         let runtime = tokio::runtime::Builder::new_multi_thread()
             .worker_threads(4)
             .enable_all()
             .build()
             .map_err(|e| CoreError::RuntimeInit(e.to_string()))?;
 
-        Ok(Self {
+        let core = Self {
             sessions: Arc::new(RwLock::new(HashMap::new())),
+            terminals: Arc::new(RwLock::new(HashMap::new())),
+            exit_handler: Arc::new(RwLock::new(None)),
+            notifications: Arc::new(RwLock::new(open_notification_store(&config))),
+            notification_handler: Arc::new(RwLock::new(None)),
+            status_handler: Arc::new(RwLock::new(None)),
+            events: Arc::new(EventBus::new()),
+            idle_timeout: Arc::new(RwLock::new(config.idle_timeout)),
+            detection_rules: Arc::new(RwLock::new(Arc::new(DetectionRules::builtin()))),
+            rules_watcher: Mutex::new(None),
+            pattern_matcher: RwLock::new(PatternMatcher::new()),
+            debounce_windows: RwLock::new(DebounceWindows::default()),
             runtime,
-        })
+        };
+        core.spawn_idle_watcher();
+        Ok(core)
     }
 
     pub fn create_session(&self, working_dir: &str) -> Result<Uuid, CoreError> {
+        self.create_session_with_spec(&SpawnSpec::new(working_dir))
+    }
+
+    pub fn create_session_with_spec(&self, spec: &SpawnSpec) -> Result<Uuid, CoreError> {
         let session_id = Uuid::new_v4();
-        let session = Session::new(session_id, working_dir.to_string());
+        let session = Session::new(session_id, spec.working_dir.clone());
+
+        let pty = Arc::new(Mutex::new(PtyHandle::spawn_with_spec(spec)?));
+        let terminal = SessionTerminal::new(
+            SessionSource::Pty(pty.clone()),
+            spec.cols,
+            spec.rows,
+            self.new_detector(),
+        );
+        let sink = terminal.output_sink(session_id, self);
 
         self.sessions.write().insert(session_id, session);
+        self.terminals.write().insert(session_id, terminal);
+        self.events
+            .publish(CoreEvent::SessionCreated { session_id });
+
+        self.spawn_output_reader(&pty, sink);
+        self.spawn_exit_watcher(session_id, &pty);
 
         Ok(session_id)
     }
 
     pub fn close_session(&self, session_id: Uuid) -> Result<(), CoreError> {
+        self.close_session_with_policy(session_id, &ShutdownPolicy::default(), false)
+    }
+
+    /// Removes the session and shuts its process down in the background
+    /// according to `policy`. Locked sessions are refused unless `force` is set.
+    pub fn close_session_with_policy(
+        &self,
+        session_id: Uuid,
+        policy: &ShutdownPolicy,
+        force: bool,
+    ) -> Result<(), CoreError> {
+        {
+            let mut sessions = self.sessions.write();
+            let session = sessions
+                .get(&session_id)
+                .ok_or(CoreError::SessionNotFound(session_id))?;
+            if session.is_locked && !force {
+                return Err(CoreError::SessionLocked(session_id));
+            }
+            sessions.remove(&session_id);
+            self.events.publish(CoreEvent::SessionClosed { session_id });
+        }
+
+        if let Some(terminal) = self.terminals.write().remove(&session_id) {
+            self.spawn_shutdown(session_id, terminal, policy.clone());
+        }
+
+        Ok(())
+    }
+
+    pub fn set_output_handler(
+        &self,
+        session_id: Uuid,
+        handler: Option<OutputHandler>,
+    ) -> Result<(), CoreError> {
+        let terminals = self.terminals.read();
+        let terminal = terminals
+            .get(&session_id)
+            .ok_or(CoreError::SessionNotFound(session_id))?;
+        *terminal.output_handler.write() = handler;
+        Ok(())
+    }
+
+    pub fn set_exit_handler(&self, handler: Option<ExitHandler>) {
+        *self.exit_handler.write() = handler;
+    }
+
+    /// Called for every notification detected in any session's output.
+    pub fn set_notification_handler(&self, handler: Option<NotificationHandler>) {
+        *self.notification_handler.write() = handler;
+    }
+
+    /// Called whenever a session's status changes, whether inferred from
+    /// its activity or set through `set_session_status`.
+    pub fn set_status_handler(&self, handler: Option<StatusHandler>) {
+        *self.status_handler.write() = handler;
+    }
+
+    /// Calls `handler` with every event `filter` lets through, on the event
+    /// dispatch thread.
+    pub fn subscribe_events(&self, filter: EventFilter, handler: EventHandler) -> SubscriptionId {
+        self.events.subscribe(filter, handler)
+    }
+
+    pub fn unsubscribe_events(&self, subscription_id: SubscriptionId) -> bool {
+        self.events.unsubscribe(subscription_id)
+    }
+
+    pub fn set_idle_timeout(&self, idle_timeout: Duration) {
+        *self.idle_timeout.write() = idle_timeout;
+    }
+
+    pub fn exit_status(&self, session_id: Uuid) -> Result<Option<ExitStatus>, CoreError> {
         self.sessions
-            .write()
-            .remove(&session_id)
+            .read()
+            .get(&session_id)
+            .map(|s| s.exit_status.clone())
+            .ok_or(CoreError::SessionNotFound(session_id))
+    }
+
+    pub fn send_input(&self, session_id: Uuid, data: &[u8]) -> Result<(), CoreError> {
+        let pty = self.pty_for(session_id)?;
+        // Everything the input changes happens before it is written, so the
+        // echo, or a prompt that follows it, is never seen ahead of it by the
+        // cast, the detector or the session status.
+        if let Some(terminal) = self.terminals.read().get(&session_id) {
+            terminal.record(|r| r.record_input(data));
+            terminal.detector.lock().note_input(session_id);
+        }
+        if let Some(session) = self.sessions.write().get_mut(&session_id) {
+            session.update_activity();
+        }
+        apply_activity(
+            &self.sessions,
+            &self.status_handler,
+            &self.events,
+            session_id,
+            SessionActivity::Input,
+        );
+        pty.lock().write_all(data)?;
+        Ok(())
+    }
+
+    pub fn resize_session(&self, session_id: Uuid, cols: u16, rows: u16) -> Result<(), CoreError> {
+        let terminals = self.terminals.read();
+        let terminal = terminals
+            .get(&session_id)
+            .ok_or(CoreError::SessionNotFound(session_id))?;
+        if let Some(pty) = terminal.pty() {
+            pty.lock().resize(cols, rows)?;
+        }
+        terminal.emulator.lock().resize(cols, rows);
+        terminal.record(|r| r.record_resize(cols, rows));
+        Ok(())
+    }
+
+    /// Runs `f` against the session's rendered screen.
+    pub fn with_screen<R>(
+        &self,
+        session_id: Uuid,
+        f: impl FnOnce(&TerminalEmulator) -> R,
+    ) -> Result<R, CoreError> {
+        let emulator = self
+            .terminals
+            .read()
+            .get(&session_id)
+            .map(|t| t.emulator.clone())
+            .ok_or(CoreError::SessionNotFound(session_id))?;
+        let emulator = emulator.lock();
+        Ok(f(&emulator))
+    }
+
+    pub fn search_session(
+        &self,
+        session_id: Uuid,
+        query: &SearchQuery,
+    ) -> Result<SearchResults, CoreError> {
+        let search = ScrollbackSearch::new(query)?;
+        self.with_screen(session_id, |screen| search.search(session_id, screen))
+    }
+
+    /// Searches every session, returning only sessions with at least one hit.
+    pub fn search_all_sessions(
+        &self,
+        query: &SearchQuery,
+    ) -> Result<HashMap<Uuid, SearchResults>, CoreError> {
+        let search = ScrollbackSearch::new(query)?;
+        let emulators: Vec<_> = self
+            .terminals
+            .read()
+            .iter()
+            .map(|(id, t)| (*id, t.emulator.clone()))
+            .collect();
+
+        Ok(emulators
+            .into_iter()
+            .map(|(id, emulator)| (id, search.search(id, &emulator.lock())))
+            .filter(|(_, results)| !results.is_empty())
+            .collect())
+    }
+
+    /// Starts writing the session to an asciicast v2 file at `path`.
+    pub fn start_recording<P: AsRef<Path>>(
+        &self,
+        session_id: Uuid,
+        path: P,
+    ) -> Result<(), CoreError> {
+        let terminals = self.terminals.read();
+        let terminal = terminals
+            .get(&session_id)
             .ok_or(CoreError::SessionNotFound(session_id))?;
 
+        let mut recorder = terminal.recorder.lock();
+        if recorder.is_some() {
+            return Err(CoreError::AlreadyRecording(session_id));
+        }
+        let (cols, rows) = {
+            let emulator = terminal.emulator.lock();
+            (emulator.cols() as u16, emulator.rows() as u16)
+        };
+        *recorder = Some(AsciicastRecorder::create(path, cols, rows)?);
+        Ok(())
+    }
+
+    pub fn stop_recording(&self, session_id: Uuid) -> Result<(), CoreError> {
+        let recorder = self
+            .terminals
+            .read()
+            .get(&session_id)
+            .ok_or(CoreError::SessionNotFound(session_id))?
+            .recorder
+            .lock()
+            .take();
+
+        match recorder {
+            Some(recorder) => recorder.finish(),
+            None => Err(CoreError::NotRecording(session_id)),
+        }
+    }
+
+    pub fn is_recording(&self, session_id: Uuid) -> bool {
+        self.terminals
+            .read()
+            .get(&session_id)
+            .is_some_and(|t| t.recorder.lock().is_some())
+    }
+
+    pub fn set_scrollback_config(
+        &self,
+        session_id: Uuid,
+        config: ScrollbackConfig,
+    ) -> Result<(), CoreError> {
+        let terminals = self.terminals.read();
+        let terminal = terminals
+            .get(&session_id)
+            .ok_or(CoreError::SessionNotFound(session_id))?;
+        terminal.emulator.lock().set_scrollback_config(config);
+        Ok(())
+    }
+
+    fn spawn_shutdown(&self, session_id: Uuid, terminal: SessionTerminal, policy: ShutdownPolicy) {
+        match terminal.source {
+            SessionSource::Pty(pty) => {
+                self.runtime.spawn_blocking(move || {
+                    if let Err(e) = pty.lock().terminate_with_policy(&policy) {
+                        log::warn!("Failed to terminate session {}: {}", session_id, e);
+                    }
+                });
+            }
+            SessionSource::Playback(playback) => playback.send(PlaybackCommand::Stop),
+        }
+    }
+
+    /// Switches every session, current and future, to the rule pack at
+    /// `path` and reloads it whenever the file changes. An edit that fails
+    /// validation is logged and the previous rules stay in effect.
+    pub fn load_detection_rules<P: Into<PathBuf>>(&self, path: P) -> Result<(), CoreError> {
+        let (mut watcher, rules) = RuleFileWatcher::open(path)?;
+        apply_detection_rules(&self.detection_rules, &self.terminals, rules);
+
+        let detection_rules = self.detection_rules.clone();
+        let terminals = self.terminals.clone();
+        let task = self.runtime.spawn(async move {
+            let mut interval = tokio::time::interval(RULES_POLL_INTERVAL);
+            loop {
+                interval.tick().await;
+                match watcher.poll() {
+                    Some(Ok(rules)) => {
+                        log::info!("Reloaded detection rules from {}", watcher.path().display());
+                        apply_detection_rules(&detection_rules, &terminals, rules);
+                    }
+                    Some(Err(e)) => log::warn!(
+                        "Keeping previous detection rules; {} failed to load: {}",
+                        watcher.path().display(),
+                        e
+                    ),
+                    None => {}
+                }
+            }
+        });
+        self.replace_rules_watcher(Some(task));
+        Ok(())
+    }
+
+    /// Goes back to the built-in rules and stops watching any rule file.
+    pub fn reset_detection_rules(&self) {
+        self.replace_rules_watcher(None);
+        apply_detection_rules(
+            &self.detection_rules,
+            &self.terminals,
+            DetectionRules::builtin(),
+        );
+    }
+
+    pub fn detection_rules(&self) -> Arc<DetectionRules> {
+        self.detection_rules.read().clone()
+    }
+
+    /// Adds a pattern that raises `Custom` notifications in every session, or
+    /// rejects it if its regex does not compile.
+    pub fn add_custom_pattern(&self, pattern: CustomPattern) -> Result<(), CoreError> {
+        self.update_pattern_matchers(|matcher| matcher.add_pattern(pattern.clone()))
+    }
+
+    pub fn remove_custom_pattern(&self, pattern_id: Uuid) {
+        self.update_pattern_matchers(|matcher| matcher.remove_pattern(pattern_id));
+    }
+
+    pub fn custom_patterns(&self) -> Vec<CustomPattern> {
+        self.pattern_matcher.read().patterns().to_vec()
+    }
+
+    /// Applies `update` to the template and every session's matcher. They
+    /// hold the same patterns, so the template's result stands for all.
+    fn update_pattern_matchers<T>(&self, update: impl Fn(&mut PatternMatcher) -> T) -> T {
+        let result = update(&mut self.pattern_matcher.write());
+        for terminal in self.terminals.read().values() {
+            update(terminal.detector.lock().pattern_matcher_mut());
+        }
+        result
+    }
+
+    /// Sets how long repeats of `notification_type` are held back in each
+    /// session, or the window for every type without its own if `None`.
+    pub fn set_debounce_window(
+        &self,
+        notification_type: Option<NotificationType>,
+        window: Duration,
+    ) {
+        let mut windows = self.debounce_windows.write();
+        match notification_type {
+            Some(notification_type) => windows.set_window(notification_type, window),
+            None => windows.default = window,
+        }
+        for terminal in self.terminals.read().values() {
+            terminal
+                .detector
+                .lock()
+                .set_debounce_windows(windows.clone());
+        }
+    }
+
+    fn new_detector(&self) -> NotificationDetector {
+        let mut detector = NotificationDetector::with_rules(self.detection_rules.read().clone());
+        detector.set_pattern_matcher(self.pattern_matcher.read().clone());
+        detector.set_debounce_windows(self.debounce_windows.read().clone());
+        detector
+    }
+
+    fn replace_rules_watcher(&self, task: Option<tokio::task::JoinHandle<()>>) {
+        let previous = std::mem::replace(&mut *self.rules_watcher.lock(), task);
+        if let Some(previous) = previous {
+            previous.abort();
+        }
+    }
+
+    fn spawn_exit_watcher(&self, session_id: Uuid, pty: &Arc<Mutex<PtyHandle>>) {
+        let pty: Weak<Mutex<PtyHandle>> = Arc::downgrade(pty);
+        let sessions = self.sessions.clone();
+        let exit_handler = self.exit_handler.clone();
+        let status_handler = self.status_handler.clone();
+        let events = self.events.clone();
+
+        self.runtime.spawn(async move {
+            let mut interval = tokio::time::interval(EXIT_POLL_INTERVAL);
+
+            loop {
+                interval.tick().await;
+
+                let Some(pty) = pty.upgrade() else {
+                    break;
+                };
+                if !sessions.read().contains_key(&session_id) {
+                    break;
+                }
+                // A shutdown in progress holds the lock while it waits.
+                let Some(mut pty) = pty.try_lock() else {
+                    continue;
+                };
+                let status = match pty.try_wait() {
+                    Ok(Some(status)) => status,
+                    Ok(None) => continue,
+                    Err(e) => {
+                        log::warn!("Failed to poll session {} child: {}", session_id, e);
+                        break;
+                    }
+                };
+
+                let previous = sessions.write().get_mut(&session_id).map(|session| {
+                    let previous = session.status;
+                    session.mark_exited(status.clone());
+                    if previous != SessionStatus::Terminated {
+                        events.publish(CoreEvent::StatusChanged {
+                            session_id,
+                            previous,
+                            status: SessionStatus::Terminated,
+                        });
+                    }
+                    events.publish(CoreEvent::Exit {
+                        session_id,
+                        status: status.clone(),
+                    });
+                    previous
+                });
+                if let Some(previous) = previous {
+                    report_status(
+                        &status_handler,
+                        session_id,
+                        previous,
+                        SessionStatus::Terminated,
+                    );
+                }
+                if let Some(handler) = exit_handler.read().as_ref() {
+                    handler(session_id, &status);
+                }
+                break;
+            }
+        });
+    }
+
+    /// Marks sessions idle once they have sat quietly at a shell prompt for
+    /// the idle timeout. Full-screen programs never count as a prompt.
+    fn spawn_idle_watcher(&self) {
+        let sessions = self.sessions.clone();
+        let terminals = self.terminals.clone();
+        let status_handler = self.status_handler.clone();
+        let events = self.events.clone();
+        let idle_timeout = self.idle_timeout.clone();
+
+        self.runtime.spawn(async move {
+            let mut interval = tokio::time::interval(IDLE_POLL_INTERVAL);
+
+            loop {
+                interval.tick().await;
+
+                let idle_timeout = *idle_timeout.read();
+                let quiet: Vec<Uuid> = sessions
+                    .read()
+                    .values()
+                    .filter(|s| s.status == SessionStatus::Running)
+                    .filter(|s| {
+                        s.last_activity
+                            .elapsed()
+                            .is_ok_and(|quiet| quiet >= idle_timeout)
+                    })
+                    .map(|s| s.id)
+                    .collect();
+
+                for session_id in quiet {
+                    let at_prompt = terminals.read().get(&session_id).is_some_and(|t| {
+                        let emulator = t.emulator.lock();
+                        !emulator.is_alternate_screen()
+                            && is_shell_prompt(&emulator.text_before_cursor())
+                    });
+                    if at_prompt {
+                        apply_activity(
+                            &sessions,
+                            &status_handler,
+                            &events,
+                            session_id,
+                            SessionActivity::QuietAtPrompt,
+                        );
+                    }
+                }
+            }
+        });
+    }
+
+    fn pty_for(&self, session_id: Uuid) -> Result<Arc<Mutex<PtyHandle>>, CoreError> {
+        self.terminals
+            .read()
+            .get(&session_id)
+            .ok_or(CoreError::SessionNotFound(session_id))?
+            .pty()
+            .cloned()
+            .ok_or(CoreError::ReadOnlySession(session_id))
+    }
+
+    fn spawn_output_reader(&self, pty: &Arc<Mutex<PtyHandle>>, mut sink: OutputSink) {
+        let output = pty.lock().output_receiver();
+
+        self.runtime.spawn_blocking(move || {
+            for chunk in output.iter() {
+                sink.deliver(&chunk);
+            }
+            log::debug!("Output reader for session {} finished", sink.session_id);
+        });
+    }
+
+    /// Replays an asciicast file into a new read-only session. Its output goes
+    /// through the same handlers as a live session's.
+    pub fn open_playback<P: AsRef<Path>>(
+        &self,
+        path: P,
+        options: &PlaybackOptions,
+    ) -> Result<Uuid, CoreError> {
+        let path = path.as_ref();
+        let cast = Asciicast::load(path)?;
+        let session_id = Uuid::new_v4();
+
+        let name = cast.header.title.clone().unwrap_or_else(|| {
+            path.file_stem()
+                .map(|s| s.to_string_lossy().into_owned())
+                .unwrap_or_else(|| "Playback".to_string())
+        });
+        let mut session = Session::with_name(session_id, name, path.to_string_lossy().into_owned());
+        session.is_read_only = true;
+
+        let (playback, handle, commands) = Playback::new(cast, options);
+        let terminal = SessionTerminal::new(
+            SessionSource::Playback(handle),
+            playback.width(),
+            playback.height(),
+            self.new_detector(),
+        );
+        let mut sink = terminal.output_sink(session_id, self);
+
+        self.sessions.write().insert(session_id, session);
+        self.terminals.write().insert(session_id, terminal);
+        self.events
+            .publish(CoreEvent::SessionCreated { session_id });
+
+        self.runtime.spawn_blocking(move || {
+            playback.run(commands, |event| match event {
+                PlaybackEvent::Output(data) => sink.deliver(data),
+                PlaybackEvent::Restore(data) => sink.restore(data),
+                PlaybackEvent::Resize(cols, rows) => sink.resize(cols, rows),
+                PlaybackEvent::Finished => apply_activity(
+                    &sink.sessions,
+                    &sink.status_handler,
+                    &sink.events,
+                    sink.session_id,
+                    SessionActivity::Exited,
+                ),
+            });
+            log::debug!("Playback for session {} finished", sink.session_id);
+        });
+
+        Ok(session_id)
+    }
+
+    pub fn control_playback(
+        &self,
+        session_id: Uuid,
+        command: PlaybackCommand,
+    ) -> Result<(), CoreError> {
+        self.playback_for(session_id)?.send(command);
+        Ok(())
+    }
+
+    pub fn playback_status(&self, session_id: Uuid) -> Result<PlaybackStatus, CoreError> {
+        Ok(self.playback_for(session_id)?.status())
+    }
+
+    fn playback_for(&self, session_id: Uuid) -> Result<PlaybackHandle, CoreError> {
+        self.terminals
+            .read()
+            .get(&session_id)
+            .ok_or(CoreError::SessionNotFound(session_id))?
+            .playback()
+            .cloned()
+            .ok_or(CoreError::NotPlayback(session_id))
+    }
+
+    pub fn list_notifications(&self, filter: &NotificationFilter) -> Vec<Notification> {
+        self.notifications.read().list(filter)
+    }
+
+    pub fn get_notification(&self, notification_id: Uuid) -> Option<Notification> {
+        self.notifications.read().get(notification_id).cloned()
+    }
+
+    pub fn mark_notification_read(&self, notification_id: Uuid) -> Result<(), CoreError> {
+        let session_id = self
+            .notifications
+            .write()
+            .mark_read(notification_id)?
+            .session_id;
+        self.sync_unread(session_id);
+        Ok(())
+    }
+
+    /// Answers a prompt by typing the input its rule gives for `response`
+    /// into the session, then marks the notification read. Only the
+    /// session's latest prompt can be answered, and only while the session
+    /// is still waiting on it.
+    pub fn respond_to_notification(
+        &self,
+        notification_id: Uuid,
+        response: NotificationResponse,
+    ) -> Result<(), CoreError> {
+        let notification = self
+            .get_notification(notification_id)
+            .ok_or(CoreError::NotificationNotFound(notification_id))?;
+        let input = notification
+            .responses
+            .get(&response)
+            .ok_or(CoreError::InvalidResponse(notification_id, response))?;
+        if !self.awaits_response(&notification) {
+            return Err(CoreError::StaleNotification(notification_id));
+        }
+
+        self.send_input(notification.session_id, input.as_bytes())?;
+        self.mark_notification_read(notification_id)
+    }
+
+    fn awaits_response(&self, notification: &Notification) -> bool {
+        let waiting = self
+            .sessions
+            .read()
+            .get(&notification.session_id)
+            .is_some_and(|s| s.status == SessionStatus::WaitingForInput);
+        let filter = NotificationFilter {
+            session_id: Some(notification.session_id),
+            ..Default::default()
+        };
+        let latest_prompt = self
+            .notifications
+            .read()
+            .list(&filter)
+            .into_iter()
+            .rfind(|n| {
+                matches!(
+                    n.notification_type,
+                    NotificationType::Question | NotificationType::PermissionRequest
+                )
+            })
+            .map(|n| n.id);
+        waiting && latest_prompt == Some(notification.id)
+    }
+
+    pub fn mark_session_notifications_read(&self, session_id: Uuid) -> usize {
+        let count = self.notifications.write().mark_session_read(session_id);
+        self.sync_unread(session_id);
+        count
+    }
+
+    pub fn toggle_notification_pin(&self, notification_id: Uuid) -> Result<bool, CoreError> {
+        Ok(self
+            .notifications
+            .write()
+            .toggle_pin(notification_id)?
+            .is_pinned)
+    }
+
+    pub fn dismiss_notification(&self, notification_id: Uuid) -> Result<(), CoreError> {
+        let notification = self.notifications.write().dismiss(notification_id)?;
+        self.sync_unread(notification.session_id);
         Ok(())
     }
 
+    pub fn clear_session_notifications(&self, session_id: Uuid) -> usize {
+        let count = self.notifications.write().clear_session(session_id);
+        self.sync_unread(session_id);
+        count
+    }
+
+    /// Unread notifications across all sessions, for the dock badge.
+    pub fn unread_notification_count(&self) -> usize {
+        self.notifications.read().unread_count()
+    }
+
+    fn sync_unread(&self, session_id: Uuid) {
+        let has_unread = self.notifications.read().has_unread(session_id);
+        if let Some(session) = self.sessions.write().get_mut(&session_id) {
+            session.has_unread_notification = has_unread;
+        }
+    }
+
     pub fn session_count(&self) -> usize {
         self.sessions.read().len()
     }
@@ -61,12 +986,23 @@ impl Core {
         self.sessions.read().keys().copied().collect()
     }
 
+    /// Every session, oldest first.
+    pub fn list_sessions(&self) -> Vec<Session> {
+        let mut sessions: Vec<Session> = self.sessions.read().values().cloned().collect();
+        sessions.sort_by_key(|s| s.created_at);
+        sessions
+    }
+
     pub fn rename_session(&self, session_id: Uuid, new_name: String) -> Result<(), CoreError> {
         let mut sessions = self.sessions.write();
         let session = sessions
             .get_mut(&session_id)
             .ok_or(CoreError::SessionNotFound(session_id))?;
         session.rename(new_name);
+        self.events.publish(CoreEvent::SessionRenamed {
+            session_id,
+            name: session.display_name().to_string(),
+        });
         Ok(())
     }
 
@@ -80,6 +1016,10 @@ impl Core {
             .get_mut(&session_id)
             .ok_or(CoreError::SessionNotFound(session_id))?;
         session.set_alias(alias);
+        self.events.publish(CoreEvent::SessionRenamed {
+            session_id,
+            name: session.display_name().to_string(),
+        });
         Ok(())
     }
 
@@ -97,15 +1037,121 @@ impl Core {
         session_id: Uuid,
         status: SessionStatus,
     ) -> Result<(), CoreError> {
-        let mut sessions = self.sessions.write();
-        let session = sessions
-            .get_mut(&session_id)
-            .ok_or(CoreError::SessionNotFound(session_id))?;
-        session.set_status(status);
+        let previous = {
+            let mut sessions = self.sessions.write();
+            let session = sessions
+                .get_mut(&session_id)
+                .ok_or(CoreError::SessionNotFound(session_id))?;
+            let previous = session.status;
+            session.set_status(status);
+            if previous != status {
+                self.events.publish(CoreEvent::StatusChanged {
+                    session_id,
+                    previous,
+                    status,
+                });
+            }
+            previous
+        };
+        report_status(&self.status_handler, session_id, previous, status);
         Ok(())
     }
 }
 
+impl Drop for Core {
+    fn drop(&mut self) {
+        // Shut sessions down in parallel; dropping the runtime afterwards
+        // waits for the blocking tasks to finish.
+        let terminals: Vec<_> = self.terminals.write().drain().collect();
+        self.sessions.write().clear();
+        for (session_id, terminal) in terminals {
+            self.spawn_shutdown(session_id, terminal, ShutdownPolicy::default());
+        }
+    }
+}
+
+/// Moves a session to the status `activity` leads to, reporting any change.
+fn apply_activity(
+    sessions: &RwLock<HashMap<Uuid, Session>>,
+    status_handler: &RwLock<Option<StatusHandler>>,
+    events: &EventBus,
+    session_id: Uuid,
+    activity: SessionActivity,
+) {
+    let change = {
+        let mut sessions = sessions.write();
+        let Some(session) = sessions.get_mut(&session_id) else {
+            return;
+        };
+        let previous = session.status;
+        let status = previous.after(activity);
+        if status != previous {
+            session.set_status(status);
+            // Queued under the lock so the bus sees changes in the order
+            // they were made.
+            events.publish(CoreEvent::StatusChanged {
+                session_id,
+                previous,
+                status,
+            });
+        }
+        (previous, status)
+    };
+    report_status(status_handler, session_id, change.0, change.1);
+}
+
+fn report_status(
+    status_handler: &RwLock<Option<StatusHandler>>,
+    session_id: Uuid,
+    previous: SessionStatus,
+    status: SessionStatus,
+) {
+    if previous == status {
+        return;
+    }
+    if let Some(handler) = status_handler.read().as_ref() {
+        handler(session_id, previous, status);
+    }
+}
+
+/// Whether a line ends the way interactive shell prompts do: `$`, `#`, `%`,
+/// `>` or one of the arrows popular themes use.
+fn is_shell_prompt(line: &str) -> bool {
+    line.trim_end()
+        .ends_with(['$', '#', '%', '>', '❯', '➜', '»', 'λ'])
+}
+
+fn apply_detection_rules(
+    detection_rules: &RwLock<Arc<DetectionRules>>,
+    terminals: &RwLock<HashMap<Uuid, SessionTerminal>>,
+    rules: DetectionRules,
+) {
+    let rules = Arc::new(rules);
+    *detection_rules.write() = rules.clone();
+    for terminal in terminals.read().values() {
+        terminal.detector.lock().set_rules(rules.clone());
+    }
+}
+
+/// Loads the notification history, falling back to an in-memory store so a
+/// bad history directory never keeps the app from starting.
+fn open_notification_store(config: &CoreConfig) -> NotificationStore {
+    let Some(directory) = &config.history_dir else {
+        return NotificationStore::with_retention(config.retention);
+    };
+    match NotificationHistory::open(directory, config.retention) {
+        Ok((history, loaded)) => NotificationStore::with_history(history, loaded),
+        Err(e) => {
+            log::warn!(
+                "Notification history unavailable at {}: {}",
+                directory.display(),
+                e
+            );
+            NotificationStore::with_retention(config.retention)
+        }
+    }
+}
+
 impl Default for Core {
     fn default() -> Self {
         Self::new().expect("Failed to initialize Core")
@@ -120,9 +1166,156 @@ pub enum CoreError {
     #[error("Session not found: {0}")]
     SessionNotFound(Uuid),
 
+    #[error("Session is locked: {0}")]
+    SessionLocked(Uuid),
+
     #[error("PTY error: {0}")]
     Pty(String),
 
+    #[error("Invalid regex: {0}")]
+    InvalidRegex(String),
+
+    #[error("Invalid pattern: {0}")]
+    InvalidPattern(#[from] PatternError),
+
+    #[error("Session is already being recorded: {0}")]
+    AlreadyRecording(Uuid),
+
+    #[error("Session is not being recorded: {0}")]
+    NotRecording(Uuid),
+
+    #[error("Session is read-only: {0}")]
+    ReadOnlySession(Uuid),
+
+    #[error("Session is not a playback: {0}")]
+    NotPlayback(Uuid),
+
+    #[error("Invalid asciicast file: {0}")]
+    InvalidCast(String),
+
+    #[error("Invalid detection rules: {}", format_rule_errors(.0))]
+    InvalidRules(Vec<RuleError>),
+
+    #[error("Notification not found: {0}")]
+    NotificationNotFound(Uuid),
+
+    #[error("Notification is pinned: {0}")]
+    NotificationPinned(Uuid),
+
+    #[error("Notification no longer awaits a response: {0}")]
+    StaleNotification(Uuid),
+
+    #[error("Notification does not accept {1:?}: {0}")]
+    InvalidResponse(Uuid, NotificationResponse),
+
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    fn wait_for<T>(what: &str, mut check: impl FnMut() -> Option<T>) -> T {
+        let deadline = std::time::Instant::now() + Duration::from_secs(10);
+        loop {
+            if let Some(value) = check() {
+                return value;
+            }
+            assert!(
+                std::time::Instant::now() < deadline,
+                "timed out waiting for {}",
+                what
+            );
+            std::thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    #[cfg(unix)]
+    fn prompt(core: &Core, session_id: Uuid, message: &str) -> Notification {
+        wait_for(message, || {
+            let filter = NotificationFilter {
+                session_id: Some(session_id),
+                ..Default::default()
+            };
+            core.list_notifications(&filter)
+                .into_iter()
+                .find(|n| n.message == message)
+        })
+    }
+
+    #[cfg(unix)]
+    fn wait_for_screen(core: &Core, session_id: Uuid, text: &str) {
+        wait_for(text, || {
+            core.with_screen(session_id, |screen| screen.screen_text())
+                .ok()
+                .filter(|screen| screen.contains(text))
+        });
+    }
+
+    #[test]
+    fn shell_prompts_are_recognised_by_their_last_char() {
+        for line in [
+            "user@host:~$ ",
+            "root@host:/# ",
+            "host% ",
+            "PS C:\\> ",
+            "~/src ❯ ",
+            "macviber ➜ ",
+            "» ",
+            "λ",
+        ] {
+            assert!(is_shell_prompt(line), "{:?}", line);
+        }
+        for line in ["", "Compiling macviber-core", "Continue? (y/n) ", "50%!"] {
+            assert!(!is_shell_prompt(line), "{:?}", line);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn respond_types_the_rule_reply_into_the_prompting_session() {
+        let core = Core::new().unwrap();
+        let script = "printf 'First? (y/n) '; read a; echo \"got:$a\"; \
+                      printf 'Second? (y/n) '; read b; echo \"got:$b\"; sleep 10";
+        let mut spec =
+            SpawnSpec::new("/").with_program("/bin/sh", vec!["-c".to_string(), script.to_string()]);
+        spec.login_shell = false;
+        let session_id = core.create_session_with_spec(&spec).unwrap();
+
+        let first = prompt(&core, session_id, "First? (y/n)");
+        assert!(matches!(
+            core.respond_to_notification(first.id, NotificationResponse::Approve),
+            Err(CoreError::InvalidResponse(id, NotificationResponse::Approve)) if id == first.id
+        ));
+        assert!(!core.get_notification(first.id).unwrap().is_read);
+
+        core.respond_to_notification(first.id, NotificationResponse::Yes)
+            .unwrap();
+        assert!(core.get_notification(first.id).unwrap().is_read);
+        wait_for_screen(&core, session_id, "got:y");
+
+        // A newer prompt supersedes the first one.
+        let second = prompt(&core, session_id, "Second? (y/n)");
+        assert!(matches!(
+            core.respond_to_notification(first.id, NotificationResponse::Yes),
+            Err(CoreError::StaleNotification(id)) if id == first.id
+        ));
+
+        core.respond_to_notification(second.id, NotificationResponse::No)
+            .unwrap();
+        wait_for_screen(&core, session_id, "got:n");
+        // Answered: the session is no longer waiting on it.
+        assert!(matches!(
+            core.respond_to_notification(second.id, NotificationResponse::No),
+            Err(CoreError::StaleNotification(_))
+        ));
+        assert!(matches!(
+            core.respond_to_notification(Uuid::new_v4(), NotificationResponse::Yes),
+            Err(CoreError::NotificationNotFound(_))
+        ));
+
+        core.close_session(session_id).unwrap();
+    }
+}
@@ -1,8 +1,9 @@
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::time::SystemTime;
 use uuid::Uuid;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[repr(C)]
 pub enum NotificationType {
     Question = 0,
@@ -12,6 +13,18 @@ pub enum NotificationType {
     Custom = 4,
 }
 
+/// A reply to a prompt, typed into the session on the user's behalf.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[repr(C)]
+pub enum NotificationResponse {
+    Yes = 0,
+    No = 1,
+    Approve = 2,
+    Deny = 3,
+    Enter = 4,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Notification {
     pub id: Uuid,
@@ -23,6 +36,21 @@ pub struct Notification {
     pub is_pinned: bool,
     pub created_at: SystemTime,
     pub pinned_at: Option<SystemTime>,
+    /// The custom pattern behind a `Custom` notification.
+    #[serde(default)]
+    pub matched_pattern_id: Option<Uuid>,
+    #[serde(default)]
+    pub matched_pattern_name: Option<String>,
+    /// Named capture groups of the custom pattern, for quoting a filename or
+    /// command from the output.
+    #[serde(default)]
+    pub captures: BTreeMap<String, String>,
+    /// The detection rule that raised the notification.
+    #[serde(default)]
+    pub matched_rule_id: Option<String>,
+    /// Replies the prompt accepts, with the exact input each one types.
+    #[serde(default)]
+    pub responses: BTreeMap<NotificationResponse, String>,
 }
 
 impl Notification {
@@ -42,6 +70,11 @@ impl Notification {
             is_pinned: false,
             created_at: SystemTime::now(),
             pinned_at: None,
+            matched_pattern_id: None,
+            matched_pattern_name: None,
+            captures: BTreeMap::new(),
+            matched_rule_id: None,
+            responses: BTreeMap::new(),
         }
     }
 
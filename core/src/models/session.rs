@@ -1,3 +1,4 @@
+use super::NotificationType;
 use serde::{Deserialize, Serialize};
 use std::time::SystemTime;
 use uuid::Uuid;
@@ -11,6 +12,87 @@ pub enum SessionStatus {
     Terminated = 3,
 }
 
+/// Something that happened in a session that can change its status.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionActivity {
+    Output,
+    Input,
+    /// Output raised a notification of this type.
+    Notified(NotificationType),
+    /// No output for a while, with a shell prompt under the cursor.
+    QuietAtPrompt,
+    Exited,
+}
+
+impl SessionStatus {
+    /// The status after `activity`. Programs keep redrawing while they wait,
+    /// so plain output does not end `WaitingForInput`; input, exit, or a
+    /// later completion or error does. Nothing leaves `Terminated`.
+    pub fn after(self, activity: SessionActivity) -> Self {
+        use SessionActivity::*;
+        use SessionStatus::*;
+
+        match (self, activity) {
+            (Terminated, _) | (_, Exited) => Terminated,
+            (_, Input) => Running,
+            (_, Notified(NotificationType::Question | NotificationType::PermissionRequest)) => {
+                WaitingForInput
+            }
+            (_, Notified(NotificationType::Completion | NotificationType::Error)) => Running,
+            (WaitingForInput, _) => WaitingForInput,
+            (Running, QuietAtPrompt) => Idle,
+            (Idle, QuietAtPrompt) => Idle,
+            (_, Output | Notified(NotificationType::Custom)) => Running,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExitStatus {
+    /// Exit code, `None` when the process was killed by a signal.
+    pub code: Option<i32>,
+    /// Terminating signal number on Unix.
+    pub signal: Option<i32>,
+}
+
+impl ExitStatus {
+    pub fn with_code(code: i32) -> Self {
+        Self {
+            code: Some(code),
+            signal: None,
+        }
+    }
+
+    pub fn with_signal(signal: i32) -> Self {
+        Self {
+            code: None,
+            signal: Some(signal),
+        }
+    }
+
+    /// A process whose status was lost, such as one reaped by someone else.
+    pub fn unknown() -> Self {
+        Self {
+            code: None,
+            signal: None,
+        }
+    }
+
+    pub fn success(&self) -> bool {
+        self.code == Some(0)
+    }
+}
+
+impl std::fmt::Display for ExitStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.code, self.signal) {
+            (_, Some(signal)) => write!(f, "killed by signal {}", signal),
+            (Some(code), None) => write!(f, "exited with {}", code),
+            (None, None) => write!(f, "exited"),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Session {
     pub id: Uuid,
@@ -22,6 +104,10 @@ pub struct Session {
     pub last_activity: SystemTime,
     pub has_unread_notification: bool,
     pub is_locked: bool,
+    /// Replayed from a recording; it accepts no input.
+    #[serde(default)]
+    pub is_read_only: bool,
+    pub exit_status: Option<ExitStatus>,
 }
 
 impl Session {
@@ -29,7 +115,7 @@ impl Session {
         let now = SystemTime::now();
         let name = working_directory
             .split('/')
-            .last()
+            .next_back()
             .unwrap_or("Terminal")
             .to_string();
 
@@ -43,6 +129,8 @@ impl Session {
             last_activity: now,
             has_unread_notification: false,
             is_locked: false,
+            is_read_only: false,
+            exit_status: None,
         }
     }
 
@@ -58,6 +146,8 @@ impl Session {
             last_activity: now,
             has_unread_notification: false,
             is_locked: false,
+            is_read_only: false,
+            exit_status: None,
         }
     }
 
@@ -74,6 +164,11 @@ impl Session {
         self.update_activity();
     }
 
+    pub fn mark_exited(&mut self, exit_status: ExitStatus) {
+        self.exit_status = Some(exit_status);
+        self.set_status(SessionStatus::Terminated);
+    }
+
     pub fn set_alias(&mut self, alias: Option<String>) {
         self.alias = alias.filter(|s| !s.is_empty());
     }
@@ -87,3 +182,46 @@ impl Session {
         self.update_activity();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use NotificationType::*;
+    use SessionActivity::*;
+    use SessionStatus::*;
+
+    #[test]
+    fn status_follows_activity() {
+        let cases = [
+            (Idle, Output, Running),
+            (Idle, Input, Running),
+            (Idle, QuietAtPrompt, Idle),
+            (Running, QuietAtPrompt, Idle),
+            (Running, Notified(Question), WaitingForInput),
+            (Running, Notified(PermissionRequest), WaitingForInput),
+            (Running, Notified(Custom), Running),
+            (Idle, Notified(Custom), Running),
+            (WaitingForInput, Output, WaitingForInput),
+            (WaitingForInput, QuietAtPrompt, WaitingForInput),
+            (WaitingForInput, Notified(Custom), WaitingForInput),
+            (WaitingForInput, Notified(Completion), Running),
+            (WaitingForInput, Notified(Error), Running),
+            (WaitingForInput, Input, Running),
+            (Running, Exited, Terminated),
+            (WaitingForInput, Exited, Terminated),
+            (Terminated, Output, Terminated),
+            (Terminated, Input, Terminated),
+            (Terminated, Notified(Question), Terminated),
+            (Terminated, QuietAtPrompt, Terminated),
+        ];
+        for (status, activity, expected) in cases {
+            assert_eq!(
+                status.after(activity),
+                expected,
+                "{:?} after {:?}",
+                status,
+                activity
+            );
+        }
+    }
+}
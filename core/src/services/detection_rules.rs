@@ -0,0 +1,643 @@
+use super::multi_matcher::{MatchPattern, MultiMatcher};
+use crate::models::{NotificationResponse, NotificationType};
+use crate::CoreError;
+use regex::{Regex, RegexBuilder};
+use serde::Deserialize;
+use serde_json::value::RawValue;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// The Claude Code rule pack the detector uses unless given another.
+const BUILTIN_RULES: &str = include_str!(concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/rules/claude-code.toml"
+));
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuleFormat {
+    Toml,
+    Json,
+}
+
+impl RuleFormat {
+    /// Picks the format from a file extension; anything but `.json` is TOML.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("json") => Self::Json,
+            _ => Self::Toml,
+        }
+    }
+}
+
+/// A problem in a rule file, located by 1-based line where possible.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleError {
+    pub line: Option<usize>,
+    pub rule_id: Option<String>,
+    pub message: String,
+}
+
+impl RuleError {
+    fn new(line: Option<usize>, rule_id: Option<&str>, message: impl Into<String>) -> Self {
+        Self {
+            line,
+            rule_id: rule_id.map(str::to_string),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for RuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(line) = self.line {
+            write!(f, "line {}: ", line)?;
+        }
+        if let Some(rule_id) = &self.rule_id {
+            write!(f, "rule '{}': ", rule_id)?;
+        }
+        f.write_str(&self.message)
+    }
+}
+
+pub(crate) fn format_rule_errors(errors: &[RuleError]) -> String {
+    errors
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleDef {
+    id: String,
+    #[serde(default)]
+    regex: Option<String>,
+    #[serde(default)]
+    keyword: Option<String>,
+    #[serde(rename = "type")]
+    notification_type: NotificationType,
+    #[serde(default)]
+    priority: i32,
+    #[serde(default)]
+    case_sensitive: bool,
+    #[serde(default)]
+    requires_also: Vec<String>,
+    #[serde(default)]
+    unless: Vec<String>,
+    #[serde(default)]
+    message: Option<String>,
+    /// Keyed by reply name; toml only reads map keys as strings.
+    #[serde(default)]
+    responses: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TomlRuleFile {
+    #[serde(default)]
+    include_builtin: bool,
+    #[serde(default)]
+    rules: Vec<RuleDef>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct JsonRuleFile<'a> {
+    #[serde(default)]
+    include_builtin: bool,
+    #[serde(default, borrow)]
+    rules: Vec<&'a RawValue>,
+}
+
+/// Rule definitions with the line each starts on, before validation.
+struct ParsedRules {
+    include_builtin: bool,
+    definitions: Vec<(Option<usize>, RuleDef)>,
+}
+
+/// One compiled detection rule.
+#[derive(Debug, Clone)]
+pub struct DetectionRule {
+    pub id: String,
+    pub notification_type: NotificationType,
+    pub priority: i32,
+    regex: Regex,
+    keyword: Option<String>,
+    case_sensitive: bool,
+    requires_also: Vec<Regex>,
+    unless: Vec<Regex>,
+    message: Option<String>,
+    responses: BTreeMap<NotificationResponse, String>,
+}
+
+impl DetectionRule {
+    /// Replies the rule's prompt accepts, with the input each one types.
+    pub fn responses(&self) -> &BTreeMap<NotificationResponse, String> {
+        &self.responses
+    }
+
+    fn match_pattern(&self) -> MatchPattern<'_> {
+        match &self.keyword {
+            Some(keyword) => MatchPattern::Keyword {
+                keyword,
+                case_insensitive: !self.case_sensitive,
+            },
+            None => MatchPattern::Regex {
+                pattern: self.regex.as_str(),
+                case_insensitive: !self.case_sensitive,
+            },
+        }
+    }
+
+    /// Matches `text`, returning the expanded message template if the rule
+    /// has one.
+    fn apply(&self, text: &str) -> Option<Option<String>> {
+        let captures = self.regex.captures(text)?;
+        if !self.requires_also.iter().all(|r| r.is_match(text))
+            || self.unless.iter().any(|r| r.is_match(text))
+        {
+            return None;
+        }
+
+        Some(self.message.as_ref().map(|template| {
+            let mut message = String::new();
+            captures.expand(template, &mut message);
+            message
+        }))
+    }
+}
+
+/// A matched rule and its expanded message template, if it has one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleMatch<'a> {
+    pub rule_id: &'a str,
+    pub notification_type: NotificationType,
+    pub message: Option<String>,
+    pub responses: &'a BTreeMap<NotificationResponse, String>,
+}
+
+/// An ordered set of detection rules loaded from a rule pack.
+#[derive(Debug, Clone)]
+pub struct DetectionRules {
+    rules: Vec<DetectionRule>,
+    /// Finds the rules whose main pattern occurs in a text in one pass.
+    index: MultiMatcher,
+}
+
+impl DetectionRules {
+    pub fn builtin() -> Self {
+        Self::parse(BUILTIN_RULES, RuleFormat::Toml).expect("built-in rules are valid")
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, CoreError> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path)?;
+        Self::parse(&source, RuleFormat::from_path(path))
+    }
+
+    /// Parses and validates a rule pack, reporting every problem found.
+    pub fn parse(source: &str, format: RuleFormat) -> Result<Self, CoreError> {
+        let ParsedRules {
+            include_builtin,
+            definitions,
+        } = match format {
+            RuleFormat::Toml => parse_toml(source),
+            RuleFormat::Json => parse_json(source),
+        }
+        .map_err(|e| CoreError::InvalidRules(vec![e]))?;
+
+        let mut errors = Vec::new();
+        let mut rules = if include_builtin {
+            Self::builtin().rules
+        } else {
+            Vec::new()
+        };
+
+        let mut ids: HashSet<String> = rules.iter().map(|r| r.id.clone()).collect();
+        for (line, definition) in definitions {
+            if !ids.insert(definition.id.clone()) {
+                errors.push(RuleError::new(
+                    line,
+                    Some(&definition.id),
+                    "duplicate rule id",
+                ));
+                continue;
+            }
+            match compile_rule(definition, line) {
+                Ok(rule) => rules.push(rule),
+                Err(mut rule_errors) => errors.append(&mut rule_errors),
+            }
+        }
+
+        if !errors.is_empty() {
+            return Err(CoreError::InvalidRules(errors));
+        }
+        // Stable, so equal priorities keep their file order.
+        rules.sort_by_key(|r| std::cmp::Reverse(r.priority));
+        let patterns: Vec<MatchPattern> = rules.iter().map(DetectionRule::match_pattern).collect();
+        let index = MultiMatcher::new(&patterns);
+        Ok(Self { rules, index })
+    }
+
+    pub fn rules(&self) -> &[DetectionRule] {
+        &self.rules
+    }
+
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// The first rule, by priority, that matches `text`. Only rules whose
+    /// main pattern the index found are checked in full.
+    pub fn find(&self, text: &str) -> Option<RuleMatch<'_>> {
+        let matched = self.index.matches(text);
+        let candidates = self.rules.iter().zip(matched).filter(|(_, m)| *m);
+        candidates.map(|(rule, _)| rule).find_map(|rule| {
+            rule.apply(text).map(|message| RuleMatch {
+                rule_id: &rule.id,
+                notification_type: rule.notification_type,
+                message,
+                responses: &rule.responses,
+            })
+        })
+    }
+}
+
+impl Default for DetectionRules {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+fn line_at(source: &str, offset: usize) -> usize {
+    source[..offset.min(source.len())].matches('\n').count() + 1
+}
+
+/// Drops the parser's own "at line N column M", which `RuleError` replaces.
+fn without_position(message: String) -> String {
+    match message.rsplit_once(" at line ") {
+        Some((message, _)) => message.to_string(),
+        None => message,
+    }
+}
+
+fn parse_toml(source: &str) -> Result<ParsedRules, RuleError> {
+    let file: TomlRuleFile = toml::from_str(source).map_err(|e| {
+        let line = e.line_col().map(|(line, _)| line + 1);
+        RuleError::new(line, None, without_position(e.to_string()))
+    })?;
+    // The parser does not locate array-of-tables entries, so find the
+    // `[[rules]]` headers ourselves. Rules written inline go unlocated.
+    let headers: Vec<usize> = source
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| line.trim_start().starts_with("[[rules]]"))
+        .map(|(index, _)| index + 1)
+        .collect();
+    let located = headers.len() == file.rules.len();
+    let definitions = file
+        .rules
+        .into_iter()
+        .enumerate()
+        .map(|(index, rule)| (located.then(|| headers[index]), rule))
+        .collect();
+    Ok(ParsedRules {
+        include_builtin: file.include_builtin,
+        definitions,
+    })
+}
+
+fn parse_json(source: &str) -> Result<ParsedRules, RuleError> {
+    let json_error = |e: serde_json::Error, first_line: usize| {
+        let message = without_position(e.to_string());
+        RuleError::new(Some(first_line + e.line() - 1), None, message)
+    };
+
+    let file: JsonRuleFile = serde_json::from_str(source).map_err(|e| json_error(e, 1))?;
+    let mut definitions = Vec::with_capacity(file.rules.len());
+    for raw in file.rules {
+        // Each raw rule borrows from `source`, which locates it.
+        let offset = raw.get().as_ptr() as usize - source.as_ptr() as usize;
+        let line = line_at(source, offset);
+        let definition = serde_json::from_str(raw.get()).map_err(|e| json_error(e, line))?;
+        definitions.push((Some(line), definition));
+    }
+    Ok(ParsedRules {
+        include_builtin: file.include_builtin,
+        definitions,
+    })
+}
+
+fn compile_rule(definition: RuleDef, line: Option<usize>) -> Result<DetectionRule, Vec<RuleError>> {
+    let id = definition.id.as_str();
+    let mut errors = Vec::new();
+    let mut error = |message: String| errors.push(RuleError::new(line, Some(id), message));
+
+    if id.trim().is_empty() {
+        error("id must not be empty".to_string());
+    }
+
+    let pattern = match (&definition.regex, &definition.keyword) {
+        (Some(regex), None) => Some(regex.clone()),
+        (None, Some(keyword)) => Some(regex::escape(keyword)),
+        _ => {
+            error("exactly one of `regex` or `keyword` is required".to_string());
+            None
+        }
+    };
+
+    let compile = |pattern: &str| {
+        RegexBuilder::new(pattern)
+            .case_insensitive(!definition.case_sensitive)
+            .build()
+    };
+    let regex = pattern.and_then(|p| match compile(&p) {
+        Ok(regex) => Some(regex),
+        Err(e) => {
+            error(format!("invalid regex: {}", e));
+            None
+        }
+    });
+    let mut co_patterns = |field: &str, patterns: &[String]| -> Vec<Regex> {
+        patterns
+            .iter()
+            .filter_map(|p| match compile(p) {
+                Ok(regex) => Some(regex),
+                Err(e) => {
+                    error(format!("invalid regex in `{}`: {}", field, e));
+                    None
+                }
+            })
+            .collect()
+    };
+    let requires_also = co_patterns("requires_also", &definition.requires_also);
+    let unless = co_patterns("unless", &definition.unless);
+    let mut responses = BTreeMap::new();
+    for (name, input) in &definition.responses {
+        match response_named(name) {
+            None => error(format!("unknown response `{}`", name)),
+            Some(_) if input.is_empty() => {
+                error(format!("input for response `{}` must not be empty", name))
+            }
+            Some(response) => {
+                responses.insert(response, input.clone());
+            }
+        }
+    }
+
+    match regex {
+        Some(regex) if errors.is_empty() => Ok(DetectionRule {
+            id: definition.id,
+            notification_type: definition.notification_type,
+            priority: definition.priority,
+            regex,
+            keyword: definition.keyword,
+            case_sensitive: definition.case_sensitive,
+            requires_also,
+            unless,
+            message: definition.message,
+            responses,
+        }),
+        _ => Err(errors),
+    }
+}
+
+fn response_named(name: &str) -> Option<NotificationResponse> {
+    match name {
+        "yes" => Some(NotificationResponse::Yes),
+        "no" => Some(NotificationResponse::No),
+        "approve" => Some(NotificationResponse::Approve),
+        "deny" => Some(NotificationResponse::Deny),
+        "enter" => Some(NotificationResponse::Enter),
+        _ => None,
+    }
+}
+
+/// Reloads a rule file when its modification time changes.
+#[derive(Debug)]
+pub struct RuleFileWatcher {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+impl RuleFileWatcher {
+    /// Loads `path` and starts watching it.
+    pub fn open<P: Into<PathBuf>>(path: P) -> Result<(Self, DetectionRules), CoreError> {
+        let path = path.into();
+        let modified = modified_time(&path);
+        let rules = DetectionRules::load(&path)?;
+        Ok((Self { path, modified }, rules))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The reloaded rules if the file changed since the last poll.
+    pub fn poll(&mut self) -> Option<Result<DetectionRules, CoreError>> {
+        let modified = modified_time(&self.path);
+        if modified == self.modified {
+            return None;
+        }
+        self.modified = modified;
+        Some(DetectionRules::load(&self.path))
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn toml(source: &str) -> Result<DetectionRules, CoreError> {
+        DetectionRules::parse(source, RuleFormat::Toml)
+    }
+
+    fn rule_errors(result: Result<DetectionRules, CoreError>) -> Vec<(Option<usize>, String)> {
+        match result {
+            Err(CoreError::InvalidRules(errors)) => errors
+                .into_iter()
+                .map(|e| (e.line, e.to_string()))
+                .collect(),
+            other => panic!("expected rule errors, got {:?}", other.map(|r| r.len())),
+        }
+    }
+
+    #[test]
+    fn toml_errors_name_the_rule_line() {
+        let source = r#"
+[[rules]]
+id = "ok"
+keyword = "fine"
+type = "Question"
+
+[[rules]]
+id = "bad"
+regex = "("
+type = "Question"
+
+[[rules]]
+id = "ok"
+keyword = "again"
+type = "Error"
+"#;
+        let errors = rule_errors(toml(source));
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].0, Some(7));
+        assert!(errors[0].1.starts_with("line 7: rule 'bad': invalid regex"));
+        assert_eq!(
+            errors[1],
+            (
+                Some(12),
+                "line 12: rule 'ok': duplicate rule id".to_string()
+            )
+        );
+
+        let errors = rule_errors(toml("[[rules]]\nid = \"x\"\nkeyword = \n"));
+        assert_eq!(errors[0].0, Some(3));
+    }
+
+    #[test]
+    fn json_errors_name_the_rule_line() {
+        let source = r#"{
+  "rules": [
+    { "id": "ok", "keyword": "fine", "type": "Question" },
+    {
+      "id": "typo", "keywrd": "x", "type": "Question"
+    }
+  ]
+}"#;
+        let errors = rule_errors(DetectionRules::parse(source, RuleFormat::Json));
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0, Some(5));
+        assert!(errors[0].1.contains("keywrd"), "{}", errors[0].1);
+
+        let errors = rule_errors(DetectionRules::parse(
+            "{\n  \"rules\": [\n    {\"id\": }\n  ]\n}",
+            RuleFormat::Json,
+        ));
+        assert_eq!(errors[0].0, Some(3));
+    }
+
+    #[test]
+    fn include_builtin_keeps_builtin_rules_and_ids() {
+        let builtin = DetectionRules::builtin();
+        let rules = toml(
+            "include_builtin = true\n[[rules]]\nid = \"zebra\"\nkeyword = \"zebra\"\ntype = \"Custom\"\n",
+        )
+        .unwrap();
+        assert_eq!(rules.len(), builtin.len() + 1);
+        assert_eq!(rules.find("a zebra").unwrap().rule_id, "zebra");
+        assert_eq!(rules.find("Continue? (y/n)").unwrap().rule_id, "yes-no");
+
+        let id = &builtin.rules()[0].id;
+        let errors = rule_errors(toml(&format!(
+            "include_builtin = true\n[[rules]]\nid = \"{}\"\nkeyword = \"x\"\ntype = \"Custom\"\n",
+            id
+        )));
+        assert_eq!(errors[0].0, Some(2));
+        assert!(errors[0].1.ends_with("duplicate rule id"));
+    }
+
+    #[test]
+    fn higher_priority_wins_and_ties_keep_file_order() {
+        let rules = toml(
+            r#"
+[[rules]]
+id = "first"
+keyword = "build"
+type = "Completion"
+
+[[rules]]
+id = "second"
+keyword = "build"
+type = "Error"
+
+[[rules]]
+id = "urgent"
+keyword = "failed"
+type = "Error"
+priority = 10
+"#,
+        )
+        .unwrap();
+        assert_eq!(rules.rules()[0].id, "urgent");
+        assert_eq!(rules.find("build failed").unwrap().rule_id, "urgent");
+        assert_eq!(rules.find("build done").unwrap().rule_id, "first");
+    }
+
+    #[test]
+    fn responses_map_reply_names_to_input() {
+        let rules = toml(
+            "[[rules]]\nid = \"confirm\"\nkeyword = \"[Y/n]\"\ntype = \"Question\"\nresponses = { yes = \"y\\r\", enter = \"\\r\" }\n",
+        )
+        .unwrap();
+        let matched = rules.find("Install? [Y/n]").unwrap();
+        let expected = BTreeMap::from([
+            (NotificationResponse::Yes, "y\r".to_string()),
+            (NotificationResponse::Enter, "\r".to_string()),
+        ]);
+        assert_eq!(matched.responses, &expected);
+
+        let errors = rule_errors(toml(
+            "[[rules]]\nid = \"r\"\nkeyword = \"x\"\ntype = \"Question\"\nresponses = { maybe = \"m\", no = \"\" }\n",
+        ));
+        let messages: Vec<&str> = errors.iter().map(|(_, m)| m.as_str()).collect();
+        assert_eq!(
+            messages,
+            [
+                "line 1: rule 'r': unknown response `maybe`",
+                "line 1: rule 'r': input for response `no` must not be empty",
+            ]
+        );
+    }
+
+    #[test]
+    fn watcher_reloads_when_the_file_changes() {
+        let path =
+            std::env::temp_dir().join(format!("macviber-rules-{}.toml", uuid::Uuid::new_v4()));
+        let write = |source: &str, age: u64| {
+            std::fs::write(&path, source).unwrap();
+            let modified = SystemTime::now() - std::time::Duration::from_secs(age);
+            std::fs::File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(modified)
+                .unwrap();
+        };
+
+        write(
+            "[[rules]]\nid = \"a\"\nkeyword = \"a\"\ntype = \"Custom\"\n",
+            60,
+        );
+        let (mut watcher, rules) = RuleFileWatcher::open(&path).unwrap();
+        assert_eq!(rules.rules()[0].id, "a");
+        assert!(watcher.poll().is_none());
+
+        write(
+            "[[rules]]\nid = \"b\"\nkeyword = \"b\"\ntype = \"Custom\"\n",
+            30,
+        );
+        let rules = watcher.poll().unwrap().unwrap();
+        assert_eq!(rules.rules()[0].id, "b");
+        assert!(watcher.poll().is_none());
+
+        write("[[rules]]\nid = \"c\"\n", 0);
+        assert!(matches!(
+            watcher.poll(),
+            Some(Err(CoreError::InvalidRules(_)))
+        ));
+        let _ = std::fs::remove_file(&path);
+    }
+}
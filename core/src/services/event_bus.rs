@@ -0,0 +1,317 @@
+use crate::models::{ExitStatus, Notification, SessionStatus};
+use parking_lot::{Mutex, RwLock};
+use serde::Serialize;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::Arc;
+use std::thread::{self, ThreadId};
+use uuid::Uuid;
+
+pub type EventHandler = Arc<dyn Fn(&CoreEvent) + Send + Sync>;
+pub type SubscriptionId = u64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[repr(C)]
+pub enum EventKind {
+    SessionCreated = 0,
+    SessionClosed = 1,
+    SessionRenamed = 2,
+    StatusChanged = 3,
+    Output = 4,
+    Notification = 5,
+    TitleChanged = 6,
+    CwdChanged = 7,
+    Exit = 8,
+}
+
+impl EventKind {
+    /// The kind's bit in an `EventFilter` mask.
+    pub fn bit(self) -> u32 {
+        1 << self as u32
+    }
+}
+
+/// Something that happened in a `Core` session.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum CoreEvent {
+    SessionCreated {
+        session_id: Uuid,
+    },
+    SessionClosed {
+        session_id: Uuid,
+    },
+    /// Carries the name the session now displays, alias included.
+    SessionRenamed {
+        session_id: Uuid,
+        name: String,
+    },
+    StatusChanged {
+        session_id: Uuid,
+        previous: SessionStatus,
+        status: SessionStatus,
+    },
+    /// Raw output, which is left out of the JSON form.
+    Output {
+        session_id: Uuid,
+        #[serde(skip)]
+        data: Vec<u8>,
+    },
+    Notification {
+        session_id: Uuid,
+        notification: Box<Notification>,
+    },
+    TitleChanged {
+        session_id: Uuid,
+        title: String,
+    },
+    CwdChanged {
+        session_id: Uuid,
+        cwd: String,
+    },
+    Exit {
+        session_id: Uuid,
+        status: ExitStatus,
+    },
+}
+
+impl CoreEvent {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            CoreEvent::SessionCreated { .. } => EventKind::SessionCreated,
+            CoreEvent::SessionClosed { .. } => EventKind::SessionClosed,
+            CoreEvent::SessionRenamed { .. } => EventKind::SessionRenamed,
+            CoreEvent::StatusChanged { .. } => EventKind::StatusChanged,
+            CoreEvent::Output { .. } => EventKind::Output,
+            CoreEvent::Notification { .. } => EventKind::Notification,
+            CoreEvent::TitleChanged { .. } => EventKind::TitleChanged,
+            CoreEvent::CwdChanged { .. } => EventKind::CwdChanged,
+            CoreEvent::Exit { .. } => EventKind::Exit,
+        }
+    }
+
+    pub fn session_id(&self) -> Uuid {
+        match self {
+            CoreEvent::SessionCreated { session_id }
+            | CoreEvent::SessionClosed { session_id }
+            | CoreEvent::SessionRenamed { session_id, .. }
+            | CoreEvent::StatusChanged { session_id, .. }
+            | CoreEvent::Output { session_id, .. }
+            | CoreEvent::Notification { session_id, .. }
+            | CoreEvent::TitleChanged { session_id, .. }
+            | CoreEvent::CwdChanged { session_id, .. }
+            | CoreEvent::Exit { session_id, .. } => *session_id,
+        }
+    }
+}
+
+/// Which events a subscriber receives: kinds whose bit is set in `kinds`,
+/// from `session_id` or from every session when it is `None`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EventFilter {
+    pub kinds: u32,
+    pub session_id: Option<Uuid>,
+}
+
+impl EventFilter {
+    pub const ALL_KINDS: u32 = (1 << 9) - 1;
+
+    pub fn new(kinds: &[EventKind], session_id: Option<Uuid>) -> Self {
+        Self {
+            kinds: kinds.iter().fold(0, |mask, kind| mask | kind.bit()),
+            session_id,
+        }
+    }
+
+    pub fn matches(&self, event: &CoreEvent) -> bool {
+        self.kinds & event.kind().bit() != 0
+            && self.session_id.is_none_or(|id| event.session_id() == id)
+    }
+}
+
+impl Default for EventFilter {
+    fn default() -> Self {
+        Self {
+            kinds: Self::ALL_KINDS,
+            session_id: None,
+        }
+    }
+}
+
+struct Subscription {
+    id: SubscriptionId,
+    filter: EventFilter,
+    handler: EventHandler,
+}
+
+/// Fans `Core` events out to subscribers.
+///
+/// Publishing only queues the event, so it is safe under any lock. One
+/// dispatch thread delivers the queue in order, one event at a time, so a
+/// session's events always reach a subscriber in the order they happened.
+pub struct EventBus {
+    sender: Sender<CoreEvent>,
+    subscriptions: Arc<RwLock<Vec<Subscription>>>,
+    /// Kinds some subscriber wants; anything else is dropped unqueued.
+    wanted: Arc<AtomicU32>,
+    next_id: AtomicU64,
+    /// Held while handlers run, so unsubscribing can wait them out.
+    dispatching: Arc<Mutex<()>>,
+    dispatcher: ThreadId,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (sender, receiver) = mpsc::channel::<CoreEvent>();
+        let subscriptions: Arc<RwLock<Vec<Subscription>>> = Arc::default();
+        let dispatching: Arc<Mutex<()>> = Arc::default();
+
+        let dispatcher = {
+            let subscriptions = subscriptions.clone();
+            let dispatching = dispatching.clone();
+            thread::Builder::new()
+                .name("macviber-events".to_string())
+                .spawn(move || {
+                    for event in receiver {
+                        let _dispatching = dispatching.lock();
+                        // Handlers may subscribe or unsubscribe, so they run
+                        // without the list locked.
+                        let handlers: Vec<EventHandler> = subscriptions
+                            .read()
+                            .iter()
+                            .filter(|s| s.filter.matches(&event))
+                            .map(|s| s.handler.clone())
+                            .collect();
+                        for handler in handlers {
+                            handler(&event);
+                        }
+                    }
+                })
+                .expect("failed to spawn event dispatch thread")
+                .thread()
+                .id()
+        };
+
+        Self {
+            sender,
+            subscriptions,
+            wanted: Arc::new(AtomicU32::new(0)),
+            next_id: AtomicU64::new(1),
+            dispatching,
+            dispatcher,
+        }
+    }
+
+    pub fn subscribe(&self, filter: EventFilter, handler: EventHandler) -> SubscriptionId {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let mut subscriptions = self.subscriptions.write();
+        subscriptions.push(Subscription {
+            id,
+            filter,
+            handler,
+        });
+        self.update_wanted(&subscriptions);
+        id
+    }
+
+    /// Removes a subscription. Once this returns its handler is not running
+    /// and will not be called again, unless called from that handler.
+    pub fn unsubscribe(&self, id: SubscriptionId) -> bool {
+        let removed = {
+            let mut subscriptions = self.subscriptions.write();
+            let before = subscriptions.len();
+            subscriptions.retain(|s| s.id != id);
+            self.update_wanted(&subscriptions);
+            subscriptions.len() != before
+        };
+        if removed && thread::current().id() != self.dispatcher {
+            drop(self.dispatching.lock());
+        }
+        removed
+    }
+
+    /// Whether any subscriber wants events of `kind`, to skip building
+    /// costly events nobody receives.
+    pub fn wants(&self, kind: EventKind) -> bool {
+        self.wanted.load(Ordering::Relaxed) & kind.bit() != 0
+    }
+
+    pub fn publish(&self, event: CoreEvent) {
+        if self.wants(event.kind()) {
+            // Fails only once the dispatch thread is gone.
+            let _ = self.sender.send(event);
+        }
+    }
+
+    fn update_wanted(&self, subscriptions: &[Subscription]) {
+        let wanted = subscriptions
+            .iter()
+            .fold(0, |mask, s| mask | s.filter.kinds);
+        self.wanted.store(wanted, Ordering::Relaxed);
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Debug for EventBus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EventBus")
+            .field("subscriptions", &self.subscriptions.read().len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn delivers_filtered_events_in_order_until_unsubscribed() {
+        let (watched, other) = (Uuid::new_v4(), Uuid::new_v4());
+        let bus = EventBus::new();
+        let (sender, received) = mpsc::channel();
+        let filter = EventFilter::new(&[EventKind::Output, EventKind::Exit], Some(watched));
+        let id = bus.subscribe(
+            filter,
+            Arc::new(move |event| sender.send(event.clone()).unwrap()),
+        );
+
+        assert!(bus.wants(EventKind::Output));
+        assert!(!bus.wants(EventKind::TitleChanged));
+        for (session_id, byte) in [(watched, b'a'), (other, b'b'), (watched, b'c')] {
+            bus.publish(CoreEvent::SessionCreated { session_id });
+            bus.publish(CoreEvent::Output {
+                session_id,
+                data: vec![byte],
+            });
+        }
+        bus.publish(CoreEvent::Exit {
+            session_id: watched,
+            status: ExitStatus::with_code(0),
+        });
+
+        let next = || received.recv_timeout(Duration::from_secs(1)).unwrap();
+        let outputs: Vec<Vec<u8>> = (0..2)
+            .map(|_| match next() {
+                CoreEvent::Output { session_id, data } if session_id == watched => data,
+                event => panic!("unexpected {:?}", event),
+            })
+            .collect();
+        assert_eq!(outputs, [b"a", b"c"]);
+        assert_eq!(next().kind(), EventKind::Exit);
+
+        assert!(bus.unsubscribe(id));
+        assert!(!bus.wants(EventKind::Output));
+        bus.publish(CoreEvent::Output {
+            session_id: watched,
+            data: vec![b'd'],
+        });
+        assert!(received.recv_timeout(Duration::from_millis(100)).is_err());
+    }
+}
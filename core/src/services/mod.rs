@@ -1,5 +1,16 @@
+mod detection_rules;
+mod event_bus;
+mod multi_matcher;
 mod notification_detector;
+mod notification_history;
+mod notification_store;
 mod pattern_matcher;
+mod scrollback_search;
 
+pub use detection_rules::*;
+pub use event_bus::*;
 pub use notification_detector::*;
+pub use notification_history::*;
+pub use notification_store::*;
 pub use pattern_matcher::*;
+pub use scrollback_search::*;
@@ -0,0 +1,247 @@
+use aho_corasick::AhoCorasick;
+use regex::{Regex, RegexBuilder, RegexSet};
+
+/// Regexes needed before one `RegexSet` pass beats searching each in turn.
+/// Below this, each regex's own literal prefilter skips most of the text
+/// faster than the set's combined automaton can scan it.
+const REGEX_SET_MIN: usize = 24;
+/// Keywords needed before an Aho-Corasick pass beats `str::contains` on
+/// each, for the same reason.
+const KEYWORD_AUTOMATON_MIN: usize = 16;
+
+/// One pattern fed to a `MultiMatcher`.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum MatchPattern<'a> {
+    Regex {
+        pattern: &'a str,
+        case_insensitive: bool,
+    },
+    Keyword {
+        keyword: &'a str,
+        case_insensitive: bool,
+    },
+}
+
+#[derive(Debug, Clone)]
+enum RegexGroup {
+    Set(RegexSet),
+    Each(Vec<Regex>),
+}
+
+#[derive(Debug, Clone)]
+enum KeywordGroup {
+    Automaton(AhoCorasick),
+    /// Lowercased already when the group ignores case.
+    Each(Vec<String>),
+}
+
+/// Finds which of many patterns occur in a text. Large pattern lists are
+/// matched in a single pass: regexes through a `RegexSet`, keywords through
+/// Aho-Corasick automatons.
+#[derive(Debug, Clone)]
+pub(crate) struct MultiMatcher {
+    len: usize,
+    regexes: (RegexGroup, Vec<usize>),
+    folded_keywords: (KeywordGroup, Vec<usize>),
+    exact_keywords: (KeywordGroup, Vec<usize>),
+}
+
+impl MultiMatcher {
+    /// Builds a matcher over patterns that are known to compile. The slot a
+    /// pattern is reported under is its position in `patterns`.
+    pub(crate) fn new(patterns: &[MatchPattern]) -> Self {
+        let folded_count = patterns
+            .iter()
+            .filter(|p| {
+                matches!(
+                    p,
+                    MatchPattern::Keyword {
+                        case_insensitive: true,
+                        ..
+                    }
+                )
+            })
+            .count();
+        let fold_with_automaton = folded_count >= KEYWORD_AUTOMATON_MIN;
+
+        let mut regexes = (Vec::new(), Vec::new());
+        let mut folded = (Vec::new(), Vec::new());
+        let mut exact = (Vec::new(), Vec::new());
+        for (slot, pattern) in patterns.iter().enumerate() {
+            match *pattern {
+                MatchPattern::Regex {
+                    pattern,
+                    case_insensitive,
+                } => {
+                    regexes.0.push((pattern.to_string(), case_insensitive));
+                    regexes.1.push(slot);
+                }
+                // Aho-Corasick only folds ASCII case.
+                MatchPattern::Keyword {
+                    keyword,
+                    case_insensitive: true,
+                } if fold_with_automaton && !keyword.is_ascii() => {
+                    regexes.0.push((regex::escape(keyword), true));
+                    regexes.1.push(slot);
+                }
+                MatchPattern::Keyword {
+                    keyword,
+                    case_insensitive: true,
+                } => {
+                    folded.0.push(keyword);
+                    folded.1.push(slot);
+                }
+                MatchPattern::Keyword { keyword, .. } => {
+                    exact.0.push(keyword);
+                    exact.1.push(slot);
+                }
+            }
+        }
+
+        Self {
+            len: patterns.len(),
+            regexes: (build_regex_group(&regexes.0), regexes.1),
+            folded_keywords: (build_keyword_group(&folded.0, true), folded.1),
+            exact_keywords: (build_keyword_group(&exact.0, false), exact.1),
+        }
+    }
+
+    /// Whether each slot's pattern occurs somewhere in `text`.
+    pub(crate) fn matches(&self, text: &str) -> Vec<bool> {
+        let mut matched = vec![false; self.len];
+
+        let (regexes, slots) = &self.regexes;
+        match regexes {
+            RegexGroup::Set(set) => {
+                for index in set.matches(text).iter() {
+                    matched[slots[index]] = true;
+                }
+            }
+            RegexGroup::Each(regexes) => {
+                for (regex, slot) in regexes.iter().zip(slots) {
+                    matched[*slot] = regex.is_match(text);
+                }
+            }
+        }
+
+        for ((keywords, slots), case_insensitive) in
+            [(&self.folded_keywords, true), (&self.exact_keywords, false)]
+        {
+            match keywords {
+                KeywordGroup::Automaton(automaton) => {
+                    for found in automaton.find_overlapping_iter(text) {
+                        matched[slots[found.pattern().as_usize()]] = true;
+                    }
+                }
+                KeywordGroup::Each(keywords) if keywords.is_empty() => {}
+                KeywordGroup::Each(keywords) => {
+                    let lowered;
+                    let haystack = if case_insensitive {
+                        lowered = text.to_lowercase();
+                        &lowered
+                    } else {
+                        text
+                    };
+                    for (keyword, slot) in keywords.iter().zip(slots) {
+                        matched[*slot] = haystack.contains(keyword.as_str());
+                    }
+                }
+            }
+        }
+        matched
+    }
+}
+
+fn build_regex_group(patterns: &[(String, bool)]) -> RegexGroup {
+    if patterns.len() >= REGEX_SET_MIN {
+        let combined = patterns.iter().map(|(pattern, case_insensitive)| {
+            if *case_insensitive {
+                format!("(?i:{})", pattern)
+            } else {
+                format!("(?:{})", pattern)
+            }
+        });
+        match RegexSet::new(combined) {
+            Ok(set) => return RegexGroup::Set(set),
+            // Too large to combine, or a pattern that cannot be wrapped in a
+            // group (such as a trailing `(?x)` comment).
+            Err(e) => log::warn!("Matching {} patterns one by one: {}", patterns.len(), e),
+        }
+    }
+    let regexes = patterns.iter().map(|(pattern, case_insensitive)| {
+        RegexBuilder::new(pattern)
+            .case_insensitive(*case_insensitive)
+            .build()
+            .expect("patterns are validated before indexing")
+    });
+    RegexGroup::Each(regexes.collect())
+}
+
+fn build_keyword_group(keywords: &[&str], case_insensitive: bool) -> KeywordGroup {
+    if keywords.len() >= KEYWORD_AUTOMATON_MIN {
+        match AhoCorasick::builder()
+            .ascii_case_insensitive(case_insensitive)
+            .build(keywords)
+        {
+            Ok(automaton) => return KeywordGroup::Automaton(automaton),
+            Err(e) => log::warn!("Matching {} keywords one by one: {}", keywords.len(), e),
+        }
+    }
+    let keywords = keywords.iter().map(|k| {
+        if case_insensitive {
+            k.to_lowercase()
+        } else {
+            k.to_string()
+        }
+    });
+    KeywordGroup::Each(keywords.collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keyword(keyword: &str, case_insensitive: bool) -> MatchPattern<'_> {
+        MatchPattern::Keyword {
+            keyword,
+            case_insensitive,
+        }
+    }
+
+    fn regex(pattern: &str) -> MatchPattern<'_> {
+        MatchPattern::Regex {
+            pattern,
+            case_insensitive: false,
+        }
+    }
+
+    #[test]
+    fn single_pass_agrees_with_checking_each_pattern() {
+        let names: Vec<String> = (0..REGEX_SET_MIN.max(KEYWORD_AUTOMATON_MIN))
+            .map(|i| format!("job-{}", i))
+            .collect();
+        let mut patterns = vec![
+            keyword("ÉCHEC", true),
+            keyword("Done", false),
+            regex(r"exit (?P<code>[1-9]\d*)"),
+        ];
+        for name in &names {
+            patterns.push(keyword(name, true));
+            patterns.push(regex(name));
+        }
+
+        let text = "JOB-3 échec: exit 2\ndone";
+        let matcher = MultiMatcher::new(&patterns);
+        let single_pass = matcher.matches(text);
+        let each: Vec<bool> = patterns
+            .iter()
+            .map(|p| MultiMatcher::new(std::slice::from_ref(p)).matches(text)[0])
+            .collect();
+
+        assert!(matches!(matcher.regexes.0, RegexGroup::Set(_)));
+        assert_eq!(single_pass, each);
+        assert_eq!(&single_pass[..3], [true, false, true]);
+        // "JOB-3" matches the keyword, which ignores case, but not the regex.
+        assert_eq!(&single_pass[9..11], [true, false]);
+    }
+}
@@ -1,112 +1,380 @@
+use super::{DetectionRules, PatternMatcher};
 use crate::models::{Notification, NotificationType};
-use regex::Regex;
+use regex::{Regex, RegexSet};
+use std::collections::HashMap;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 use uuid::Uuid;
 
+const CLAUDE_PROMPT_MESSAGE: &str = "Claude is waiting for input";
+/// Longest incomplete escape sequence held back for the next chunk.
+const MAX_PENDING_ESCAPE: usize = 256;
+/// Matches of `SLASH_COMMAND_PATTERNS` needed to treat output as the menu.
+const SLASH_COMMAND_MIN_MATCHES: usize = 2;
+
+/// Prompts Claude shows on its last line while it waits for input.
+const CLAUDE_PROMPT_PATTERNS: &[&str] = &["❯", "claude>", r"\[Claude\]", ">>> ", r"\.\.\. "];
+
+/// Cursor movement and colors Claude uses to draw its slash-command menu,
+/// which is full of words the detection patterns would otherwise match.
+const SLASH_COMMAND_PATTERNS: &[&str] = &[
+    r"\[3C\[1B",
+    r"\[17C",
+    r"\[\d+C\[1B",
+    r"\[94m.*\[39m",
+    r"\[37m.*\[39m",
+    r"(\[3C\[1B.*){2,}",
+];
+
+/// How long after a notification another of the same type is held back in
+/// the same session.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DebounceWindows {
+    pub default: Duration,
+    pub per_type: HashMap<NotificationType, Duration>,
+}
+
+impl DebounceWindows {
+    pub const DEFAULT_WINDOW: Duration = Duration::from_millis(500);
+
+    /// The same window for every type.
+    pub fn uniform(window: Duration) -> Self {
+        Self {
+            default: window,
+            per_type: HashMap::new(),
+        }
+    }
+
+    pub fn window(&self, notification_type: NotificationType) -> Duration {
+        self.per_type
+            .get(&notification_type)
+            .copied()
+            .unwrap_or(self.default)
+    }
+
+    pub fn set_window(&mut self, notification_type: NotificationType, window: Duration) {
+        self.per_type.insert(notification_type, window);
+    }
+}
+
+impl Default for DebounceWindows {
+    fn default() -> Self {
+        Self::uniform(Self::DEFAULT_WINDOW)
+    }
+}
+
+/// One session's recent output and what it last raised, so output from
+/// different sessions never runs together and repeats can be held back.
+#[derive(Debug, Default)]
+struct SessionMatchState {
+    /// Recent ANSI-stripped output, capped at the detector's `buffer_size`.
+    buffer: String,
+    /// Start of an escape sequence cut off at the end of the last chunk.
+    pending_escape: String,
+    last_fired: HashMap<NotificationType, Instant>,
+    last_matched_key: Option<String>,
+}
+
+impl SessionMatchState {
+    fn trim_buffer(&mut self, buffer_size: usize) {
+        if self.buffer.len() <= buffer_size {
+            return;
+        }
+        let mut cut = self.buffer.len() - buffer_size;
+        while !self.buffer.is_char_boundary(cut) {
+            cut += 1;
+        }
+        self.buffer.drain(..cut);
+    }
+}
+
 pub struct NotificationDetector {
-    patterns: Vec<(Regex, NotificationType)>,
+    rules: Arc<DetectionRules>,
+    pattern_matcher: PatternMatcher,
+    claude_prompt_patterns: RegexSet,
+    slash_command_patterns: RegexSet,
     ansi_strip_regex: Regex,
-    last_detection_time: Option<Instant>,
-    debounce_interval: Duration,
-    last_matched_key: Option<String>,
+    incomplete_escape_regex: Regex,
+    /// Per-session output kept, in bytes.
+    buffer_size: usize,
+    context_lines: usize,
+    debounce_windows: DebounceWindows,
+    sessions: HashMap<Uuid, SessionMatchState>,
 }
 
 impl NotificationDetector {
+    pub const DEFAULT_BUFFER_SIZE: usize = 10_000;
+    pub const DEFAULT_CONTEXT_LINES: usize = 10;
+
     pub fn new() -> Self {
-        let patterns = vec![
-            (r"\?\s*$", NotificationType::Question),
-            (r"\(y/n\)", NotificationType::Question),
-            (r"\[Y/n\]", NotificationType::Question),
-            (r"\[yes/no\]", NotificationType::Question),
-            (r"Press Enter to continue", NotificationType::Question),
-            (r"Allow\s+.*\?", NotificationType::PermissionRequest),
-            (r"Do you want to", NotificationType::PermissionRequest),
-            (r"Proceed\?", NotificationType::PermissionRequest),
-            (r"\(approve/deny\)", NotificationType::PermissionRequest),
-            (r"✓.*completed", NotificationType::Completion),
-            (r"Done\.", NotificationType::Completion),
-            (r"Successfully", NotificationType::Completion),
-            (r"Error:", NotificationType::Error),
-            (r"Failed:", NotificationType::Error),
-            (r"FAILED", NotificationType::Error),
-        ];
-
-        let compiled_patterns: Vec<_> = patterns
-            .into_iter()
-            .filter_map(|(p, t)| Regex::new(p).ok().map(|r| (r, t)))
-            .collect();
+        Self::with_rules(Arc::new(DetectionRules::builtin()))
+    }
 
+    pub fn with_rules(rules: Arc<DetectionRules>) -> Self {
         let ansi_strip_regex = Regex::new(
             r"(?x)
             \x1b\[[0-9;]*[A-Za-z] |
             \x1b\[\?[0-9;]*[A-Za-z] |
-            \x1b\][^\x07]*\x07 |
-            \x1b[()][AB012]
+            \x1b\[[0-9;]*[>=<] |
+            \x1b\].*?\x07 |
+            \x1b\].*?\x1b\\ |
+            \x1b[()][AB012] |
+            \x1b= |
+            \x1b>
             ",
         )
         .unwrap();
+        let incomplete_escape_regex = Regex::new(r"^\x1b(?:\[[?0-9;]*|[()])?$").unwrap();
 
         Self {
-            patterns: compiled_patterns,
+            rules,
+            pattern_matcher: PatternMatcher::new(),
+            claude_prompt_patterns: RegexSet::new(CLAUDE_PROMPT_PATTERNS).unwrap(),
+            slash_command_patterns: RegexSet::new(SLASH_COMMAND_PATTERNS).unwrap(),
             ansi_strip_regex,
-            last_detection_time: None,
-            debounce_interval: Duration::from_millis(500),
-            last_matched_key: None,
+            incomplete_escape_regex,
+            buffer_size: Self::DEFAULT_BUFFER_SIZE,
+            context_lines: Self::DEFAULT_CONTEXT_LINES,
+            debounce_windows: DebounceWindows::default(),
+            sessions: HashMap::new(),
         }
     }
 
+    /// Adds `text` to the session's rolling buffer and checks the output
+    /// since the start of the line it continues, so a prompt split across
+    /// reads still matches.
     pub fn detect(&mut self, text: &str, session_id: Uuid) -> Option<Notification> {
-        if let Some(last_time) = self.last_detection_time {
-            if last_time.elapsed() < self.debounce_interval {
+        let clean_text = self.append_output(session_id, text);
+
+        if self.is_slash_command_menu(text) {
+            return None;
+        }
+
+        let matched = self.rules.find(&clean_text).map(|m| {
+            let rule_id = m.rule_id.to_string();
+            (m.notification_type, m.message, rule_id, m.responses.clone())
+        });
+
+        if let Some((notification_type, message, rule_id, responses)) = matched {
+            let match_key = format!("{:?}", notification_type);
+            if !self.record_match(session_id, notification_type, match_key) {
+                return None;
+            }
+            let mut notification = Notification::new(
+                session_id,
+                notification_type,
+                message.unwrap_or_else(|| extract_message(&clean_text)),
+                self.recent_context(session_id),
+            );
+            notification.matched_rule_id = Some(rule_id);
+            notification.responses = responses;
+            return Some(notification);
+        }
+
+        if self.is_claude_prompt_waiting(&clean_text) {
+            let match_key = "claudePrompt".to_string();
+            if !self.record_match(session_id, NotificationType::Question, match_key) {
                 return None;
             }
+            return Some(Notification::new(
+                session_id,
+                NotificationType::Question,
+                CLAUDE_PROMPT_MESSAGE.to_string(),
+                self.recent_context(session_id),
+            ));
         }
 
-        let clean_text = self.strip_ansi(text);
+        let custom = self.pattern_matcher.first_match(&clean_text).map(|m| {
+            let captures = m.captures.into_iter().map(|c| (c.name, c.text)).collect();
+            (
+                m.pattern.id,
+                m.pattern.name.clone(),
+                m.pattern.auto_pin,
+                captures,
+            )
+        });
+        if let Some((pattern_id, pattern_name, auto_pin, captures)) = custom {
+            let match_key = format!("custom:{}", pattern_id);
+            if !self.record_match(session_id, NotificationType::Custom, match_key) {
+                return None;
+            }
+            let mut notification = Notification::new(
+                session_id,
+                NotificationType::Custom,
+                extract_message(&clean_text),
+                self.recent_context(session_id),
+            );
+            if auto_pin {
+                notification.toggle_pin();
+            }
+            notification.matched_pattern_id = Some(pattern_id);
+            notification.matched_pattern_name = Some(pattern_name);
+            notification.captures = captures;
+            return Some(notification);
+        }
+
+        None
+    }
+
+    pub fn rules(&self) -> &Arc<DetectionRules> {
+        &self.rules
+    }
 
-        for (regex, notification_type) in &self.patterns {
-            if regex.is_match(&clean_text) {
-                let match_key = format!("{:?}:{}", notification_type, session_id);
+    /// Swaps the rule pack; buffers and debounce state carry over.
+    pub fn set_rules(&mut self, rules: Arc<DetectionRules>) {
+        self.rules = rules;
+    }
 
-                if self.last_matched_key.as_ref() == Some(&match_key) {
-                    return None;
-                }
+    /// User-defined patterns, checked after the rules and the Claude prompt.
+    pub fn pattern_matcher(&self) -> &PatternMatcher {
+        &self.pattern_matcher
+    }
 
-                self.last_matched_key = Some(match_key);
-                self.last_detection_time = Some(Instant::now());
+    pub fn pattern_matcher_mut(&mut self) -> &mut PatternMatcher {
+        &mut self.pattern_matcher
+    }
 
-                let message = self.extract_message(&clean_text);
+    pub fn set_pattern_matcher(&mut self, pattern_matcher: PatternMatcher) {
+        self.pattern_matcher = pattern_matcher;
+    }
 
-                return Some(Notification::new(
-                    session_id,
-                    *notification_type,
-                    message,
-                    String::new(),
-                ));
-            }
+    pub fn set_buffer_size(&mut self, buffer_size: usize) {
+        self.buffer_size = buffer_size;
+        for state in self.sessions.values_mut() {
+            state.trim_buffer(buffer_size);
         }
+    }
 
-        None
+    pub fn set_context_lines(&mut self, context_lines: usize) {
+        self.context_lines = context_lines;
+    }
+
+    /// The last `context_lines` lines of the session's buffered output,
+    /// ignoring trailing blank lines.
+    pub fn recent_context(&self, session_id: Uuid) -> String {
+        let Some(state) = self.sessions.get(&session_id) else {
+            return String::new();
+        };
+        let lines: Vec<&str> = state
+            .buffer
+            .trim_end()
+            .lines()
+            .map(|line| line.trim_end_matches('\r'))
+            .collect();
+        let skip = lines.len().saturating_sub(self.context_lines);
+        lines[skip..].join("\n")
+    }
+
+    pub fn debounce_windows(&self) -> &DebounceWindows {
+        &self.debounce_windows
+    }
+
+    pub fn set_debounce_windows(&mut self, debounce_windows: DebounceWindows) {
+        self.debounce_windows = debounce_windows;
     }
 
     pub fn reset(&mut self) {
-        self.last_detection_time = None;
-        self.last_matched_key = None;
+        self.sessions.clear();
+    }
+
+    /// Forgets a session's last match so the same kind of notification can
+    /// fire again, without lifting the debounce.
+    pub fn reset_last_match(&mut self, session_id: Uuid) {
+        if let Some(state) = self.sessions.get_mut(&session_id) {
+            state.last_matched_key = None;
+        }
+    }
+
+    /// Called when the user types into a session. Whatever it shows next
+    /// answers that input, so even an identical prompt is new and notifies
+    /// again at once. The partial line the input answers is dropped, so its
+    /// echo is not matched against the prompt again.
+    pub fn note_input(&mut self, session_id: Uuid) {
+        if let Some(state) = self.sessions.get_mut(&session_id) {
+            state.last_fired.clear();
+            state.last_matched_key = None;
+            state.pending_escape.clear();
+            let line_start = state.buffer.rfind('\n').map_or(0, |i| i + 1);
+            state.buffer.truncate(line_start);
+        }
+    }
+
+    /// Records a match for the session, or returns false if it falls inside
+    /// the debounce window for its type or repeats the session's last match.
+    fn record_match(
+        &mut self,
+        session_id: Uuid,
+        notification_type: NotificationType,
+        match_key: String,
+    ) -> bool {
+        let window = self.debounce_windows.window(notification_type);
+        let state = self.sessions.entry(session_id).or_default();
+        let debounced = state
+            .last_fired
+            .get(&notification_type)
+            .is_some_and(|fired| fired.elapsed() < window);
+        if debounced || state.last_matched_key.as_ref() == Some(&match_key) {
+            return false;
+        }
+        state.last_matched_key = Some(match_key);
+        state.last_fired.insert(notification_type, Instant::now());
+        true
+    }
+
+    /// Strips `text`, appends it to the session's buffer and returns the
+    /// window to match: the buffered partial line it continues plus the new
+    /// text.
+    fn append_output(&mut self, session_id: Uuid, text: &str) -> String {
+        let mut raw = self
+            .sessions
+            .get_mut(&session_id)
+            .map(|state| std::mem::take(&mut state.pending_escape))
+            .unwrap_or_default();
+        raw.push_str(text);
+        let pending_escape = match self.incomplete_escape_start(&raw) {
+            Some(start) => raw.split_off(start),
+            None => String::new(),
+        };
+        let clean = self.strip_ansi(&raw);
+
+        let state = self.sessions.entry(session_id).or_default();
+        state.pending_escape = pending_escape;
+        let carried = state.buffer.len() - state.buffer.rfind('\n').map_or(0, |i| i + 1);
+        state.buffer.push_str(&clean);
+        state.trim_buffer(self.buffer_size);
+
+        let buffer = &state.buffer;
+        let mut start = buffer.len().saturating_sub(carried + clean.len());
+        while !buffer.is_char_boundary(start) {
+            start += 1;
+        }
+        buffer[start..].to_string()
+    }
+
+    /// Where an escape sequence cut off at the end of `raw` begins.
+    fn incomplete_escape_start(&self, raw: &str) -> Option<usize> {
+        // An OSC string can hold a cut-off ST, so look for its start first.
+        let unterminated_osc = raw.rfind("\x1b]").filter(|&start| {
+            let osc = &raw[start..];
+            !osc.contains('\x07') && !osc.contains("\x1b\\")
+        });
+        let start = unterminated_osc.or_else(|| raw.rfind('\x1b'))?;
+        let tail = &raw[start..];
+        let incomplete = unterminated_osc.is_some() || self.incomplete_escape_regex.is_match(tail);
+        (incomplete && tail.len() <= MAX_PENDING_ESCAPE).then_some(start)
     }
 
     fn strip_ansi(&self, text: &str) -> String {
         self.ansi_strip_regex.replace_all(text, "").to_string()
     }
 
-    fn extract_message(&self, text: &str) -> String {
-        text.lines()
-            .rev()
-            .find(|line| {
-                let trimmed = line.trim();
-                !trimmed.is_empty() && trimmed.len() > 5
-            })
-            .map(|s| s.chars().take(200).collect())
-            .unwrap_or_else(|| text.chars().take(200).collect())
+    fn is_slash_command_menu(&self, raw_text: &str) -> bool {
+        self.slash_command_patterns.matches(raw_text).iter().count() >= SLASH_COMMAND_MIN_MATCHES
+    }
+
+    fn is_claude_prompt_waiting(&self, clean_text: &str) -> bool {
+        let last_line = clean_text.rsplit(is_newline).next().unwrap_or("").trim();
+        !last_line.is_empty() && self.claude_prompt_patterns.is_match(last_line)
     }
 }
 
@@ -115,3 +383,193 @@ impl Default for NotificationDetector {
         Self::new()
     }
 }
+
+/// Line separators as Foundation's `CharacterSet.newlines` defines them.
+fn is_newline(c: char) -> bool {
+    matches!(
+        c,
+        '\n' | '\u{0B}' | '\u{0C}' | '\r' | '\u{85}' | '\u{2028}' | '\u{2029}'
+    )
+}
+
+/// The last line with more than five characters, trimmed and capped at 200.
+fn extract_message(text: &str) -> String {
+    text.split(is_newline)
+        .rev()
+        .map(str::trim)
+        .find(|line| line.chars().count() > 5)
+        .unwrap_or(text)
+        .chars()
+        .take(200)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::NotificationResponse;
+    use crate::services::CustomPattern;
+    use serde::Deserialize;
+
+    /// Terminal output paired with what the Swift `ClaudeNotificationDetector`
+    /// reported for it.
+    const CORPUS: &str = include_str!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/notification_corpus.json"
+    ));
+
+    #[derive(Deserialize)]
+    struct Case {
+        name: String,
+        output: String,
+        expected: Option<Expected>,
+    }
+
+    #[derive(Deserialize)]
+    struct Expected {
+        #[serde(rename = "type")]
+        notification_type: NotificationType,
+        message: String,
+    }
+
+    #[test]
+    fn matches_swift_detector_corpus() {
+        let cases: Vec<Case> = serde_json::from_str(CORPUS).unwrap();
+        let session_id = Uuid::new_v4();
+
+        for case in cases {
+            let detected = NotificationDetector::new().detect(&case.output, session_id);
+            let actual = detected.map(|n| (n.notification_type, n.message));
+            let expected = case.expected.map(|e| (e.notification_type, e.message));
+            assert_eq!(actual, expected, "case {}", case.name);
+        }
+    }
+
+    #[test]
+    fn repeated_match_is_suppressed_until_reset() {
+        let session_id = Uuid::new_v4();
+        let mut detector = NotificationDetector::new();
+        detector.set_debounce_windows(DebounceWindows::uniform(Duration::ZERO));
+
+        assert!(detector.detect("Continue? ", session_id).is_some());
+        assert!(detector.detect("Really continue? ", session_id).is_none());
+        assert!(detector.detect("error: oops", session_id).is_some());
+
+        detector.reset_last_match(session_id);
+        assert!(detector.detect("error: oops", session_id).is_some());
+    }
+
+    #[test]
+    fn debounce_is_per_session_and_type_and_lifted_by_input() {
+        let (first, second) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut detector = NotificationDetector::new();
+        let mut windows = DebounceWindows::uniform(Duration::from_secs(60));
+        windows.set_window(NotificationType::Error, Duration::ZERO);
+        detector.set_debounce_windows(windows);
+
+        assert!(detector.detect("error: oops\n", first).is_some());
+        // No window for errors, but the same match as last time.
+        assert!(detector.detect("error: oops\n", first).is_none());
+        assert!(detector.detect("Continue? ", second).is_some());
+        assert!(detector.detect("Continue? ", first).is_some());
+        assert!(detector.detect("Overwrite? ", first).is_none());
+
+        detector.note_input(first);
+        assert!(detector.detect("Continue? ", first).is_some());
+        assert!(detector.detect("Overwrite? ", second).is_none());
+    }
+
+    #[test]
+    fn matches_prompt_split_across_chunks() {
+        let session_id = Uuid::new_v4();
+        let mut detector = NotificationDetector::new();
+        detector.set_context_lines(2);
+
+        assert!(detector
+            .detect("$ make deploy\r\nBuilding\r\n", session_id)
+            .is_none());
+        assert!(detector
+            .detect("Overwrite config (y/\x1b[1", session_id)
+            .is_none());
+        let notification = detector
+            .detect("mn\x1b[0m) ", session_id)
+            .expect("prompt split across chunks");
+
+        assert_eq!(notification.notification_type, NotificationType::Question);
+        assert_eq!(notification.message, "Overwrite config (y/n)");
+        assert_eq!(notification.context, "Building\nOverwrite config (y/n)");
+        assert_eq!(notification.matched_rule_id.as_deref(), Some("yes-no"));
+        assert_eq!(
+            notification.responses.get(&NotificationResponse::Yes),
+            Some(&"y\r".to_string())
+        );
+    }
+
+    #[test]
+    fn echoed_answer_does_not_renotify_the_prompt() {
+        let session_id = Uuid::new_v4();
+        let mut detector = NotificationDetector::new();
+
+        assert!(detector
+            .detect("Overwrite config (y/n) ", session_id)
+            .is_some());
+        detector.note_input(session_id);
+        assert!(detector.detect("y", session_id).is_none());
+        assert!(detector
+            .detect("\r\nWrote config\r\n", session_id)
+            .is_none());
+
+        // The next prompt is new and notifies at once.
+        let notification = detector
+            .detect("Restart now? (y/n) ", session_id)
+            .expect("next prompt");
+        assert_eq!(notification.message, "Restart now? (y/n)");
+    }
+
+    #[test]
+    fn interleaved_sessions_do_not_share_output() {
+        let (first, second) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut detector = NotificationDetector::new();
+
+        assert!(detector
+            .detect("Overwrite config (y/\x1b[1", first)
+            .is_none());
+        assert!(detector.detect("building\r\nn) ", second).is_none());
+        let notification = detector
+            .detect("mn) ", first)
+            .expect("prompt split around another session's output");
+
+        assert_eq!(notification.session_id, first);
+        assert_eq!(notification.message, "Overwrite config (y/n)");
+        assert_eq!(notification.context, "Overwrite config (y/n)");
+        assert_eq!(detector.recent_context(second), "building\nn)");
+    }
+
+    #[test]
+    fn custom_pattern_raises_pinned_custom_notification() {
+        let session_id = Uuid::new_v4();
+        let pattern_id = Uuid::new_v4();
+        let mut detector = NotificationDetector::new();
+        detector
+            .pattern_matcher_mut()
+            .add_pattern(CustomPattern {
+                id: pattern_id,
+                name: "Deploy".to_string(),
+                pattern: r"deploy to (?P<env>\w+)".to_string(),
+                is_regex: true,
+                is_enabled: true,
+                auto_pin: true,
+            })
+            .unwrap();
+
+        let notification = detector
+            .detect("starting deploy to staging\r\n", session_id)
+            .expect("custom pattern match");
+
+        assert_eq!(notification.notification_type, NotificationType::Custom);
+        assert!(notification.is_pinned);
+        assert_eq!(notification.matched_pattern_id, Some(pattern_id));
+        assert_eq!(notification.matched_pattern_name.as_deref(), Some("Deploy"));
+        assert_eq!(notification.captures["env"], "staging");
+    }
+}
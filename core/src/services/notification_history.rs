@@ -0,0 +1,321 @@
+use crate::models::Notification;
+use crate::CoreError;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use uuid::Uuid;
+
+/// Records tolerated beyond twice the live count before compacting.
+const COMPACT_SLACK: usize = 256;
+
+/// How long unpinned notifications are kept. Pinned ones are never expired.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetentionPolicy {
+    pub max_age: Option<Duration>,
+    pub max_count: Option<usize>,
+}
+
+impl RetentionPolicy {
+    pub const DEFAULT_MAX_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+    pub const DEFAULT_MAX_COUNT: usize = 1000;
+
+    pub fn new(max_age: Option<Duration>, max_count: Option<usize>) -> Self {
+        Self { max_age, max_count }
+    }
+
+    /// Ids of unpinned notifications that fall outside the policy: older than
+    /// `max_age`, or beyond the newest `max_count`.
+    pub fn expired<'a>(&self, notifications: impl Iterator<Item = &'a Notification>) -> Vec<Uuid> {
+        let now = SystemTime::now();
+        let mut unpinned: Vec<&Notification> = notifications.filter(|n| !n.is_pinned).collect();
+        unpinned.sort_by_key(|n| std::cmp::Reverse(n.created_at));
+
+        unpinned
+            .iter()
+            .enumerate()
+            .filter(|(rank, n)| {
+                let too_old = self.max_age.is_some_and(|max_age| {
+                    now.duration_since(n.created_at)
+                        .is_ok_and(|age| age > max_age)
+                });
+                let too_many = self.max_count.is_some_and(|max| *rank >= max);
+                too_old || too_many
+            })
+            .map(|(_, n)| n.id)
+            .collect()
+    }
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self::new(Some(Self::DEFAULT_MAX_AGE), Some(Self::DEFAULT_MAX_COUNT))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum HistoryRecord {
+    Upsert { notification: Box<Notification> },
+    Remove { id: Uuid },
+}
+
+/// Append-only JSON-lines log of notification changes.
+///
+/// Every change is appended as one record; replaying the log rebuilds the
+/// latest state. The log is rewritten with just the live notifications when
+/// it is opened and whenever stale records start to dominate it.
+pub struct NotificationHistory {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    retention: RetentionPolicy,
+    records: usize,
+}
+
+impl NotificationHistory {
+    pub const FILE_NAME: &'static str = "notifications.jsonl";
+
+    /// Opens the log in `directory`, creating it if needed, and returns the
+    /// notifications it holds after applying `retention`.
+    pub fn open<P: AsRef<Path>>(
+        directory: P,
+        retention: RetentionPolicy,
+    ) -> Result<(Self, Vec<Notification>), CoreError> {
+        let directory = directory.as_ref();
+        std::fs::create_dir_all(directory)?;
+        let path = directory.join(Self::FILE_NAME);
+
+        let mut notifications = match File::open(&path) {
+            Ok(file) => replay(BufReader::new(file), &path)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+        for id in retention.expired(notifications.values()) {
+            notifications.remove(&id);
+        }
+
+        let mut loaded: Vec<Notification> = notifications.into_values().collect();
+        loaded.sort_by_key(|n| n.created_at);
+
+        let writer = rewrite(&path, &loaded)?;
+        let history = Self {
+            path,
+            writer,
+            retention,
+            records: loaded.len(),
+        };
+        Ok((history, loaded))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn retention(&self) -> RetentionPolicy {
+        self.retention
+    }
+
+    pub fn record_upsert(&mut self, notification: &Notification) -> Result<(), CoreError> {
+        self.append(&HistoryRecord::Upsert {
+            notification: Box::new(notification.clone()),
+        })
+    }
+
+    pub fn record_remove(&mut self, id: Uuid) -> Result<(), CoreError> {
+        self.append(&HistoryRecord::Remove { id })
+    }
+
+    /// Rewrites the log if most of its records are superseded.
+    pub fn compact_if_needed<'a>(
+        &mut self,
+        live: impl ExactSizeIterator<Item = &'a Notification>,
+    ) -> Result<(), CoreError> {
+        if self.records <= 2 * live.len() + COMPACT_SLACK {
+            return Ok(());
+        }
+        let mut live: Vec<Notification> = live.cloned().collect();
+        live.sort_by_key(|n| n.created_at);
+        self.writer = rewrite(&self.path, &live)?;
+        self.records = live.len();
+        Ok(())
+    }
+
+    fn append(&mut self, record: &HistoryRecord) -> Result<(), CoreError> {
+        serde_json::to_writer(&mut self.writer, record).map_err(std::io::Error::from)?;
+        self.writer.write_all(b"\n")?;
+        // Flush per record so a crash loses at most the change in flight.
+        self.writer.flush()?;
+        self.records += 1;
+        Ok(())
+    }
+}
+
+fn replay(reader: impl BufRead, path: &Path) -> Result<HashMap<Uuid, Notification>, CoreError> {
+    let mut notifications = HashMap::new();
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        // A torn final line from a crash should not cost the whole history.
+        match serde_json::from_str(&line) {
+            Ok(HistoryRecord::Upsert { notification }) => {
+                notifications.insert(notification.id, *notification);
+            }
+            Ok(HistoryRecord::Remove { id }) => {
+                notifications.remove(&id);
+            }
+            Err(e) => log::warn!(
+                "Skipping bad record at {}:{}: {}",
+                path.display(),
+                index + 1,
+                e
+            ),
+        }
+    }
+    Ok(notifications)
+}
+
+/// Atomically replaces the log with `notifications` and reopens it for append.
+fn rewrite(path: &Path, notifications: &[Notification]) -> Result<BufWriter<File>, CoreError> {
+    let temp_path = path.with_extension("jsonl.tmp");
+    {
+        let mut writer = BufWriter::new(File::create(&temp_path)?);
+        for notification in notifications {
+            let record = HistoryRecord::Upsert {
+                notification: Box::new(notification.clone()),
+            };
+            serde_json::to_writer(&mut writer, &record).map_err(std::io::Error::from)?;
+            writer.write_all(b"\n")?;
+        }
+        writer
+            .into_inner()
+            .map_err(|e| e.into_error())?
+            .sync_all()?;
+    }
+    std::fs::rename(&temp_path, path)?;
+
+    let file = OpenOptions::new().append(true).open(path)?;
+    Ok(BufWriter::new(file))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::NotificationType;
+
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new() -> Self {
+            Self(std::env::temp_dir().join(format!("macviber-history-{}", Uuid::new_v4())))
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn notification(message: &str, age: Duration) -> Notification {
+        let mut notification = Notification::new(
+            Uuid::new_v4(),
+            NotificationType::Question,
+            message.to_string(),
+            String::new(),
+        );
+        notification.created_at = SystemTime::now() - age;
+        notification
+    }
+
+    fn record_count(history: &NotificationHistory) -> usize {
+        std::fs::read_to_string(history.path())
+            .unwrap()
+            .lines()
+            .count()
+    }
+
+    fn unlimited() -> RetentionPolicy {
+        RetentionPolicy::new(None, None)
+    }
+
+    #[test]
+    fn replays_changes_after_reopening() {
+        let dir = TempDir::new();
+        let (mut history, loaded) = NotificationHistory::open(&dir.0, unlimited()).unwrap();
+        assert!(loaded.is_empty());
+
+        let mut kept = notification("kept", Duration::from_secs(2));
+        let removed = notification("removed", Duration::from_secs(1));
+        history.record_upsert(&kept).unwrap();
+        history.record_upsert(&removed).unwrap();
+        kept.mark_as_read();
+        history.record_upsert(&kept).unwrap();
+        history.record_remove(removed.id).unwrap();
+        let path = history.path().to_path_buf();
+        drop(history);
+
+        // A torn last line is skipped, not fatal.
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"{\"op\":\"ups").unwrap();
+        drop(file);
+
+        let (history, loaded) = NotificationHistory::open(&dir.0, unlimited()).unwrap();
+        assert_eq!(loaded.len(), 1);
+        assert_eq!(loaded[0].id, kept.id);
+        assert!(loaded[0].is_read);
+        assert_eq!(record_count(&history), 1);
+    }
+
+    #[test]
+    fn compacts_once_stale_records_dominate() {
+        let dir = TempDir::new();
+        let (mut history, _) = NotificationHistory::open(&dir.0, unlimited()).unwrap();
+        let live = notification("live", Duration::ZERO);
+
+        for _ in 0..COMPACT_SLACK {
+            history.record_upsert(&live).unwrap();
+        }
+        history.compact_if_needed(std::iter::once(&live)).unwrap();
+        assert_eq!(record_count(&history), COMPACT_SLACK);
+
+        for _ in 0..3 {
+            history.record_upsert(&live).unwrap();
+        }
+        history.compact_if_needed(std::iter::once(&live)).unwrap();
+        assert_eq!(record_count(&history), 1);
+
+        // Appends keep going to the rewritten log.
+        history.record_remove(live.id).unwrap();
+        assert_eq!(record_count(&history), 2);
+    }
+
+    #[test]
+    fn retention_drops_old_and_excess_unpinned_notifications() {
+        let dir = TempDir::new();
+        let (mut history, _) = NotificationHistory::open(&dir.0, unlimited()).unwrap();
+        let day = Duration::from_secs(24 * 60 * 60);
+        let mut pinned = notification("pinned", 3 * day);
+        pinned.toggle_pin();
+        let notifications = [
+            pinned,
+            notification("expired", 2 * day),
+            notification("excess", Duration::from_secs(30)),
+            notification("older", Duration::from_secs(20)),
+            notification("newest", Duration::from_secs(10)),
+        ];
+        for notification in &notifications {
+            history.record_upsert(notification).unwrap();
+        }
+        drop(history);
+
+        let retention = RetentionPolicy::new(Some(day), Some(2));
+        let (history, loaded) = NotificationHistory::open(&dir.0, retention).unwrap();
+        let messages: Vec<&str> = loaded.iter().map(|n| n.message.as_str()).collect();
+        assert_eq!(messages, ["pinned", "older", "newest"]);
+        assert_eq!(record_count(&history), 3);
+    }
+}
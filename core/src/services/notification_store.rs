@@ -0,0 +1,376 @@
+use super::{NotificationHistory, RetentionPolicy};
+use crate::models::{Notification, NotificationType};
+use crate::CoreError;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// Criteria for listing notifications. `None` fields match anything.
+#[derive(Debug, Clone, Default)]
+pub struct NotificationFilter {
+    pub session_id: Option<Uuid>,
+    pub notification_type: Option<NotificationType>,
+    pub is_read: Option<bool>,
+    pub is_pinned: Option<bool>,
+}
+
+impl NotificationFilter {
+    pub fn matches(&self, notification: &Notification) -> bool {
+        self.session_id
+            .is_none_or(|id| notification.session_id == id)
+            && self
+                .notification_type
+                .is_none_or(|t| notification.notification_type == t)
+            && self.is_read.is_none_or(|read| notification.is_read == read)
+            && self
+                .is_pinned
+                .is_none_or(|pinned| notification.is_pinned == pinned)
+    }
+}
+
+/// Notifications kept in memory, optionally mirrored to a history log, and
+/// pruned by a retention policy either way.
+#[derive(Default)]
+pub struct NotificationStore {
+    notifications: HashMap<Uuid, Notification>,
+    history: Option<NotificationHistory>,
+    retention: RetentionPolicy,
+}
+
+impl NotificationStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// An in-memory store that keeps what `retention` allows.
+    pub fn with_retention(retention: RetentionPolicy) -> Self {
+        Self {
+            retention,
+            ..Self::default()
+        }
+    }
+
+    /// A store seeded with `loaded` that writes every change to `history`
+    /// and keeps what the history's retention policy allows.
+    pub fn with_history(history: NotificationHistory, loaded: Vec<Notification>) -> Self {
+        Self {
+            notifications: loaded.into_iter().map(|n| (n.id, n)).collect(),
+            retention: history.retention(),
+            history: Some(history),
+        }
+    }
+
+    pub fn is_persistent(&self) -> bool {
+        self.history.is_some()
+    }
+
+    pub fn insert(&mut self, notification: Notification) {
+        self.persist(|h| h.record_upsert(&notification));
+        self.notifications.insert(notification.id, notification);
+        self.apply_retention();
+    }
+
+    pub fn get(&self, notification_id: Uuid) -> Option<&Notification> {
+        self.notifications.get(&notification_id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.notifications.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.notifications.is_empty()
+    }
+
+    /// Matching notifications, oldest first.
+    pub fn list(&self, filter: &NotificationFilter) -> Vec<Notification> {
+        let mut list: Vec<_> = self
+            .notifications
+            .values()
+            .filter(|n| filter.matches(n))
+            .cloned()
+            .collect();
+        list.sort_by_key(|n| n.created_at);
+        list
+    }
+
+    pub fn mark_read(&mut self, notification_id: Uuid) -> Result<&Notification, CoreError> {
+        self.update(notification_id, Notification::mark_as_read)
+    }
+
+    /// Marks every notification of a session as read and returns how many
+    /// were unread.
+    pub fn mark_session_read(&mut self, session_id: Uuid) -> usize {
+        let mut marked = 0;
+        for notification in self
+            .notifications
+            .values_mut()
+            .filter(|n| n.session_id == session_id && !n.is_read)
+        {
+            notification.mark_as_read();
+            marked += 1;
+            if let Some(history) = &mut self.history {
+                log_failure(history.record_upsert(notification));
+            }
+        }
+        marked
+    }
+
+    pub fn toggle_pin(&mut self, notification_id: Uuid) -> Result<&Notification, CoreError> {
+        self.update(notification_id, Notification::toggle_pin)
+    }
+
+    /// Removes a notification. Pinned notifications must be unpinned first.
+    pub fn dismiss(&mut self, notification_id: Uuid) -> Result<Notification, CoreError> {
+        let notification = self.get_mut(notification_id)?;
+        if notification.is_pinned {
+            return Err(CoreError::NotificationPinned(notification_id));
+        }
+        let notification = self
+            .notifications
+            .remove(&notification_id)
+            .expect("notification exists");
+        self.persist(|h| h.record_remove(notification_id));
+        self.compact();
+        Ok(notification)
+    }
+
+    /// Removes a session's unpinned notifications and returns how many went.
+    pub fn clear_session(&mut self, session_id: Uuid) -> usize {
+        let ids: Vec<Uuid> = self
+            .notifications
+            .values()
+            .filter(|n| n.session_id == session_id && !n.is_pinned)
+            .map(|n| n.id)
+            .collect();
+        self.remove_all(&ids)
+    }
+
+    pub fn unread_count(&self) -> usize {
+        self.notifications.values().filter(|n| !n.is_read).count()
+    }
+
+    pub fn has_unread(&self, session_id: Uuid) -> bool {
+        self.notifications
+            .values()
+            .any(|n| n.session_id == session_id && !n.is_read)
+    }
+
+    fn get_mut(&mut self, notification_id: Uuid) -> Result<&mut Notification, CoreError> {
+        self.notifications
+            .get_mut(&notification_id)
+            .ok_or(CoreError::NotificationNotFound(notification_id))
+    }
+
+    fn update(
+        &mut self,
+        notification_id: Uuid,
+        change: impl FnOnce(&mut Notification),
+    ) -> Result<&Notification, CoreError> {
+        let notification = self
+            .notifications
+            .get_mut(&notification_id)
+            .ok_or(CoreError::NotificationNotFound(notification_id))?;
+        change(notification);
+        if let Some(history) = &mut self.history {
+            log_failure(history.record_upsert(notification));
+        }
+        Ok(notification)
+    }
+
+    fn remove_all(&mut self, ids: &[Uuid]) -> usize {
+        let mut removed = 0;
+        for id in ids {
+            if self.notifications.remove(id).is_some() {
+                removed += 1;
+                self.persist(|h| h.record_remove(*id));
+            }
+        }
+        self.compact();
+        removed
+    }
+
+    /// Drops notifications the retention policy no longer keeps.
+    fn apply_retention(&mut self) {
+        let expired = self.retention.expired(self.notifications.values());
+        self.remove_all(&expired);
+    }
+
+    fn compact(&mut self) {
+        if let Some(history) = &mut self.history {
+            log_failure(history.compact_if_needed(self.notifications.values()));
+        }
+    }
+
+    fn persist(&mut self, write: impl FnOnce(&mut NotificationHistory) -> Result<(), CoreError>) {
+        if let Some(history) = &mut self.history {
+            log_failure(write(history));
+        }
+    }
+}
+
+impl std::fmt::Debug for NotificationStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NotificationStore")
+            .field("notifications", &self.notifications)
+            .field("history", &self.history.as_ref().map(|h| h.path()))
+            .finish()
+    }
+}
+
+/// The in-memory store stays authoritative when the log cannot be written.
+fn log_failure(result: Result<(), CoreError>) {
+    if let Err(e) = result {
+        log::warn!("Failed to write notification history: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, SystemTime};
+
+    fn notification(session_id: Uuid, notification_type: NotificationType) -> Notification {
+        Notification::new(
+            session_id,
+            notification_type,
+            format!("{:?}", notification_type),
+            String::new(),
+        )
+    }
+
+    fn filter() -> NotificationFilter {
+        NotificationFilter::default()
+    }
+
+    #[test]
+    fn filters_by_session_type_read_and_pinned_state() {
+        let (first, second) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut store = NotificationStore::new();
+        let question = notification(first, NotificationType::Question);
+        let error = notification(first, NotificationType::Error);
+        let completion = notification(second, NotificationType::Completion);
+        for n in [&question, &error, &completion] {
+            store.insert(n.clone());
+        }
+        store.mark_read(error.id).unwrap();
+        assert!(store.toggle_pin(completion.id).unwrap().is_pinned);
+
+        let ids = |filter: NotificationFilter| -> Vec<Uuid> {
+            store.list(&filter).iter().map(|n| n.id).collect()
+        };
+        assert_eq!(ids(filter()), [question.id, error.id, completion.id]);
+        let by_session = NotificationFilter {
+            session_id: Some(first),
+            ..filter()
+        };
+        assert_eq!(ids(by_session), [question.id, error.id]);
+        let by_type = NotificationFilter {
+            notification_type: Some(NotificationType::Error),
+            ..filter()
+        };
+        assert_eq!(ids(by_type), [error.id]);
+        let unread = NotificationFilter {
+            is_read: Some(false),
+            ..filter()
+        };
+        assert_eq!(ids(unread), [question.id, completion.id]);
+        let pinned = NotificationFilter {
+            is_pinned: Some(true),
+            ..filter()
+        };
+        assert_eq!(ids(pinned), [completion.id]);
+        let unread_in_second = NotificationFilter {
+            session_id: Some(second),
+            is_read: Some(false),
+            is_pinned: Some(false),
+            ..filter()
+        };
+        assert!(ids(unread_in_second).is_empty());
+    }
+
+    #[test]
+    fn pinned_notifications_survive_dismiss_and_clear_until_unpinned() {
+        let (session_id, other) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut store = NotificationStore::new();
+        let pinned = notification(session_id, NotificationType::Question);
+        let loose = notification(session_id, NotificationType::Error);
+        let elsewhere = notification(other, NotificationType::Error);
+        for n in [&pinned, &loose, &elsewhere] {
+            store.insert(n.clone());
+        }
+
+        let toggled = store.toggle_pin(pinned.id).unwrap();
+        assert!(toggled.is_pinned && toggled.pinned_at.is_some());
+        assert!(matches!(
+            store.dismiss(pinned.id),
+            Err(CoreError::NotificationPinned(id)) if id == pinned.id
+        ));
+
+        assert_eq!(store.clear_session(session_id), 1);
+        assert!(store.get(loose.id).is_none());
+        assert!(store.get(elsewhere.id).is_some());
+
+        let toggled = store.toggle_pin(pinned.id).unwrap();
+        assert!(!toggled.is_pinned && toggled.pinned_at.is_none());
+        assert_eq!(store.dismiss(pinned.id).unwrap().id, pinned.id);
+        assert!(matches!(
+            store.dismiss(pinned.id),
+            Err(CoreError::NotificationNotFound(_))
+        ));
+        assert_eq!(store.len(), 1);
+    }
+
+    #[test]
+    fn unread_count_follows_reads_and_dismissals() {
+        let (session_id, other) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut store = NotificationStore::new();
+        let notifications: Vec<Notification> = [session_id, session_id, session_id, other]
+            .into_iter()
+            .map(|id| notification(id, NotificationType::Question))
+            .collect();
+        for n in &notifications {
+            store.insert(n.clone());
+        }
+        assert_eq!(store.unread_count(), 4);
+
+        store.mark_read(notifications[0].id).unwrap();
+        assert_eq!(store.unread_count(), 3);
+        store.dismiss(notifications[1].id).unwrap();
+        assert_eq!(store.unread_count(), 2);
+        // Already read, so dismissing it leaves the count alone.
+        store.dismiss(notifications[0].id).unwrap();
+        assert_eq!(store.unread_count(), 2);
+
+        assert_eq!(store.mark_session_read(session_id), 1);
+        assert!(!store.has_unread(session_id));
+        assert!(store.has_unread(other));
+        assert_eq!(store.unread_count(), 1);
+    }
+
+    #[test]
+    fn in_memory_store_applies_retention() {
+        let session_id = Uuid::new_v4();
+        let mut store = NotificationStore::with_retention(RetentionPolicy::new(
+            Some(Duration::from_secs(60)),
+            Some(2),
+        ));
+        let mut stale = notification(session_id, NotificationType::Error);
+        stale.created_at = SystemTime::now() - Duration::from_secs(120);
+        store.insert(stale);
+        assert!(store.is_empty());
+
+        let mut pinned = notification(session_id, NotificationType::Custom);
+        pinned.toggle_pin();
+        pinned.created_at = SystemTime::now() - Duration::from_secs(120);
+        store.insert(pinned);
+        for _ in 0..3 {
+            store.insert(notification(session_id, NotificationType::Question));
+        }
+        assert_eq!(store.len(), 3);
+        let unpinned = NotificationFilter {
+            is_pinned: Some(false),
+            ..filter()
+        };
+        assert_eq!(store.list(&unpinned).len(), 2);
+    }
+}
@@ -1,7 +1,12 @@
-use regex::Regex;
+use super::multi_matcher::{MatchPattern, MultiMatcher};
+use crate::CoreError;
+use regex::{Regex, RegexBuilder};
 use std::collections::HashMap;
+use std::fmt;
+use std::ops::Range;
 use uuid::Uuid;
 
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct CustomPattern {
     pub id: Uuid,
     pub name: String,
@@ -11,9 +16,96 @@ pub struct CustomPattern {
     pub auto_pin: bool,
 }
 
+impl CustomPattern {
+    /// Checks that the pattern compiles. Keywords always do.
+    pub fn validate(&self) -> Result<(), PatternError> {
+        compile(self).map(drop)
+    }
+}
+
+/// Why a regex does not compile, for showing next to the pattern as typed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatternError {
+    pub message: String,
+    /// UTF-8 byte range of the offending part of the pattern, when the
+    /// problem is a syntax error rather than, say, the compiled size.
+    pub span: Option<Range<usize>>,
+}
+
+impl PatternError {
+    fn from_regex(pattern: &str, error: regex::Error) -> Self {
+        // `regex::Error` only renders the span into its message, so parse
+        // again to get it as data.
+        let located = match regex_syntax::Parser::new().parse(pattern) {
+            Err(regex_syntax::Error::Parse(e)) => Some((e.kind().to_string(), *e.span())),
+            Err(regex_syntax::Error::Translate(e)) => Some((e.kind().to_string(), *e.span())),
+            _ => None,
+        };
+        match located {
+            Some((message, span)) => Self {
+                message,
+                span: Some(span.start.offset..span.end.offset),
+            },
+            None => Self {
+                message: error.to_string(),
+                span: None,
+            },
+        }
+    }
+}
+
+impl fmt::Display for PatternError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)?;
+        if let Some(span) = &self.span {
+            write!(f, " at {}..{}", span.start, span.end)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for PatternError {}
+
+/// Checks a regex the way `PatternMatcher` will compile it.
+pub fn validate_regex(pattern: &str) -> Result<(), PatternError> {
+    Regex::new(pattern)
+        .map(drop)
+        .map_err(|e| PatternError::from_regex(pattern, e))
+}
+
+/// A named capture group's text and byte range within the matched text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NamedCapture {
+    pub name: String,
+    pub range: Range<usize>,
+    pub text: String,
+}
+
+/// One occurrence of a pattern, by byte range into the matched text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatternMatch<'a> {
+    pub pattern: &'a CustomPattern,
+    pub range: Range<usize>,
+    pub captures: Vec<NamedCapture>,
+}
+
+impl PatternMatch<'_> {
+    pub fn capture(&self, name: &str) -> Option<&str> {
+        self.captures
+            .iter()
+            .find(|c| c.name == name)
+            .map(|c| c.text.as_str())
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct PatternMatcher {
     patterns: Vec<CustomPattern>,
     regex_cache: HashMap<Uuid, Regex>,
+    /// Finds the enabled, valid patterns present in a text in one pass.
+    index: MultiMatcher,
+    /// Position in `patterns` of each pattern `index` reports.
+    indexed: Vec<usize>,
 }
 
 impl PatternMatcher {
@@ -21,60 +113,249 @@ impl PatternMatcher {
         Self {
             patterns: Vec::new(),
             regex_cache: HashMap::new(),
+            index: MultiMatcher::new(&[]),
+            indexed: Vec::new(),
         }
     }
 
-    pub fn add_pattern(&mut self, pattern: CustomPattern) {
-        if pattern.is_regex {
-            if let Ok(regex) = Regex::new(&pattern.pattern) {
-                self.regex_cache.insert(pattern.id, regex);
-            }
-        }
+    /// Adds a pattern, or rejects it if its regex does not compile.
+    pub fn add_pattern(&mut self, pattern: CustomPattern) -> Result<(), CoreError> {
+        let regex = compile(&pattern)?;
+        self.regex_cache.insert(pattern.id, regex);
         self.patterns.push(pattern);
+        self.rebuild_index();
+        Ok(())
     }
 
     pub fn remove_pattern(&mut self, pattern_id: Uuid) {
         self.patterns.retain(|p| p.id != pattern_id);
         self.regex_cache.remove(&pattern_id);
+        self.rebuild_index();
+    }
+
+    pub fn patterns(&self) -> &[CustomPattern] {
+        &self.patterns
     }
 
     pub fn match_text(&self, text: &str) -> Option<&CustomPattern> {
-        for pattern in &self.patterns {
-            if !pattern.is_enabled {
-                continue;
-            }
+        self.candidates(text).next()
+    }
 
-            let matched = if pattern.is_regex {
-                self.regex_cache
-                    .get(&pattern.id)
-                    .map(|r| r.is_match(text))
-                    .unwrap_or(false)
-            } else {
-                text.to_lowercase()
-                    .contains(&pattern.pattern.to_lowercase())
-            };
+    /// The first occurrence of the first enabled pattern that matches.
+    pub fn first_match(&self, text: &str) -> Option<PatternMatch<'_>> {
+        self.candidates(text).find_map(|pattern| {
+            let regex = self.regex_cache.get(&pattern.id)?;
+            let captures = regex.captures(text)?;
+            Some(pattern_match(pattern, regex, &captures))
+        })
+    }
 
-            if matched {
-                return Some(pattern);
-            }
+    /// Every occurrence of every enabled pattern, ordered by position and
+    /// then by pattern order. Occurrences of one pattern never overlap.
+    pub fn match_all(&self, text: &str) -> Vec<PatternMatch<'_>> {
+        let mut matches = Vec::new();
+        for pattern in self.candidates(text) {
+            let Some(regex) = self.regex_cache.get(&pattern.id) else {
+                continue;
+            };
+            matches.extend(
+                regex
+                    .captures_iter(text)
+                    .map(|captures| pattern_match(pattern, regex, &captures)),
+            );
         }
-        None
+        // Stable, so patterns keep their order at equal positions.
+        matches.sort_by_key(|m| m.range.start);
+        matches
     }
 
-    pub fn invalidate_cache(&mut self) {
+    /// Recompiles every pattern, returning the ones that no longer compile.
+    /// Those stay listed but never match.
+    pub fn invalidate_cache(&mut self) -> Vec<(Uuid, PatternError)> {
         self.regex_cache.clear();
+        let mut errors = Vec::new();
         for pattern in &self.patterns {
-            if pattern.is_regex {
-                if let Ok(regex) = Regex::new(&pattern.pattern) {
+            match compile(pattern) {
+                Ok(regex) => {
                     self.regex_cache.insert(pattern.id, regex);
                 }
+                Err(e) => errors.push((pattern.id, e)),
             }
         }
+        self.rebuild_index();
+        errors
+    }
+
+    /// Enabled patterns found in `text`, in pattern order, from one pass
+    /// over it rather than one per pattern.
+    fn candidates<'a>(&'a self, text: &str) -> impl Iterator<Item = &'a CustomPattern> {
+        let matched = self.index.matches(text);
+        self.indexed
+            .iter()
+            .zip(matched)
+            .filter(|(_, matched)| *matched)
+            .map(|(position, _)| &self.patterns[*position])
+    }
+
+    fn rebuild_index(&mut self) {
+        let (indexed, sources): (Vec<usize>, Vec<MatchPattern>) = self
+            .patterns
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| p.is_enabled && self.regex_cache.contains_key(&p.id))
+            .map(|(position, p)| {
+                let source = if p.is_regex {
+                    MatchPattern::Regex {
+                        pattern: &p.pattern,
+                        case_insensitive: false,
+                    }
+                } else {
+                    MatchPattern::Keyword {
+                        keyword: &p.pattern,
+                        case_insensitive: true,
+                    }
+                };
+                (position, source)
+            })
+            .unzip();
+        self.index = MultiMatcher::new(&sources);
+        self.indexed = indexed;
     }
 }
 
+fn pattern_match<'a>(
+    pattern: &'a CustomPattern,
+    regex: &Regex,
+    captures: &regex::Captures,
+) -> PatternMatch<'a> {
+    let named = regex
+        .capture_names()
+        .flatten()
+        .filter_map(|name| {
+            captures.name(name).map(|m| NamedCapture {
+                name: name.to_string(),
+                range: m.range(),
+                text: m.as_str().to_string(),
+            })
+        })
+        .collect();
+    PatternMatch {
+        pattern,
+        range: captures.get(0).expect("group 0 always matches").range(),
+        captures: named,
+    }
+}
+
+/// Keywords compile to an escaped, case-insensitive regex so `match_all`
+/// can report their positions.
+fn compile(pattern: &CustomPattern) -> Result<Regex, PatternError> {
+    let source = if pattern.is_regex {
+        pattern.pattern.clone()
+    } else {
+        regex::escape(&pattern.pattern)
+    };
+    RegexBuilder::new(&source)
+        .case_insensitive(!pattern.is_regex)
+        .build()
+        .map_err(|e| PatternError::from_regex(&source, e))
+}
+
 impl Default for PatternMatcher {
     fn default() -> Self {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pattern(name: &str, pattern: &str, is_regex: bool) -> CustomPattern {
+        CustomPattern {
+            id: Uuid::new_v4(),
+            name: name.to_string(),
+            pattern: pattern.to_string(),
+            is_regex,
+            is_enabled: true,
+            auto_pin: false,
+        }
+    }
+
+    #[test]
+    fn match_all_reports_ranges_and_captures_in_text_then_pattern_order() {
+        let mut matcher = PatternMatcher::new();
+        matcher
+            .add_pattern(pattern("full", r"disk (?P<what>\w+)", true))
+            .unwrap();
+        matcher.add_pattern(pattern("disk", "DISK", false)).unwrap();
+        matcher
+            .add_pattern(pattern("exit", r"exit (?P<code>\d)", true))
+            .unwrap();
+        matcher
+            .add_pattern(pattern("error", "error", false))
+            .unwrap();
+        matcher
+            .add_pattern(CustomPattern {
+                is_enabled: false,
+                ..pattern("time", r"(?P<hh>\d+):(?P<mm>\d+)", true)
+            })
+            .unwrap();
+
+        let text = "disk full: exit 2, exit 3; Error at 10:04";
+        let matches = matcher.match_all(text);
+        let found: Vec<(&str, Range<usize>)> = matches
+            .iter()
+            .map(|m| (m.pattern.name.as_str(), m.range.clone()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("full", 0..9),
+                ("disk", 0..4),
+                ("exit", 11..17),
+                ("exit", 19..25),
+                ("error", 27..32),
+            ]
+        );
+
+        assert_eq!(
+            matches[0].captures,
+            [NamedCapture {
+                name: "what".to_string(),
+                range: 5..9,
+                text: "full".to_string(),
+            }]
+        );
+        assert!(matches[1].captures.is_empty());
+        assert_eq!(matches[2].capture("code"), Some("2"));
+        assert_eq!(matches[3].capture("code"), Some("3"));
+        assert_eq!(matches[3].captures[0].range, 24..25);
+        assert_eq!(&text[matches[4].range.clone()], "Error");
+    }
+
+    #[test]
+    fn bad_regex_is_reported_with_its_span_and_rejected() {
+        let expected = PatternError {
+            message: "unclosed group".to_string(),
+            span: Some(1..2),
+        };
+        assert_eq!(validate_regex("a(b"), Err(expected.clone()));
+        assert_eq!(expected.to_string(), "unclosed group at 1..2");
+        assert_eq!(validate_regex("a(b)"), Ok(()));
+
+        let mut matcher = PatternMatcher::new();
+        let bad = pattern("bad", "a(b", true);
+        assert_eq!(bad.validate(), Err(expected.clone()));
+        assert!(matches!(
+            matcher.add_pattern(bad),
+            Err(CoreError::InvalidPattern(e)) if e == expected
+        ));
+        assert!(matcher.patterns().is_empty());
+
+        // As a keyword the same text is escaped, so it always compiles.
+        matcher
+            .add_pattern(pattern("keyword", "a(b", false))
+            .unwrap();
+        assert_eq!(matcher.match_all("xA(By").len(), 1);
+    }
+}
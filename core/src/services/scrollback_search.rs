@@ -0,0 +1,268 @@
+use crate::terminal::{Cell, ScrollbackLine, TerminalEmulator};
+use crate::CoreError;
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SearchQuery {
+    pub text: String,
+    pub is_regex: bool,
+    pub case_insensitive: bool,
+    pub whole_word: bool,
+}
+
+impl SearchQuery {
+    pub fn new(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            ..Self::default()
+        }
+    }
+
+    fn compile(&self) -> Result<Regex, CoreError> {
+        let pattern = if self.is_regex {
+            Cow::Borrowed(self.text.as_str())
+        } else {
+            Cow::Owned(regex::escape(&self.text))
+        };
+        let pattern = if self.whole_word {
+            Cow::Owned(format!(r"\b(?:{})\b", pattern))
+        } else {
+            pattern
+        };
+
+        RegexBuilder::new(&pattern)
+            .case_insensitive(self.case_insensitive)
+            .build()
+            .map_err(|e| CoreError::InvalidRegex(e.to_string()))
+    }
+}
+
+/// A match in a session's output.
+///
+/// `line` uses the scrollback's absolute numbering, continued through the
+/// visible screen, so the top screen row is `scrollback.end_index()`.
+/// Columns are cell columns on that row, so a wide char before the match
+/// counts twice; `end_col` runs past the row width when the match continues
+/// onto an auto-wrapped row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SearchHit {
+    pub session_id: Uuid,
+    pub line: u64,
+    pub start_col: usize,
+    pub end_col: usize,
+}
+
+/// Hits in output order with a cursor for next/previous navigation.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SearchResults {
+    hits: Vec<SearchHit>,
+    current: Option<usize>,
+}
+
+impl SearchResults {
+    pub fn new(hits: Vec<SearchHit>) -> Self {
+        Self {
+            hits,
+            current: None,
+        }
+    }
+
+    pub fn hits(&self) -> &[SearchHit] {
+        &self.hits
+    }
+
+    pub fn len(&self) -> usize {
+        self.hits.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hits.is_empty()
+    }
+
+    pub fn current(&self) -> Option<&SearchHit> {
+        self.current.and_then(|i| self.hits.get(i))
+    }
+
+    pub fn current_index(&self) -> Option<usize> {
+        self.current
+    }
+
+    /// Moves to the next hit, wrapping to the first. Starts at the first hit.
+    pub fn next_hit(&mut self) -> Option<&SearchHit> {
+        if self.hits.is_empty() {
+            return None;
+        }
+        self.current = Some(match self.current {
+            Some(i) => (i + 1) % self.hits.len(),
+            None => 0,
+        });
+        self.current()
+    }
+
+    /// Moves to the previous hit, wrapping to the last. Starts at the newest
+    /// hit, since searches usually run upward from the bottom of the output.
+    pub fn previous_hit(&mut self) -> Option<&SearchHit> {
+        if self.hits.is_empty() {
+            return None;
+        }
+        self.current = Some(match self.current {
+            Some(0) | None => self.hits.len() - 1,
+            Some(i) => i - 1,
+        });
+        self.current()
+    }
+
+    pub(crate) fn extend(&mut self, other: SearchResults) {
+        self.hits.extend(other.hits);
+    }
+}
+
+pub struct ScrollbackSearch {
+    regex: Regex,
+}
+
+impl ScrollbackSearch {
+    pub fn new(query: &SearchQuery) -> Result<Self, CoreError> {
+        Ok(Self {
+            regex: query.compile()?,
+        })
+    }
+
+    /// Searches the scrollback and visible screen. Rows joined by auto-wrap
+    /// are searched as one line so matches can span the wrap.
+    pub fn search(&self, session_id: Uuid, screen: &TerminalEmulator) -> SearchResults {
+        let scrollback = screen.scrollback();
+        // Scrollback keeps only text, whose chars map one to one onto the
+        // cells they were printed into.
+        let scrolled = scrollback
+            .range(scrollback.first_index(), scrollback.len())
+            .map(|line| {
+                let widths: Vec<usize> = line.text.chars().map(Cell::width_of).collect();
+                (Cow::Borrowed(line), widths)
+            });
+        let visible = screen.visible_rows().iter().map(|row| {
+            let widths: Vec<usize> = row
+                .cells
+                .iter()
+                .filter(|cell| !cell.is_wide_spacer())
+                .map(|cell| cell.width as usize)
+                .collect();
+            (Cow::Owned(ScrollbackLine::from_row(row)), widths)
+        });
+        let lines = scrolled.chain(visible).zip(scrollback.first_index()..);
+
+        let mut hits = Vec::new();
+        let mut text = String::new();
+        // Cell offset at which each row of the current line starts.
+        let mut row_starts: Vec<(u64, usize)> = Vec::new();
+        // Byte offset and cell offset of each char in `text`, then of its end.
+        let mut columns: Vec<(usize, usize)> = Vec::new();
+        let mut cells = 0;
+
+        for ((line, widths), index) in lines {
+            row_starts.push((index, cells));
+            for (c, width) in line.text.chars().zip(widths) {
+                columns.push((text.len(), cells));
+                text.push(c);
+                cells += width;
+            }
+
+            if !line.wrapped {
+                columns.push((text.len(), cells));
+                self.search_line(session_id, &text, &row_starts, &columns, &mut hits);
+                text.clear();
+                row_starts.clear();
+                columns.clear();
+                cells = 0;
+            }
+        }
+        if !text.is_empty() {
+            columns.push((text.len(), cells));
+            self.search_line(session_id, &text, &row_starts, &columns, &mut hits);
+        }
+
+        SearchResults::new(hits)
+    }
+
+    fn search_line(
+        &self,
+        session_id: Uuid,
+        text: &str,
+        row_starts: &[(u64, usize)],
+        columns: &[(usize, usize)],
+        hits: &mut Vec<SearchHit>,
+    ) {
+        let column = |byte: usize| columns[columns.partition_point(|&(b, _)| b < byte)].1;
+
+        for m in self.regex.find_iter(text) {
+            if m.is_empty() {
+                continue;
+            }
+            let (start, end) = (column(m.start()), column(m.end()));
+            let (line, row_start) = row_starts
+                .iter()
+                .rev()
+                .find(|(_, offset)| *offset <= start)
+                .copied()
+                .unwrap_or(row_starts[0]);
+            hits.push(SearchHit {
+                session_id,
+                line,
+                start_col: start - row_start,
+                end_col: end - row_start,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hits(cols: u16, rows: u16, output: &str, query: &str) -> Vec<(u64, usize, usize)> {
+        let mut screen = TerminalEmulator::new(cols, rows);
+        screen.process(output.as_bytes());
+        ScrollbackSearch::new(&SearchQuery::new(query))
+            .unwrap()
+            .search(Uuid::nil(), &screen)
+            .hits()
+            .iter()
+            .map(|hit| (hit.line, hit.start_col, hit.end_col))
+            .collect()
+    }
+
+    #[test]
+    fn columns_count_cells_not_chars() {
+        assert_eq!(hits(10, 2, "中文 abc", "abc"), [(0, 5, 8)]);
+        assert_eq!(hits(10, 2, "ab中文", "中文"), [(0, 2, 6)]);
+        // Same numbering once the line has scrolled off the screen.
+        assert_eq!(hits(10, 2, "中x\r\n1\r\n2", "x"), [(0, 2, 3)]);
+    }
+
+    #[test]
+    fn matches_span_auto_wrapped_rows() {
+        assert_eq!(hits(4, 3, "ab中cd", "中c"), [(0, 2, 5)]);
+        // A wide char that does not fit leaves a blank cell before the wrap.
+        assert_eq!(hits(5, 3, "abcd中x", "x"), [(1, 2, 3)]);
+        assert_eq!(hits(5, 3, "abcd中x", "d 中"), [(0, 3, 7)]);
+    }
+
+    #[test]
+    fn navigation_wraps_in_both_directions() {
+        let hit = |line| SearchHit {
+            session_id: Uuid::nil(),
+            line,
+            start_col: 0,
+            end_col: 1,
+        };
+        let mut results = SearchResults::new(vec![hit(0), hit(1), hit(2)]);
+        assert_eq!(results.previous_hit().unwrap().line, 2);
+        assert_eq!(results.next_hit().unwrap().line, 0);
+        assert_eq!(results.previous_hit().unwrap().line, 2);
+        assert_eq!(results.current_index(), Some(2));
+        assert!(SearchResults::default().next_hit().is_none());
+    }
+}
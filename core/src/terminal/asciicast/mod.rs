@@ -0,0 +1,86 @@
+mod player;
+mod recorder;
+
+pub use player::*;
+pub use recorder::*;
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+pub const ASCIICAST_VERSION: u8 = 2;
+
+/// First line of an asciicast v2 file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AsciicastHeader {
+    pub version: u8,
+    pub width: u16,
+    pub height: u16,
+    /// Unix time the recording started, in seconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idle_time_limit: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub env: HashMap<String, String>,
+}
+
+impl AsciicastHeader {
+    pub fn new(width: u16, height: u16) -> Self {
+        Self {
+            version: ASCIICAST_VERSION,
+            width,
+            height,
+            timestamp: None,
+            idle_time_limit: None,
+            title: None,
+            env: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EventKind {
+    #[serde(rename = "o")]
+    Output,
+    #[serde(rename = "i")]
+    Input,
+    #[serde(rename = "r")]
+    Resize,
+    #[serde(rename = "m")]
+    Marker,
+}
+
+/// One event line, stored on disk as `[time, kind, data]`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "(f64, EventKind, String)", into = "(f64, EventKind, String)")]
+pub struct AsciicastEvent {
+    /// Seconds since the start of the recording.
+    pub time: f64,
+    pub kind: EventKind,
+    pub data: String,
+}
+
+impl AsciicastEvent {
+    /// Parses the `COLSxROWS` payload of a resize event.
+    pub fn resize_size(&self) -> Option<(u16, u16)> {
+        if self.kind != EventKind::Resize {
+            return None;
+        }
+        let (cols, rows) = self.data.split_once('x')?;
+        Some((cols.trim().parse().ok()?, rows.trim().parse().ok()?))
+    }
+}
+
+impl From<(f64, EventKind, String)> for AsciicastEvent {
+    fn from((time, kind, data): (f64, EventKind, String)) -> Self {
+        Self { time, kind, data }
+    }
+}
+
+impl From<AsciicastEvent> for (f64, EventKind, String) {
+    fn from(event: AsciicastEvent) -> Self {
+        (event.time, event.kind, event.data)
+    }
+}
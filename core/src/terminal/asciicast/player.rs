@@ -0,0 +1,412 @@
+use super::{AsciicastEvent, AsciicastHeader, EventKind};
+use crate::CoreError;
+use crossbeam_channel::{Receiver, RecvTimeoutError, Sender};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Clears the screen and scrollback before a seek redraws from the start.
+const RESET_SEQUENCE: &[u8] = b"\x1bc\x1b[3J";
+
+/// A parsed asciicast v2 file.
+#[derive(Debug, Clone)]
+pub struct Asciicast {
+    pub header: AsciicastHeader,
+    pub events: Vec<AsciicastEvent>,
+}
+
+impl Asciicast {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, CoreError> {
+        let file = std::fs::File::open(path)?;
+        Self::parse(BufReader::new(file))
+    }
+
+    pub fn parse(reader: impl BufRead) -> Result<Self, CoreError> {
+        let mut lines = reader.lines().enumerate();
+
+        let header: AsciicastHeader = loop {
+            let Some((index, line)) = lines.next() else {
+                return Err(CoreError::InvalidCast("missing header".to_string()));
+            };
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            break serde_json::from_str(&line).map_err(|e| invalid_line(index, e))?;
+        };
+        if header.version != super::ASCIICAST_VERSION {
+            return Err(CoreError::InvalidCast(format!(
+                "unsupported version {}",
+                header.version
+            )));
+        }
+
+        let mut events = Vec::new();
+        for (index, line) in lines {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            events.push(serde_json::from_str(&line).map_err(|e| invalid_line(index, e))?);
+        }
+
+        Ok(Self { header, events })
+    }
+}
+
+fn invalid_line(index: usize, error: serde_json::Error) -> CoreError {
+    CoreError::InvalidCast(format!("line {}: {}", index + 1, error))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PlaybackOptions {
+    /// Multiplier on recorded time; 2.0 plays twice as fast.
+    pub speed: f64,
+    /// Longest pause kept between events, in seconds. `None` falls back to
+    /// the file's `idle_time_limit`.
+    pub idle_time_limit: Option<f64>,
+}
+
+impl Default for PlaybackOptions {
+    fn default() -> Self {
+        Self {
+            speed: 1.0,
+            idle_time_limit: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PlaybackCommand {
+    Pause,
+    Resume,
+    /// Jumps to a position in seconds of (idle-capped) playback time.
+    Seek(f64),
+    SetSpeed(f64),
+    Stop,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct PlaybackStatus {
+    pub position: f64,
+    pub duration: f64,
+    pub speed: f64,
+    pub paused: bool,
+    pub finished: bool,
+}
+
+pub enum PlaybackEvent<'a> {
+    Output(&'a [u8]),
+    /// Output replayed at once to rebuild the screen after a seek.
+    Restore(&'a [u8]),
+    Resize(u16, u16),
+    Finished,
+}
+
+/// Controls a playback running on another thread.
+#[derive(Clone)]
+pub struct PlaybackHandle {
+    commands: Sender<PlaybackCommand>,
+    status: Arc<Mutex<PlaybackStatus>>,
+}
+
+impl PlaybackHandle {
+    pub fn send(&self, command: PlaybackCommand) {
+        // The player has already stopped if the channel is closed.
+        let _ = self.commands.send(command);
+    }
+
+    pub fn status(&self) -> PlaybackStatus {
+        *self.status.lock()
+    }
+}
+
+/// Event timeline with idle gaps capped, driven in real time by `run`.
+pub struct Playback {
+    width: u16,
+    height: u16,
+    events: Vec<AsciicastEvent>,
+    speed: f64,
+    status: Arc<Mutex<PlaybackStatus>>,
+}
+
+impl Playback {
+    pub fn new(
+        cast: Asciicast,
+        options: &PlaybackOptions,
+    ) -> (Self, PlaybackHandle, Receiver<PlaybackCommand>) {
+        let idle_limit = options
+            .idle_time_limit
+            .or(cast.header.idle_time_limit)
+            .filter(|limit| *limit > 0.0);
+
+        let mut events = cast.events;
+        let mut previous = 0.0;
+        let mut elapsed = 0.0;
+        for event in &mut events {
+            let gap = (event.time - previous).max(0.0);
+            previous = event.time;
+            elapsed += idle_limit.map_or(gap, |limit| gap.min(limit));
+            event.time = elapsed;
+        }
+
+        let speed = valid_speed(options.speed).unwrap_or(1.0);
+        let status = Arc::new(Mutex::new(PlaybackStatus {
+            position: 0.0,
+            duration: elapsed,
+            speed,
+            paused: false,
+            finished: false,
+        }));
+        let (commands, receiver) = crossbeam_channel::unbounded();
+
+        let playback = Self {
+            width: cast.header.width,
+            height: cast.header.height,
+            events,
+            speed,
+            status: status.clone(),
+        };
+        (playback, PlaybackHandle { commands, status }, receiver)
+    }
+
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+
+    pub fn height(&self) -> u16 {
+        self.height
+    }
+
+    /// Plays events as they come due until a `Stop` command or the handle is
+    /// dropped. At the end it waits for a seek back. Blocks the calling thread.
+    pub fn run(mut self, commands: Receiver<PlaybackCommand>, mut emit: impl FnMut(PlaybackEvent)) {
+        let mut next = 0;
+        let mut paused = false;
+        let mut finished = false;
+        // Playback time `origin_time` corresponds to wall-clock `origin`.
+        let mut origin = Instant::now();
+        let mut origin_time = 0.0;
+
+        loop {
+            if next >= self.events.len() && !finished {
+                finished = true;
+                self.update_status(|s| {
+                    s.position = s.duration;
+                    s.finished = true;
+                });
+                emit(PlaybackEvent::Finished);
+            }
+
+            let command = match self.events.get(next) {
+                Some(event) if !paused => {
+                    let wait = ((event.time - origin_time) / self.speed).max(0.0);
+                    match commands.recv_deadline(origin + Duration::from_secs_f64(wait)) {
+                        Ok(command) => Some(command),
+                        Err(RecvTimeoutError::Timeout) => None,
+                        Err(RecvTimeoutError::Disconnected) => return,
+                    }
+                }
+                _ => match commands.recv() {
+                    Ok(command) => Some(command),
+                    Err(_) => return,
+                },
+            };
+
+            match command {
+                None => {
+                    let event = &self.events[next];
+                    let time = event.time;
+                    match event.kind {
+                        EventKind::Output => emit(PlaybackEvent::Output(event.data.as_bytes())),
+                        EventKind::Resize => {
+                            if let Some((cols, rows)) = event.resize_size() {
+                                emit(PlaybackEvent::Resize(cols, rows));
+                            }
+                        }
+                        EventKind::Input | EventKind::Marker => {}
+                    }
+                    next += 1;
+                    self.update_status(|s| s.position = time);
+                }
+                Some(PlaybackCommand::Pause) if !paused => {
+                    origin_time = self.position(origin, origin_time, next);
+                    paused = true;
+                    self.update_status(|s| {
+                        s.position = origin_time;
+                        s.paused = true;
+                    });
+                }
+                Some(PlaybackCommand::Resume) if paused => {
+                    origin = Instant::now();
+                    paused = false;
+                    self.update_status(|s| s.paused = false);
+                }
+                Some(PlaybackCommand::Seek(target)) => {
+                    let target = target.clamp(0.0, self.duration());
+                    next = self.restore_until(target, &mut emit);
+                    finished = false;
+                    origin = Instant::now();
+                    origin_time = target;
+                    self.update_status(|s| {
+                        s.position = target;
+                        s.finished = false;
+                    });
+                }
+                Some(PlaybackCommand::SetSpeed(speed)) => {
+                    if let Some(speed) = valid_speed(speed) {
+                        if !paused {
+                            origin_time = self.position(origin, origin_time, next);
+                            origin = Instant::now();
+                        }
+                        self.speed = speed;
+                        self.update_status(|s| s.speed = speed);
+                    }
+                }
+                Some(PlaybackCommand::Stop) => return,
+                Some(_) => {}
+            }
+        }
+    }
+
+    /// Current playback time, never past the event that is about to play.
+    fn position(&self, origin: Instant, origin_time: f64, next: usize) -> f64 {
+        let elapsed = origin_time + origin.elapsed().as_secs_f64() * self.speed;
+        elapsed.min(self.next_time(next))
+    }
+
+    fn duration(&self) -> f64 {
+        self.events.last().map_or(0.0, |e| e.time)
+    }
+
+    fn next_time(&self, next: usize) -> f64 {
+        self.events.get(next).map_or(self.duration(), |e| e.time)
+    }
+
+    /// Redraws the state at `target` from scratch and returns the index of
+    /// the first event after it.
+    fn restore_until(&self, target: f64, emit: &mut impl FnMut(PlaybackEvent)) -> usize {
+        let end = self.events.partition_point(|e| e.time <= target);
+        let past = &self.events[..end];
+
+        let (cols, rows) = past
+            .iter()
+            .rev()
+            .find_map(AsciicastEvent::resize_size)
+            .unwrap_or((self.width, self.height));
+        emit(PlaybackEvent::Resize(cols, rows));
+
+        let mut output = RESET_SEQUENCE.to_vec();
+        for event in past.iter().filter(|e| e.kind == EventKind::Output) {
+            output.extend_from_slice(event.data.as_bytes());
+        }
+        emit(PlaybackEvent::Restore(&output));
+        end
+    }
+
+    fn update_status(&self, update: impl FnOnce(&mut PlaybackStatus)) {
+        update(&mut self.status.lock());
+    }
+}
+
+fn valid_speed(speed: f64) -> Option<f64> {
+    (speed.is_finite() && speed > 0.0).then_some(speed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::thread;
+
+    #[derive(Debug, PartialEq)]
+    enum Emitted {
+        Output(Vec<u8>),
+        Restore(Vec<u8>),
+        Resize(u16, u16),
+        Finished,
+    }
+
+    fn cast(events: &[(f64, EventKind, &str)]) -> Asciicast {
+        Asciicast {
+            header: AsciicastHeader::new(80, 24),
+            events: events
+                .iter()
+                .map(|&(time, kind, data)| AsciicastEvent {
+                    time,
+                    kind,
+                    data: data.to_string(),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn idle_gaps_are_capped() {
+        let options = PlaybackOptions {
+            speed: 1.0,
+            idle_time_limit: Some(1.0),
+        };
+        let events = [
+            (0.5, EventKind::Output, "a"),
+            (10.0, EventKind::Output, "b"),
+            (10.5, EventKind::Output, "c"),
+        ];
+        let (playback, handle, _) = Playback::new(cast(&events), &options);
+        let times: Vec<f64> = playback.events.iter().map(|e| e.time).collect();
+        assert_eq!(times, [0.5, 1.5, 2.0]);
+        assert_eq!(handle.status().duration, 2.0);
+    }
+
+    #[test]
+    fn seek_restores_and_finished_fires_once_per_end() {
+        let options = PlaybackOptions {
+            speed: 100.0,
+            idle_time_limit: Some(1.0),
+        };
+        let events = [
+            (0.0, EventKind::Output, "a"),
+            (0.1, EventKind::Resize, "100x30"),
+            (5.0, EventKind::Output, "b"),
+            (5.1, EventKind::Output, "c"),
+        ];
+        let (playback, handle, commands) = Playback::new(cast(&events), &options);
+        let (sender, received) = mpsc::channel();
+        let player = thread::spawn(move || {
+            playback.run(commands, |event| {
+                let event = match event {
+                    PlaybackEvent::Output(data) => Emitted::Output(data.to_vec()),
+                    PlaybackEvent::Restore(data) => Emitted::Restore(data.to_vec()),
+                    PlaybackEvent::Resize(cols, rows) => Emitted::Resize(cols, rows),
+                    PlaybackEvent::Finished => Emitted::Finished,
+                };
+                sender.send(event).unwrap();
+            })
+        });
+        let next = || received.recv_timeout(Duration::from_secs(1)).unwrap();
+
+        assert_eq!(next(), Emitted::Output(b"a".to_vec()));
+        assert_eq!(next(), Emitted::Resize(100, 30));
+        assert_eq!(next(), Emitted::Output(b"b".to_vec()));
+        assert_eq!(next(), Emitted::Output(b"c".to_vec()));
+        assert_eq!(next(), Emitted::Finished);
+        assert!(received.recv_timeout(Duration::from_millis(100)).is_err());
+        assert!(handle.status().finished);
+
+        // Capped times are 0.0, 0.1, 1.1 and 1.2.
+        handle.send(PlaybackCommand::Seek(1.15));
+        assert_eq!(next(), Emitted::Resize(100, 30));
+        let mut restored = RESET_SEQUENCE.to_vec();
+        restored.extend_from_slice(b"ab");
+        assert_eq!(next(), Emitted::Restore(restored));
+        assert_eq!(next(), Emitted::Output(b"c".to_vec()));
+        assert_eq!(next(), Emitted::Finished);
+        assert!(received.recv_timeout(Duration::from_millis(100)).is_err());
+
+        handle.send(PlaybackCommand::Stop);
+        player.join().unwrap();
+    }
+}
@@ -0,0 +1,147 @@
+use super::{AsciicastEvent, AsciicastHeader, EventKind};
+use crate::terminal::Utf8Decoder;
+use crate::CoreError;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// Environment variables recorded in the header, as asciinema does.
+const RECORDED_ENV: [&str; 2] = ["SHELL", "TERM"];
+
+/// Writes an asciicast v2 stream as events arrive.
+pub struct AsciicastRecorder {
+    writer: Box<dyn Write + Send>,
+    started: Instant,
+    output: Utf8Decoder,
+    input: Utf8Decoder,
+}
+
+impl AsciicastRecorder {
+    pub fn create<P: AsRef<Path>>(path: P, width: u16, height: u16) -> Result<Self, CoreError> {
+        let mut header = AsciicastHeader::new(width, height);
+        header.timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .ok()
+            .map(|d| d.as_secs());
+        for key in RECORDED_ENV {
+            if let Ok(value) = std::env::var(key) {
+                header.env.insert(key.to_string(), value);
+            }
+        }
+
+        let file = File::create(path)?;
+        Self::new(Box::new(BufWriter::new(file)), &header)
+    }
+
+    pub fn new(
+        mut writer: Box<dyn Write + Send>,
+        header: &AsciicastHeader,
+    ) -> Result<Self, CoreError> {
+        serde_json::to_writer(&mut writer, header).map_err(std::io::Error::from)?;
+        writer.write_all(b"\n")?;
+
+        Ok(Self {
+            writer,
+            started: Instant::now(),
+            output: Utf8Decoder::default(),
+            input: Utf8Decoder::default(),
+        })
+    }
+
+    pub fn record_output(&mut self, data: &[u8]) -> Result<(), CoreError> {
+        let text = self.output.decode(data);
+        self.write_event(EventKind::Output, text)
+    }
+
+    pub fn record_input(&mut self, data: &[u8]) -> Result<(), CoreError> {
+        let text = self.input.decode(data);
+        self.write_event(EventKind::Input, text)
+    }
+
+    pub fn record_resize(&mut self, cols: u16, rows: u16) -> Result<(), CoreError> {
+        self.write_event(EventKind::Resize, format!("{}x{}", cols, rows))
+    }
+
+    pub fn finish(mut self) -> Result<(), CoreError> {
+        self.writer.flush().map_err(CoreError::Io)
+    }
+
+    fn write_event(&mut self, kind: EventKind, data: String) -> Result<(), CoreError> {
+        if data.is_empty() {
+            return Ok(());
+        }
+
+        // Microsecond precision, matching asciinema's own files.
+        let time = self.started.elapsed().as_micros() as f64 / 1_000_000.0;
+        let event = AsciicastEvent { time, kind, data };
+        serde_json::to_writer(&mut self.writer, &event).map_err(std::io::Error::from)?;
+        self.writer.write_all(b"\n")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terminal::Asciicast;
+    use parking_lot::Mutex;
+    use std::sync::Arc;
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn recording_parses_back_with_split_utf8_rejoined() {
+        let buffer = SharedBuffer::default();
+        let mut header = AsciicastHeader::new(80, 24);
+        header.title = Some("demo".to_string());
+        let mut recorder = AsciicastRecorder::new(Box::new(buffer.clone()), &header).unwrap();
+
+        // "é" is split across writes; the first event holds only "h".
+        recorder.record_output(b"h\xc3").unwrap();
+        recorder.record_output(b"\xa9llo\r\n").unwrap();
+        recorder.record_input(b"y").unwrap();
+        recorder.record_resize(100, 30).unwrap();
+        // A lone lead byte is held back rather than written as an event.
+        recorder.record_output(b"\xe4").unwrap();
+        recorder.finish().unwrap();
+
+        let bytes = buffer.0.lock().clone();
+        let text = String::from_utf8(bytes).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert!(lines[1].starts_with('[') && lines[1].ends_with(r#","o","h"]"#));
+        assert!(lines[2].ends_with(r#","o","éllo\r\n"]"#));
+
+        let cast = Asciicast::parse(text.as_bytes()).unwrap();
+        assert_eq!(cast.header, header);
+        let events: Vec<(EventKind, &str)> = cast
+            .events
+            .iter()
+            .map(|e| (e.kind, e.data.as_str()))
+            .collect();
+        assert_eq!(
+            events,
+            [
+                (EventKind::Output, "h"),
+                (EventKind::Output, "éllo\r\n"),
+                (EventKind::Input, "y"),
+                (EventKind::Resize, "100x30"),
+            ]
+        );
+        assert_eq!(cast.events[3].resize_size(), Some((100, 30)));
+        assert!(cast.events.windows(2).all(|w| w[0].time <= w[1].time));
+    }
+}
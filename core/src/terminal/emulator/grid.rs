@@ -0,0 +1,249 @@
+use serde::{Deserialize, Serialize};
+use unicode_width::UnicodeWidthChar;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Color {
+    #[default]
+    Default,
+    Indexed(u8),
+    Rgb(u8, u8, u8),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct CellAttributes {
+    pub fg: Color,
+    pub bg: Color,
+    pub bold: bool,
+    pub dim: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub inverse: bool,
+    pub hidden: bool,
+    pub strikethrough: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Cell {
+    pub ch: char,
+    pub attrs: CellAttributes,
+    /// 1 for normal cells, 2 for the first half of a wide glyph and 0 for the
+    /// spacer cell that follows it.
+    pub width: u8,
+}
+
+impl Cell {
+    pub fn blank(attrs: CellAttributes) -> Self {
+        Self {
+            ch: ' ',
+            // Erased cells keep only the background (BCE), like xterm.
+            attrs: CellAttributes {
+                bg: attrs.bg,
+                ..CellAttributes::default()
+            },
+            width: 1,
+        }
+    }
+
+    pub fn is_wide_spacer(&self) -> bool {
+        self.width == 0
+    }
+
+    /// Cells `c` takes when printed, or 0 when it is not printed at all.
+    pub(crate) fn width_of(c: char) -> usize {
+        match c.width() {
+            Some(w) => w.min(2),
+            None => 1,
+        }
+    }
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self::blank(CellAttributes::default())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Row {
+    pub cells: Vec<Cell>,
+    /// Set when the line continued onto the next row because of auto-wrap
+    /// rather than an explicit newline.
+    pub wrapped: bool,
+}
+
+impl Row {
+    pub fn new(cols: usize, attrs: CellAttributes) -> Self {
+        Self {
+            cells: vec![Cell::blank(attrs); cols],
+            wrapped: false,
+        }
+    }
+
+    pub fn text(&self) -> String {
+        let text: String = self
+            .cells
+            .iter()
+            .filter(|c| !c.is_wide_spacer())
+            .map(|c| c.ch)
+            .collect();
+        text.trim_end().to_string()
+    }
+
+    pub fn is_blank(&self) -> bool {
+        self.cells.iter().all(|c| c.ch == ' ' || c.is_wide_spacer())
+    }
+
+    pub(crate) fn resize(&mut self, cols: usize) {
+        self.cells.resize(cols, Cell::default());
+        self.clear_trailing_wide();
+    }
+
+    pub(crate) fn erase(&mut self, start: usize, end: usize, attrs: CellAttributes) {
+        let end = end.min(self.cells.len());
+        if start >= end {
+            return;
+        }
+        self.split_wide_at(start);
+        self.split_wide_at(end);
+        for cell in &mut self.cells[start..end] {
+            *cell = Cell::blank(attrs);
+        }
+    }
+
+    pub(crate) fn insert_blanks(&mut self, col: usize, count: usize, attrs: CellAttributes) {
+        let len = self.cells.len();
+        if col >= len {
+            return;
+        }
+        self.split_wide_at(col);
+        let count = count.min(len - col);
+        self.cells.truncate(len - count);
+        for _ in 0..count {
+            self.cells.insert(col, Cell::blank(attrs));
+        }
+        self.clear_trailing_wide();
+    }
+
+    pub(crate) fn delete_cells(&mut self, col: usize, count: usize, attrs: CellAttributes) {
+        let len = self.cells.len();
+        if col >= len {
+            return;
+        }
+        let count = count.min(len - col);
+        self.split_wide_at(col);
+        self.split_wide_at(col + count);
+        self.cells.drain(col..col + count);
+        self.cells.resize(len, Cell::blank(attrs));
+    }
+
+    /// A wide glyph whose spacer was pushed off the end cannot be drawn.
+    fn clear_trailing_wide(&mut self) {
+        if let Some(last) = self.cells.last_mut() {
+            if last.width == 2 {
+                *last = Cell::default();
+            }
+        }
+    }
+
+    /// Blanks both halves of a wide glyph straddling the boundary before `col`,
+    /// so edits never leave a dangling half.
+    pub(crate) fn split_wide_at(&mut self, col: usize) {
+        if col == 0 || col >= self.cells.len() {
+            return;
+        }
+        if self.cells[col].is_wide_spacer() && self.cells[col - 1].width == 2 {
+            self.cells[col - 1] = Cell::default();
+            self.cells[col] = Cell::default();
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Grid {
+    rows: Vec<Row>,
+    cols: usize,
+}
+
+impl Grid {
+    pub fn new(cols: usize, rows: usize) -> Self {
+        Self {
+            rows: (0..rows)
+                .map(|_| Row::new(cols, CellAttributes::default()))
+                .collect(),
+            cols,
+        }
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    pub fn rows(&self) -> &[Row] {
+        &self.rows
+    }
+
+    pub fn row(&self, index: usize) -> Option<&Row> {
+        self.rows.get(index)
+    }
+
+    pub(crate) fn row_mut(&mut self, index: usize) -> &mut Row {
+        &mut self.rows[index]
+    }
+
+    pub(crate) fn clear(&mut self, attrs: CellAttributes) {
+        for row in &mut self.rows {
+            *row = Row::new(self.cols, attrs);
+        }
+    }
+
+    /// Scrolls rows `top..=bottom` up by `count` and returns the rows that
+    /// fell off the top of the region.
+    pub(crate) fn scroll_up(
+        &mut self,
+        top: usize,
+        bottom: usize,
+        count: usize,
+        attrs: CellAttributes,
+    ) -> Vec<Row> {
+        let count = count.min(bottom + 1 - top);
+        let removed: Vec<Row> = self.rows.drain(top..top + count).collect();
+        let insert_at = bottom + 1 - count;
+        for _ in 0..count {
+            self.rows.insert(insert_at, Row::new(self.cols, attrs));
+        }
+        removed
+    }
+
+    pub(crate) fn scroll_down(
+        &mut self,
+        top: usize,
+        bottom: usize,
+        count: usize,
+        attrs: CellAttributes,
+    ) {
+        let count = count.min(bottom + 1 - top);
+        self.rows.drain(bottom + 1 - count..=bottom);
+        for _ in 0..count {
+            self.rows.insert(top, Row::new(self.cols, attrs));
+        }
+    }
+
+    /// Resizes without reflow. Returns rows removed from the top to keep
+    /// `keep_row` on screen when shrinking.
+    pub(crate) fn resize(&mut self, cols: usize, rows: usize, keep_row: usize) -> Vec<Row> {
+        let mut removed = Vec::new();
+        if keep_row >= rows {
+            let shift = (keep_row + 1 - rows).min(self.rows.len());
+            removed = self.rows.drain(..shift).collect();
+        }
+        self.rows.truncate(rows);
+        for row in &mut self.rows {
+            row.resize(cols);
+        }
+        while self.rows.len() < rows {
+            self.rows.push(Row::new(cols, CellAttributes::default()));
+        }
+        self.cols = cols;
+        removed
+    }
+}
@@ -0,0 +1,993 @@
+mod grid;
+mod scrollback;
+
+pub use grid::*;
+pub use scrollback::*;
+
+use serde::{Deserialize, Serialize};
+use vte::{Params, Parser, Perform};
+
+const TAB_WIDTH: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Cursor {
+    pub row: usize,
+    pub col: usize,
+    pub visible: bool,
+}
+
+/// VT100/xterm screen model fed with raw PTY output.
+///
+/// The emulator is passive: it never answers device queries, since the
+/// attached frontend already does.
+pub struct TerminalEmulator {
+    parser: Parser,
+    screen: Screen,
+}
+
+impl TerminalEmulator {
+    pub fn new(cols: u16, rows: u16) -> Self {
+        Self::with_scrollback(cols, rows, ScrollbackConfig::default())
+    }
+
+    pub fn with_scrollback(cols: u16, rows: u16, config: ScrollbackConfig) -> Self {
+        let mut screen = Screen::new(cols.max(1) as usize, rows.max(1) as usize);
+        screen.scrollback.set_config(config);
+        Self {
+            parser: Parser::new(),
+            screen,
+        }
+    }
+
+    pub fn process(&mut self, bytes: &[u8]) {
+        self.parser.advance(&mut self.screen, bytes);
+    }
+
+    pub fn resize(&mut self, cols: u16, rows: u16) {
+        self.screen
+            .resize(cols.max(1) as usize, rows.max(1) as usize);
+    }
+
+    pub fn cols(&self) -> usize {
+        self.screen.cols
+    }
+
+    pub fn rows(&self) -> usize {
+        self.screen.rows
+    }
+
+    pub fn cursor(&self) -> Cursor {
+        Cursor {
+            row: self.screen.cursor_row,
+            col: self.screen.cursor_col,
+            visible: self.screen.cursor_visible,
+        }
+    }
+
+    pub fn is_alternate_screen(&self) -> bool {
+        self.screen.alt_active
+    }
+
+    pub fn bracketed_paste(&self) -> bool {
+        self.screen.bracketed_paste
+    }
+
+    pub fn title(&self) -> &str {
+        &self.screen.title
+    }
+
+    /// Last directory reported through OSC 7.
+    pub fn working_directory(&self) -> Option<&str> {
+        self.screen.working_directory.as_deref()
+    }
+
+    pub fn visible_rows(&self) -> &[Row] {
+        self.screen.grid().rows()
+    }
+
+    pub fn row(&self, index: usize) -> Option<&Row> {
+        self.screen.grid().row(index)
+    }
+
+    pub fn cell(&self, row: usize, col: usize) -> Option<&Cell> {
+        self.row(row).and_then(|r| r.cells.get(col))
+    }
+
+    pub fn row_text(&self, index: usize) -> Option<String> {
+        self.row(index).map(Row::text)
+    }
+
+    pub fn cursor_row_text(&self) -> String {
+        self.row_text(self.screen.cursor_row).unwrap_or_default()
+    }
+
+    /// The cursor's line up to the cursor, such as a shell prompt without
+    /// anything drawn to its right.
+    pub fn text_before_cursor(&self) -> String {
+        let Some(row) = self.row(self.screen.cursor_row) else {
+            return String::new();
+        };
+        let text: String = row
+            .cells
+            .iter()
+            .take(self.screen.cursor_col)
+            .filter(|c| !c.is_wide_spacer())
+            .map(|c| c.ch)
+            .collect();
+        text.trim_end().to_string()
+    }
+
+    pub fn scrollback(&self) -> &Scrollback {
+        &self.screen.scrollback
+    }
+
+    pub fn set_scrollback_config(&mut self, config: ScrollbackConfig) {
+        self.screen.scrollback.set_config(config);
+    }
+
+    /// The last `count` lines of output: the tail of the scrollback followed
+    /// by the visible screen, without trailing blank rows.
+    pub fn recent_lines(&self, count: usize) -> Vec<ScrollbackLine> {
+        let mut screen: Vec<ScrollbackLine> = self
+            .visible_rows()
+            .iter()
+            .map(ScrollbackLine::from_row)
+            .collect();
+        while screen.last().is_some_and(|l| l.text.is_empty()) {
+            screen.pop();
+        }
+
+        let mut lines: Vec<ScrollbackLine> = self
+            .screen
+            .scrollback
+            .tail(count.saturating_sub(screen.len()))
+            .cloned()
+            .collect();
+        let skip = screen.len().saturating_sub(count);
+        lines.extend(screen.into_iter().skip(skip));
+        lines
+    }
+
+    /// Visible screen as text, one line per row, without trailing blank rows.
+    pub fn screen_text(&self) -> String {
+        let mut lines: Vec<String> = self.visible_rows().iter().map(Row::text).collect();
+        while lines.last().is_some_and(|l| l.is_empty()) {
+            lines.pop();
+        }
+        lines.join("\n")
+    }
+}
+
+#[derive(Clone)]
+struct SavedCursor {
+    row: usize,
+    col: usize,
+    attrs: CellAttributes,
+    origin_mode: bool,
+    line_drawing: bool,
+}
+
+struct Screen {
+    cols: usize,
+    rows: usize,
+    primary: Grid,
+    alternate: Grid,
+    alt_active: bool,
+
+    cursor_row: usize,
+    cursor_col: usize,
+    cursor_visible: bool,
+    wrap_pending: bool,
+    attrs: CellAttributes,
+
+    scroll_top: usize,
+    scroll_bottom: usize,
+    tab_stops: Vec<bool>,
+
+    auto_wrap: bool,
+    origin_mode: bool,
+    insert_mode: bool,
+    bracketed_paste: bool,
+    line_drawing: bool,
+
+    saved_cursor: Option<SavedCursor>,
+    alt_saved_cursor: Option<SavedCursor>,
+    last_printed: Option<char>,
+
+    title: String,
+    working_directory: Option<String>,
+    scrollback: Scrollback,
+}
+
+impl Screen {
+    fn new(cols: usize, rows: usize) -> Self {
+        Self {
+            cols,
+            rows,
+            primary: Grid::new(cols, rows),
+            alternate: Grid::new(cols, rows),
+            alt_active: false,
+            cursor_row: 0,
+            cursor_col: 0,
+            cursor_visible: true,
+            wrap_pending: false,
+            attrs: CellAttributes::default(),
+            scroll_top: 0,
+            scroll_bottom: rows - 1,
+            tab_stops: default_tab_stops(cols),
+            auto_wrap: true,
+            origin_mode: false,
+            insert_mode: false,
+            bracketed_paste: false,
+            line_drawing: false,
+            saved_cursor: None,
+            alt_saved_cursor: None,
+            last_printed: None,
+            title: String::new(),
+            working_directory: None,
+            scrollback: Scrollback::default(),
+        }
+    }
+
+    fn grid(&self) -> &Grid {
+        if self.alt_active {
+            &self.alternate
+        } else {
+            &self.primary
+        }
+    }
+
+    fn grid_mut(&mut self) -> &mut Grid {
+        if self.alt_active {
+            &mut self.alternate
+        } else {
+            &mut self.primary
+        }
+    }
+
+    fn resize(&mut self, cols: usize, rows: usize) {
+        let keep_row = self.cursor_row;
+        let removed = self.primary.resize(cols, rows, keep_row);
+        self.scrollback.push_rows(&removed);
+        let removed = removed.len();
+        self.alternate.resize(cols, rows, keep_row);
+
+        self.cols = cols;
+        self.rows = rows;
+        self.cursor_row = keep_row.saturating_sub(removed).min(rows - 1);
+        self.cursor_col = self.cursor_col.min(cols - 1);
+        self.wrap_pending = false;
+        self.scroll_top = 0;
+        self.scroll_bottom = rows - 1;
+        self.tab_stops = default_tab_stops(cols);
+    }
+
+    fn reset(&mut self) {
+        let title = std::mem::take(&mut self.title);
+        let working_directory = self.working_directory.take();
+        let scrollback = std::mem::take(&mut self.scrollback);
+        *self = Self::new(self.cols, self.rows);
+        self.title = title;
+        self.working_directory = working_directory;
+        self.scrollback = scrollback;
+    }
+
+    // Cursor movement
+
+    fn move_to(&mut self, row: usize, col: usize) {
+        let (min_row, max_row) = if self.origin_mode {
+            (self.scroll_top, self.scroll_bottom)
+        } else {
+            (0, self.rows - 1)
+        };
+        self.cursor_row = row.clamp(min_row, max_row);
+        self.cursor_col = col.min(self.cols - 1);
+        self.wrap_pending = false;
+    }
+
+    fn move_to_origin_relative(&mut self, row: usize, col: usize) {
+        let offset = if self.origin_mode { self.scroll_top } else { 0 };
+        self.move_to(row + offset, col);
+    }
+
+    fn move_up(&mut self, count: usize) {
+        let min_row = if self.cursor_row >= self.scroll_top {
+            self.scroll_top
+        } else {
+            0
+        };
+        self.cursor_row = self.cursor_row.saturating_sub(count).max(min_row);
+        self.wrap_pending = false;
+    }
+
+    fn move_down(&mut self, count: usize) {
+        let max_row = if self.cursor_row <= self.scroll_bottom {
+            self.scroll_bottom
+        } else {
+            self.rows - 1
+        };
+        self.cursor_row = (self.cursor_row + count).min(max_row);
+        self.wrap_pending = false;
+    }
+
+    fn move_right(&mut self, count: usize) {
+        self.cursor_col = (self.cursor_col + count).min(self.cols - 1);
+        self.wrap_pending = false;
+    }
+
+    fn move_left(&mut self, count: usize) {
+        self.cursor_col = self.cursor_col.saturating_sub(count);
+        self.wrap_pending = false;
+    }
+
+    fn carriage_return(&mut self) {
+        self.cursor_col = 0;
+        self.wrap_pending = false;
+    }
+
+    fn linefeed(&mut self) {
+        if self.cursor_row == self.scroll_bottom {
+            self.scroll_up(1);
+        } else if self.cursor_row < self.rows - 1 {
+            self.cursor_row += 1;
+        }
+        self.wrap_pending = false;
+    }
+
+    fn reverse_index(&mut self) {
+        if self.cursor_row == self.scroll_top {
+            self.scroll_down(1);
+        } else {
+            self.cursor_row = self.cursor_row.saturating_sub(1);
+        }
+        self.wrap_pending = false;
+    }
+
+    fn tab_forward(&mut self, count: usize) {
+        for _ in 0..count {
+            let next = (self.cursor_col + 1..self.cols).find(|&c| self.tab_stops[c]);
+            self.cursor_col = next.unwrap_or(self.cols - 1);
+        }
+        self.wrap_pending = false;
+    }
+
+    fn tab_backward(&mut self, count: usize) {
+        for _ in 0..count {
+            let prev = (0..self.cursor_col).rev().find(|&c| self.tab_stops[c]);
+            self.cursor_col = prev.unwrap_or(0);
+        }
+        self.wrap_pending = false;
+    }
+
+    fn save_cursor(&mut self) {
+        self.saved_cursor = Some(self.cursor_state());
+    }
+
+    fn restore_cursor(&mut self) {
+        let saved = self.saved_cursor.clone();
+        self.apply_cursor_state(saved);
+    }
+
+    fn cursor_state(&self) -> SavedCursor {
+        SavedCursor {
+            row: self.cursor_row,
+            col: self.cursor_col,
+            attrs: self.attrs,
+            origin_mode: self.origin_mode,
+            line_drawing: self.line_drawing,
+        }
+    }
+
+    fn apply_cursor_state(&mut self, saved: Option<SavedCursor>) {
+        match saved {
+            Some(saved) => {
+                self.cursor_row = saved.row.min(self.rows - 1);
+                self.cursor_col = saved.col.min(self.cols - 1);
+                self.attrs = saved.attrs;
+                self.origin_mode = saved.origin_mode;
+                self.line_drawing = saved.line_drawing;
+            }
+            None => {
+                self.cursor_row = 0;
+                self.cursor_col = 0;
+                self.attrs = CellAttributes::default();
+                self.origin_mode = false;
+                self.line_drawing = false;
+            }
+        }
+        self.wrap_pending = false;
+    }
+
+    // Scrolling and editing
+
+    fn scroll_up(&mut self, count: usize) {
+        let (top, bottom, attrs) = (self.scroll_top, self.scroll_bottom, self.attrs);
+        let removed = self.grid_mut().scroll_up(top, bottom, count, attrs);
+        // Like xterm, only full-width scrolls of the primary screen feed the
+        // scrollback; alternate-screen apps and scroll regions redraw in place.
+        if top == 0 && !self.alt_active {
+            self.scrollback.push_rows(&removed);
+        }
+    }
+
+    fn scroll_down(&mut self, count: usize) {
+        let (top, bottom, attrs) = (self.scroll_top, self.scroll_bottom, self.attrs);
+        self.grid_mut().scroll_down(top, bottom, count, attrs);
+    }
+
+    fn set_scroll_region(&mut self, top: usize, bottom: usize) {
+        let bottom = bottom.min(self.rows - 1);
+        if top < bottom {
+            self.scroll_top = top;
+            self.scroll_bottom = bottom;
+            self.move_to_origin_relative(0, 0);
+        }
+    }
+
+    fn erase_in_display(&mut self, mode: u16) {
+        let (row, col, attrs, cols) = (self.cursor_row, self.cursor_col, self.attrs, self.cols);
+        let rows = self.rows;
+        let grid = self.grid_mut();
+        match mode {
+            0 => {
+                grid.row_mut(row).erase(col, cols, attrs);
+                for r in row + 1..rows {
+                    grid.row_mut(r).erase(0, cols, attrs);
+                }
+            }
+            1 => {
+                for r in 0..row {
+                    grid.row_mut(r).erase(0, cols, attrs);
+                }
+                grid.row_mut(row).erase(0, col + 1, attrs);
+            }
+            2 => grid.clear(attrs),
+            3 => self.scrollback.clear(),
+            _ => {}
+        }
+        self.wrap_pending = false;
+    }
+
+    fn erase_in_line(&mut self, mode: u16) {
+        let (row, col, attrs, cols) = (self.cursor_row, self.cursor_col, self.attrs, self.cols);
+        let line = self.grid_mut().row_mut(row);
+        match mode {
+            0 => line.erase(col, cols, attrs),
+            1 => line.erase(0, col + 1, attrs),
+            2 => line.erase(0, cols, attrs),
+            _ => {}
+        }
+        self.wrap_pending = false;
+    }
+
+    fn insert_lines(&mut self, count: usize) {
+        if self.cursor_row < self.scroll_top || self.cursor_row > self.scroll_bottom {
+            return;
+        }
+        let (row, bottom, attrs) = (self.cursor_row, self.scroll_bottom, self.attrs);
+        self.grid_mut().scroll_down(row, bottom, count, attrs);
+        self.cursor_col = 0;
+        self.wrap_pending = false;
+    }
+
+    fn delete_lines(&mut self, count: usize) {
+        if self.cursor_row < self.scroll_top || self.cursor_row > self.scroll_bottom {
+            return;
+        }
+        let (row, bottom, attrs) = (self.cursor_row, self.scroll_bottom, self.attrs);
+        self.grid_mut().scroll_up(row, bottom, count, attrs);
+        self.cursor_col = 0;
+        self.wrap_pending = false;
+    }
+
+    fn set_alternate_screen(&mut self, enable: bool, save_cursor: bool) {
+        if enable == self.alt_active {
+            return;
+        }
+        if enable {
+            if save_cursor {
+                self.alt_saved_cursor = Some(self.cursor_state());
+            }
+            self.alt_active = true;
+            let attrs = self.attrs;
+            self.alternate.clear(attrs);
+        } else {
+            self.alt_active = false;
+            if save_cursor {
+                let saved = self.alt_saved_cursor.take();
+                self.apply_cursor_state(saved);
+            }
+        }
+        self.wrap_pending = false;
+    }
+
+    fn set_private_mode(&mut self, mode: u16, enable: bool) {
+        match mode {
+            6 => {
+                self.origin_mode = enable;
+                self.move_to_origin_relative(0, 0);
+            }
+            7 => self.auto_wrap = enable,
+            25 => self.cursor_visible = enable,
+            47 | 1047 => self.set_alternate_screen(enable, false),
+            1049 => self.set_alternate_screen(enable, true),
+            2004 => self.bracketed_paste = enable,
+            _ => {}
+        }
+    }
+
+    // Printing
+
+    fn put_char(&mut self, c: char) {
+        let c = if self.line_drawing {
+            translate_line_drawing(c)
+        } else {
+            c
+        };
+        let width = Cell::width_of(c);
+        if width == 0 {
+            return;
+        }
+
+        if self.wrap_pending && self.auto_wrap {
+            self.wrap_line();
+        }
+        if width == 2 && self.cursor_col + 1 >= self.cols {
+            if self.auto_wrap && self.cols > 1 {
+                let (row, col, attrs, cols) =
+                    (self.cursor_row, self.cursor_col, self.attrs, self.cols);
+                self.grid_mut().row_mut(row).erase(col, cols, attrs);
+                self.wrap_line();
+            } else {
+                return;
+            }
+        }
+
+        let (row, col, attrs, insert) = (
+            self.cursor_row,
+            self.cursor_col,
+            self.attrs,
+            self.insert_mode,
+        );
+        let line = self.grid_mut().row_mut(row);
+        if insert {
+            line.insert_blanks(col, width, attrs);
+        }
+        line.split_wide_at(col);
+        line.split_wide_at(col + width);
+        line.cells[col] = Cell {
+            ch: c,
+            attrs,
+            width: width as u8,
+        };
+        if width == 2 {
+            line.cells[col + 1] = Cell {
+                ch: ' ',
+                attrs,
+                width: 0,
+            };
+        }
+
+        self.last_printed = Some(c);
+        if self.cursor_col + width >= self.cols {
+            self.cursor_col = self.cols - 1;
+            self.wrap_pending = self.auto_wrap;
+        } else {
+            self.cursor_col += width;
+        }
+    }
+
+    fn wrap_line(&mut self) {
+        let row = self.cursor_row;
+        self.grid_mut().row_mut(row).wrapped = true;
+        self.linefeed();
+        self.cursor_col = 0;
+    }
+
+    fn set_graphics_rendition(&mut self, params: &Params) {
+        let mut iter = params.iter();
+        if params.is_empty() {
+            self.attrs = CellAttributes::default();
+            return;
+        }
+
+        while let Some(param) = iter.next() {
+            let attrs = &mut self.attrs;
+            match param[0] {
+                0 => *attrs = CellAttributes::default(),
+                1 => attrs.bold = true,
+                2 => attrs.dim = true,
+                3 => attrs.italic = true,
+                4 => attrs.underline = param.get(1) != Some(&0),
+                7 => attrs.inverse = true,
+                8 => attrs.hidden = true,
+                9 => attrs.strikethrough = true,
+                21 => attrs.underline = true,
+                22 => {
+                    attrs.bold = false;
+                    attrs.dim = false;
+                }
+                23 => attrs.italic = false,
+                24 => attrs.underline = false,
+                27 => attrs.inverse = false,
+                28 => attrs.hidden = false,
+                29 => attrs.strikethrough = false,
+                n @ 30..=37 => attrs.fg = Color::Indexed((n - 30) as u8),
+                38 => {
+                    if let Some(color) = parse_extended_color(param, &mut iter) {
+                        self.attrs.fg = color;
+                    }
+                }
+                39 => attrs.fg = Color::Default,
+                n @ 40..=47 => attrs.bg = Color::Indexed((n - 40) as u8),
+                48 => {
+                    if let Some(color) = parse_extended_color(param, &mut iter) {
+                        self.attrs.bg = color;
+                    }
+                }
+                49 => attrs.bg = Color::Default,
+                n @ 90..=97 => attrs.fg = Color::Indexed((n - 90 + 8) as u8),
+                n @ 100..=107 => attrs.bg = Color::Indexed((n - 100 + 8) as u8),
+                _ => {}
+            }
+        }
+    }
+
+    fn set_working_directory(&mut self, url: &str) {
+        let path = url
+            .strip_prefix("file://")
+            .map(|rest| rest.find('/').map(|i| &rest[i..]).unwrap_or(""))
+            .unwrap_or(url);
+        if !path.is_empty() {
+            self.working_directory = Some(percent_decode(path));
+        }
+    }
+}
+
+impl Perform for Screen {
+    fn print(&mut self, c: char) {
+        self.put_char(c);
+    }
+
+    fn execute(&mut self, byte: u8) {
+        match byte {
+            0x08 => self.move_left(1),
+            0x09 => self.tab_forward(1),
+            0x0A..=0x0C => self.linefeed(),
+            0x0D => self.carriage_return(),
+            _ => {}
+        }
+    }
+
+    fn csi_dispatch(&mut self, params: &Params, intermediates: &[u8], ignore: bool, action: char) {
+        if ignore {
+            return;
+        }
+
+        let values: Vec<u16> = params.iter().map(|p| p[0]).collect();
+        let arg = |index: usize, default: usize| -> usize {
+            match values.get(index) {
+                Some(&0) | None => default,
+                Some(&v) => v as usize,
+            }
+        };
+
+        if intermediates.first() == Some(&b'?') {
+            match action {
+                'h' | 'l' => {
+                    for &mode in &values {
+                        self.set_private_mode(mode, action == 'h');
+                    }
+                }
+                'J' => self.erase_in_display(values.first().copied().unwrap_or(0)),
+                'K' => self.erase_in_line(values.first().copied().unwrap_or(0)),
+                _ => {}
+            }
+            return;
+        }
+        if !intermediates.is_empty() {
+            return;
+        }
+
+        match action {
+            'A' => self.move_up(arg(0, 1)),
+            'B' | 'e' => self.move_down(arg(0, 1)),
+            'C' | 'a' => self.move_right(arg(0, 1)),
+            'D' => self.move_left(arg(0, 1)),
+            'E' => {
+                self.move_down(arg(0, 1));
+                self.carriage_return();
+            }
+            'F' => {
+                self.move_up(arg(0, 1));
+                self.carriage_return();
+            }
+            'G' | '`' => {
+                let row = self.cursor_row;
+                self.move_to(row, arg(0, 1) - 1);
+            }
+            'H' | 'f' => self.move_to_origin_relative(arg(0, 1) - 1, arg(1, 1) - 1),
+            'd' => {
+                let col = self.cursor_col;
+                self.move_to_origin_relative(arg(0, 1) - 1, col);
+            }
+            'I' => self.tab_forward(arg(0, 1)),
+            'Z' => self.tab_backward(arg(0, 1)),
+            'J' => self.erase_in_display(values.first().copied().unwrap_or(0)),
+            'K' => self.erase_in_line(values.first().copied().unwrap_or(0)),
+            'L' => self.insert_lines(arg(0, 1)),
+            'M' => self.delete_lines(arg(0, 1)),
+            '@' => {
+                let (row, col, attrs) = (self.cursor_row, self.cursor_col, self.attrs);
+                self.grid_mut()
+                    .row_mut(row)
+                    .insert_blanks(col, arg(0, 1), attrs);
+                self.wrap_pending = false;
+            }
+            'P' => {
+                let (row, col, attrs) = (self.cursor_row, self.cursor_col, self.attrs);
+                self.grid_mut()
+                    .row_mut(row)
+                    .delete_cells(col, arg(0, 1), attrs);
+                self.wrap_pending = false;
+            }
+            'X' => {
+                let (row, col, attrs) = (self.cursor_row, self.cursor_col, self.attrs);
+                self.grid_mut()
+                    .row_mut(row)
+                    .erase(col, col + arg(0, 1), attrs);
+                self.wrap_pending = false;
+            }
+            'S' => self.scroll_up(arg(0, 1)),
+            'T' => self.scroll_down(arg(0, 1)),
+            'b' => {
+                if let Some(c) = self.last_printed {
+                    for _ in 0..arg(0, 1).min(self.cols * self.rows) {
+                        self.put_char(c);
+                    }
+                }
+            }
+            'g' => match values.first().copied().unwrap_or(0) {
+                0 => self.tab_stops[self.cursor_col] = false,
+                3 => self.tab_stops.iter_mut().for_each(|t| *t = false),
+                _ => {}
+            },
+            'h' | 'l' if values.contains(&4) => self.insert_mode = action == 'h',
+            'm' => self.set_graphics_rendition(params),
+            'r' => self.set_scroll_region(arg(0, 1) - 1, arg(1, self.rows) - 1),
+            's' => self.save_cursor(),
+            'u' => self.restore_cursor(),
+            _ => {}
+        }
+    }
+
+    fn esc_dispatch(&mut self, intermediates: &[u8], _ignore: bool, byte: u8) {
+        match (intermediates, byte) {
+            ([], b'7') => self.save_cursor(),
+            ([], b'8') => self.restore_cursor(),
+            ([], b'D') => self.linefeed(),
+            ([], b'E') => {
+                self.linefeed();
+                self.carriage_return();
+            }
+            ([], b'H') => self.tab_stops[self.cursor_col] = true,
+            ([], b'M') => self.reverse_index(),
+            ([], b'c') => self.reset(),
+            ([b'('], b'0') => self.line_drawing = true,
+            ([b'('], _) => self.line_drawing = false,
+            _ => {}
+        }
+    }
+
+    fn osc_dispatch(&mut self, params: &[&[u8]], _bell_terminated: bool) {
+        let Some(kind) = params.first() else {
+            return;
+        };
+        let value = params[1..]
+            .iter()
+            .map(|p| String::from_utf8_lossy(p))
+            .collect::<Vec<_>>()
+            .join(";");
+
+        match *kind {
+            b"0" | b"2" => self.title = value,
+            b"7" => self.set_working_directory(&value),
+            _ => {}
+        }
+    }
+}
+
+fn default_tab_stops(cols: usize) -> Vec<bool> {
+    (0..cols).map(|c| c > 0 && c % TAB_WIDTH == 0).collect()
+}
+
+/// Handles both `38;5;n` / `38;2;r;g;b` and the colon sub-parameter forms.
+fn parse_extended_color<'a>(
+    param: &[u16],
+    rest: &mut impl Iterator<Item = &'a [u16]>,
+) -> Option<Color> {
+    let values: Vec<u16> = if param.len() > 1 {
+        param[1..].to_vec()
+    } else {
+        let mut values = vec![rest.next()?[0]];
+        let needed = match values[0] {
+            5 => 1,
+            2 => 3,
+            _ => 0,
+        };
+        for _ in 0..needed {
+            values.push(rest.next()?[0]);
+        }
+        values
+    };
+
+    match values.as_slice() {
+        [5, index, ..] => Some(Color::Indexed(*index as u8)),
+        // The colon form may carry a color-space id before the components.
+        [2, _, r, g, b] | [2, r, g, b, ..] => Some(Color::Rgb(*r as u8, *g as u8, *b as u8)),
+        _ => None,
+    }
+}
+
+fn translate_line_drawing(c: char) -> char {
+    match c {
+        'j' => '┘',
+        'k' => '┐',
+        'l' => '┌',
+        'm' => '└',
+        'n' => '┼',
+        'q' => '─',
+        't' => '├',
+        'u' => '┤',
+        'v' => '┴',
+        'w' => '┬',
+        'x' => '│',
+        '`' => '◆',
+        'a' => '▒',
+        'f' => '°',
+        'g' => '±',
+        '~' => '·',
+        _ => c,
+    }
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = |b: u8| (b as char).to_digit(16);
+            if let (Some(hi), Some(lo)) = (hex(bytes[i + 1]), hex(bytes[i + 2])) {
+                out.push((hi * 16 + lo) as u8);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn emulator(cols: u16, rows: u16, input: &str) -> TerminalEmulator {
+        let mut emulator = TerminalEmulator::new(cols, rows);
+        emulator.process(input.as_bytes());
+        emulator
+    }
+
+    fn cursor(emulator: &TerminalEmulator) -> (usize, usize) {
+        let cursor = emulator.cursor();
+        (cursor.row, cursor.col)
+    }
+
+    #[test]
+    fn cursor_movement_is_clamped_to_the_screen() {
+        let mut term = emulator(10, 5, "\x1b[99;99H");
+        assert_eq!(cursor(&term), (4, 9));
+        term.process(b"\x1b[99A\x1b[99D");
+        assert_eq!(cursor(&term), (0, 0));
+        term.process(b"\x1b[3;4H\x1b[99B\x1b[99C");
+        assert_eq!(cursor(&term), (4, 9));
+        term.process(b"\x1b[0;0H");
+        assert_eq!(cursor(&term), (0, 0));
+    }
+
+    #[test]
+    fn printing_past_the_last_column_wraps_on_the_next_char() {
+        let mut term = emulator(5, 3, "abcde");
+        assert_eq!(cursor(&term), (0, 4));
+        assert!(!term.row(0).unwrap().wrapped);
+
+        term.process(b"f");
+        assert_eq!(term.screen_text(), "abcde\nf");
+        assert!(term.row(0).unwrap().wrapped);
+        assert_eq!(cursor(&term), (1, 1));
+
+        let term = emulator(5, 3, "\x1b[?7labcdefg");
+        assert_eq!(term.screen_text(), "abcdg");
+    }
+
+    #[test]
+    fn scroll_region_scrolls_only_its_rows() {
+        let mut term = emulator(10, 5, "1\r\n2\r\n3\r\n4\r\n5\x1b[2;4r");
+        assert_eq!(cursor(&term), (0, 0));
+
+        term.process(b"\x1b[4;1H\nx");
+        assert_eq!(term.screen_text(), "1\n3\n4\nx\n5");
+        assert!(term.scrollback().is_empty());
+
+        // The full-screen region pushes scrolled rows to the scrollback.
+        term.process(b"\x1b[r\x1b[5;1H\n");
+        assert_eq!(term.screen_text(), "3\n4\nx\n5");
+        assert_eq!(term.scrollback().get(0).unwrap().text, "1");
+    }
+
+    #[test]
+    fn erase_modes_clear_the_expected_cells() {
+        let erased = |sequence: &str| {
+            let term = emulator(
+                10,
+                3,
+                &format!("abcdef\r\nghijkl\r\nmnopqr\x1b[2;3H{}", sequence),
+            );
+            term.visible_rows()
+                .iter()
+                .map(Row::text)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(erased("\x1b[K"), ["abcdef", "gh", "mnopqr"]);
+        assert_eq!(erased("\x1b[1K"), ["abcdef", "   jkl", "mnopqr"]);
+        assert_eq!(erased("\x1b[2K"), ["abcdef", "", "mnopqr"]);
+        assert_eq!(erased("\x1b[J"), ["abcdef", "gh", ""]);
+        assert_eq!(erased("\x1b[1J"), ["", "   jkl", "mnopqr"]);
+        assert_eq!(erased("\x1b[2J"), ["", "", ""]);
+        assert_eq!(erased("\x1b[2X"), ["abcdef", "gh  kl", "mnopqr"]);
+    }
+
+    #[test]
+    fn wide_characters_take_two_cells() {
+        let mut term = emulator(6, 2, "a中b");
+        assert_eq!(term.cell(0, 1).unwrap().width, 2);
+        assert!(term.cell(0, 2).unwrap().is_wide_spacer());
+        assert_eq!(term.row_text(0).unwrap(), "a中b");
+        assert_eq!(cursor(&term), (0, 4));
+
+        // Overwriting half of a wide char blanks the other half.
+        term.process(b"\x1b[1;3Hx");
+        assert_eq!(term.row_text(0).unwrap(), "a xb");
+
+        // A wide char that does not fit wraps whole.
+        let term = emulator(4, 2, "abc中");
+        assert_eq!(term.screen_text(), "abc\n中");
+        assert!(term.row(0).unwrap().wrapped);
+    }
+
+    #[test]
+    fn resize_keeps_the_cursor_row_without_reflowing() {
+        let mut term = emulator(10, 4, "1\r\n2\r\n3\r\n4");
+        term.resize(10, 2);
+        assert_eq!(term.screen_text(), "3\n4");
+        assert_eq!(cursor(&term), (1, 1));
+        let scrolled: Vec<&str> = term
+            .scrollback()
+            .range(0, 10)
+            .map(|line| line.text.as_str())
+            .collect();
+        assert_eq!(scrolled, ["1", "2"]);
+
+        // Rows are truncated and padded as they are, wrapped or not.
+        let mut term = emulator(6, 3, "abcdefgh");
+        term.resize(4, 3);
+        assert_eq!(term.screen_text(), "abcd\ngh");
+        assert_eq!(cursor(&term), (1, 2));
+        term.resize(8, 3);
+        assert_eq!(term.screen_text(), "abcd\ngh");
+        assert!(term.row(0).unwrap().wrapped);
+    }
+}
@@ -0,0 +1,266 @@
+use super::Row;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScrollbackConfig {
+    pub max_lines: usize,
+    /// Cap on the UTF-8 text retained, so a few very long lines cannot
+    /// outgrow the line cap's intent.
+    pub max_bytes: usize,
+}
+
+impl ScrollbackConfig {
+    pub const DEFAULT_MAX_LINES: usize = 10_000;
+    pub const DEFAULT_MAX_BYTES: usize = 4 * 1024 * 1024;
+
+    pub fn new(max_lines: usize, max_bytes: usize) -> Self {
+        Self {
+            max_lines,
+            max_bytes,
+        }
+    }
+}
+
+impl Default for ScrollbackConfig {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_MAX_LINES, Self::DEFAULT_MAX_BYTES)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScrollbackLine {
+    pub text: String,
+    /// The line continues on the next one because of auto-wrap.
+    pub wrapped: bool,
+}
+
+impl ScrollbackLine {
+    pub(crate) fn from_row(row: &Row) -> Self {
+        let text = if row.wrapped {
+            // Trailing spaces on a wrapped row are real content.
+            row.cells
+                .iter()
+                .filter(|c| !c.is_wide_spacer())
+                .map(|c| c.ch)
+                .collect()
+        } else {
+            row.text()
+        };
+        Self {
+            text,
+            wrapped: row.wrapped,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScrollbackStats {
+    pub lines: usize,
+    pub bytes: usize,
+    pub evicted_lines: u64,
+    pub evicted_bytes: u64,
+}
+
+/// Bounded history of lines scrolled off the top of the primary screen.
+///
+/// Lines are addressed by absolute index: the first line ever pushed is 0 and
+/// indices stay stable as old lines are evicted, so a position remembered by
+/// a caller stays valid until that line falls out of the buffer.
+#[derive(Debug, Clone)]
+pub struct Scrollback {
+    lines: VecDeque<ScrollbackLine>,
+    config: ScrollbackConfig,
+    bytes: usize,
+    evicted_lines: u64,
+    evicted_bytes: u64,
+}
+
+impl Scrollback {
+    pub fn new(config: ScrollbackConfig) -> Self {
+        Self {
+            lines: VecDeque::new(),
+            config,
+            bytes: 0,
+            evicted_lines: 0,
+            evicted_bytes: 0,
+        }
+    }
+
+    pub fn config(&self) -> ScrollbackConfig {
+        self.config
+    }
+
+    pub fn set_config(&mut self, config: ScrollbackConfig) {
+        self.config = config;
+        self.enforce_limits();
+    }
+
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    /// Absolute index of the oldest retained line.
+    pub fn first_index(&self) -> u64 {
+        self.evicted_lines
+    }
+
+    /// Absolute index one past the newest line.
+    pub fn end_index(&self) -> u64 {
+        self.evicted_lines + self.lines.len() as u64
+    }
+
+    pub fn get(&self, index: u64) -> Option<&ScrollbackLine> {
+        let offset = index.checked_sub(self.evicted_lines)?;
+        self.lines.get(usize::try_from(offset).ok()?)
+    }
+
+    /// Lines in `start..start + count`, clamped to what is still retained.
+    pub fn range(&self, start: u64, count: usize) -> impl Iterator<Item = &ScrollbackLine> {
+        let end = start.saturating_add(count as u64).min(self.end_index());
+        let start = start.max(self.first_index()).min(end);
+        let offset = (start - self.evicted_lines) as usize;
+        self.lines.iter().skip(offset).take((end - start) as usize)
+    }
+
+    /// The newest `count` lines, oldest first.
+    pub fn tail(&self, count: usize) -> impl Iterator<Item = &ScrollbackLine> {
+        self.lines
+            .iter()
+            .skip(self.lines.len().saturating_sub(count))
+    }
+
+    pub fn stats(&self) -> ScrollbackStats {
+        ScrollbackStats {
+            lines: self.lines.len(),
+            bytes: self.bytes,
+            evicted_lines: self.evicted_lines,
+            evicted_bytes: self.evicted_bytes,
+        }
+    }
+
+    pub fn push(&mut self, line: ScrollbackLine) {
+        self.bytes += line.text.len();
+        self.lines.push_back(line);
+        self.enforce_limits();
+    }
+
+    pub(crate) fn push_rows(&mut self, rows: &[Row]) {
+        for row in rows {
+            self.push(ScrollbackLine::from_row(row));
+        }
+    }
+
+    /// Drops every line. Cleared lines count as evicted so absolute indices
+    /// keep increasing.
+    pub fn clear(&mut self) {
+        self.evicted_lines += self.lines.len() as u64;
+        self.evicted_bytes += self.bytes as u64;
+        self.lines.clear();
+        self.bytes = 0;
+    }
+
+    fn enforce_limits(&mut self) {
+        while self.lines.len() > self.config.max_lines || self.bytes > self.config.max_bytes {
+            let Some(line) = self.lines.pop_front() else {
+                break;
+            };
+            self.bytes -= line.text.len();
+            self.evicted_lines += 1;
+            self.evicted_bytes += line.text.len() as u64;
+        }
+    }
+}
+
+impl Default for Scrollback {
+    fn default() -> Self {
+        Self::new(ScrollbackConfig::default())
+    }
+}
+
+/// Joins lines into text, ending a line only where the output did rather than
+/// at auto-wrap boundaries.
+pub fn join_lines<'a>(lines: impl IntoIterator<Item = &'a ScrollbackLine>) -> String {
+    let mut text = String::new();
+    let mut continued = true;
+    for line in lines {
+        if !continued {
+            text.push('\n');
+        }
+        text.push_str(&line.text);
+        continued = line.wrapped;
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terminal::emulator::TerminalEmulator;
+
+    fn line(text: &str) -> ScrollbackLine {
+        ScrollbackLine {
+            text: text.to_string(),
+            wrapped: false,
+        }
+    }
+
+    fn texts<'a>(lines: impl Iterator<Item = &'a ScrollbackLine>) -> Vec<&'a str> {
+        lines.map(|line| line.text.as_str()).collect()
+    }
+
+    #[test]
+    fn evicts_oldest_lines_past_either_cap_keeping_indices() {
+        let mut scrollback = Scrollback::new(ScrollbackConfig::new(3, 1024));
+        for text in ["a", "b", "c", "d", "e"] {
+            scrollback.push(line(text));
+        }
+        assert_eq!(texts(scrollback.tail(10)), ["c", "d", "e"]);
+        assert_eq!((scrollback.first_index(), scrollback.end_index()), (2, 5));
+        assert_eq!(scrollback.get(1), None);
+        assert_eq!(scrollback.get(3).unwrap().text, "d");
+        assert_eq!(texts(scrollback.range(0, 4)), ["c", "d"]);
+
+        scrollback.push(line("0123456789"));
+        scrollback.set_config(ScrollbackConfig::new(3, 11));
+        assert_eq!(texts(scrollback.tail(10)), ["e", "0123456789"]);
+        let stats = scrollback.stats();
+        assert_eq!((stats.lines, stats.bytes), (2, 11));
+        assert_eq!((stats.evicted_lines, stats.evicted_bytes), (4, 4));
+    }
+
+    #[test]
+    fn clear_counts_lines_as_evicted() {
+        let mut scrollback = Scrollback::default();
+        scrollback.push(line("a"));
+        scrollback.push(line("bc"));
+        scrollback.clear();
+        assert!(scrollback.is_empty());
+        assert_eq!(scrollback.first_index(), 2);
+        assert_eq!(scrollback.stats().evicted_bytes, 3);
+
+        scrollback.push(line("d"));
+        assert_eq!(scrollback.get(2).unwrap().text, "d");
+    }
+
+    #[test]
+    fn rows_scrolled_off_the_primary_screen_move_here() {
+        let mut term = TerminalEmulator::new(4, 2);
+        term.process(b"one\r\nabcdef\r\nx");
+        let scrollback = term.scrollback();
+        assert_eq!(texts(scrollback.tail(10)), ["one", "abcd"]);
+        assert!(scrollback.get(1).unwrap().wrapped);
+        assert_eq!(term.screen_text(), "ef\nx");
+        assert_eq!(join_lines(scrollback.tail(10)), "one\nabcd");
+
+        // The alternate screen never feeds the scrollback, and ED 3 clears it.
+        term.process(b"\x1b[?1049h1\r\n2\r\n3\r\n\x1b[?1049l");
+        assert_eq!(term.scrollback().len(), 2);
+        term.process(b"\x1b[3J");
+        assert!(term.scrollback().is_empty());
+    }
+}
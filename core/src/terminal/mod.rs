@@ -1,3 +1,13 @@
+mod asciicast;
+mod emulator;
 mod pty;
+mod shutdown;
+mod spawn;
+mod utf8;
 
+pub use asciicast::*;
+pub use emulator::*;
 pub use pty::*;
+pub use shutdown::*;
+pub use spawn::*;
+pub(crate) use utf8::*;
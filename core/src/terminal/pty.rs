@@ -1,36 +1,51 @@
+use super::{ShutdownPolicy, SpawnSpec};
+use crate::models::ExitStatus;
 use crate::CoreError;
+use crossbeam_channel::{Receiver, RecvTimeoutError, Sender, TryRecvError};
 use parking_lot::Mutex;
-use portable_pty::{native_pty_system, CommandBuilder, PtyPair, PtySize};
+use portable_pty::{native_pty_system, MasterPty, PtySize};
 use std::io::{Read, Write};
 use std::path::Path;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const WAIT_POLL_INTERVAL: Duration = Duration::from_millis(20);
+const KILL_REAP_TIMEOUT: Duration = Duration::from_millis(500);
+const READ_BUFFER_SIZE: usize = 8192;
+/// Chunks buffered before the reader thread blocks and back-pressures the child.
+const OUTPUT_CHANNEL_CAPACITY: usize = 256;
 
 pub struct PtyHandle {
-    pair: PtyPair,
+    master: Box<dyn MasterPty + Send>,
     writer: Arc<Mutex<Box<dyn Write + Send>>>,
-    reader: Arc<Mutex<Box<dyn Read + Send>>>,
+    output: Receiver<Vec<u8>>,
+    /// Remainder of a chunk that did not fit in the caller's buffer.
+    pending: Mutex<Vec<u8>>,
     child: Option<Box<dyn portable_pty::Child + Send + Sync>>,
+    exit_status: Option<ExitStatus>,
 }
 
 impl PtyHandle {
     pub fn spawn<P: AsRef<Path>>(working_dir: P, cols: u16, rows: u16) -> Result<Self, CoreError> {
+        let spec = SpawnSpec::new(working_dir.as_ref().to_string_lossy()).with_size(cols, rows);
+        Self::spawn_with_spec(&spec)
+    }
+
+    pub fn spawn_with_spec(spec: &SpawnSpec) -> Result<Self, CoreError> {
         let pty_system = native_pty_system();
 
         let pair = pty_system
             .openpty(PtySize {
-                rows,
-                cols,
+                rows: spec.rows,
+                cols: spec.cols,
                 pixel_width: 0,
                 pixel_height: 0,
             })
             .map_err(|e| CoreError::Pty(e.to_string()))?;
 
-        let mut cmd = CommandBuilder::new_default_prog();
-        cmd.cwd(working_dir.as_ref());
-
         let child = pair
             .slave
-            .spawn_command(cmd)
+            .spawn_command(spec.to_command())
             .map_err(|e| CoreError::Pty(e.to_string()))?;
 
         let writer = pair
@@ -43,11 +58,21 @@ impl PtyHandle {
             .try_clone_reader()
             .map_err(|e| CoreError::Pty(e.to_string()))?;
 
+        // Only the child keeps the slave open, so reads hit EOF once it exits.
+        drop(pair.slave);
+
+        let (sender, output) = crossbeam_channel::bounded(OUTPUT_CHANNEL_CAPACITY);
+        std::thread::Builder::new()
+            .name("pty-reader".to_string())
+            .spawn(move || pump_output(reader, sender))?;
+
         Ok(Self {
-            pair,
+            master: pair.master,
             writer: Arc::new(Mutex::new(writer)),
-            reader: Arc::new(Mutex::new(reader)),
+            output,
+            pending: Mutex::new(Vec::new()),
             child: Some(child),
+            exit_status: None,
         })
     }
 
@@ -56,14 +81,79 @@ impl PtyHandle {
         writer.write(data).map_err(CoreError::Io)
     }
 
+    pub fn write_all(&self, data: &[u8]) -> Result<(), CoreError> {
+        let mut writer = self.writer.lock();
+        writer.write_all(data)?;
+        writer.flush().map_err(CoreError::Io)
+    }
+
+    /// Blocks until output is available. Returns 0 once the PTY has closed.
     pub fn read(&self, buf: &mut [u8]) -> Result<usize, CoreError> {
-        let mut reader = self.reader.lock();
-        reader.read(buf).map_err(CoreError::Io)
+        if let Some(n) = self.take_pending(buf) {
+            return Ok(n);
+        }
+        match self.output.recv() {
+            Ok(chunk) => Ok(self.deliver(chunk, buf)),
+            Err(_) => Ok(0),
+        }
+    }
+
+    /// Returns `None` when no output is ready, `Some(0)` once the PTY has closed.
+    pub fn try_read(&self, buf: &mut [u8]) -> Result<Option<usize>, CoreError> {
+        if let Some(n) = self.take_pending(buf) {
+            return Ok(Some(n));
+        }
+        match self.output.try_recv() {
+            Ok(chunk) => Ok(Some(self.deliver(chunk, buf))),
+            Err(TryRecvError::Empty) => Ok(None),
+            Err(TryRecvError::Disconnected) => Ok(Some(0)),
+        }
+    }
+
+    /// Like `try_read`, but waits up to `timeout` for output to arrive.
+    pub fn read_timeout(
+        &self,
+        buf: &mut [u8],
+        timeout: Duration,
+    ) -> Result<Option<usize>, CoreError> {
+        if let Some(n) = self.take_pending(buf) {
+            return Ok(Some(n));
+        }
+        match self.output.recv_timeout(timeout) {
+            Ok(chunk) => Ok(Some(self.deliver(chunk, buf))),
+            Err(RecvTimeoutError::Timeout) => Ok(None),
+            Err(RecvTimeoutError::Disconnected) => Ok(Some(0)),
+        }
+    }
+
+    /// Receiver of raw output chunks, for consumers that want whole chunks
+    /// rather than copying into a buffer. Chunks are shared with `read` callers.
+    pub fn output_receiver(&self) -> Receiver<Vec<u8>> {
+        self.output.clone()
+    }
+
+    fn take_pending(&self, buf: &mut [u8]) -> Option<usize> {
+        let mut pending = self.pending.lock();
+        if pending.is_empty() {
+            return None;
+        }
+        let n = pending.len().min(buf.len());
+        buf[..n].copy_from_slice(&pending[..n]);
+        pending.drain(..n);
+        Some(n)
+    }
+
+    fn deliver(&self, chunk: Vec<u8>, buf: &mut [u8]) -> usize {
+        let n = chunk.len().min(buf.len());
+        buf[..n].copy_from_slice(&chunk[..n]);
+        if n < chunk.len() {
+            self.pending.lock().extend_from_slice(&chunk[n..]);
+        }
+        n
     }
 
     pub fn resize(&self, cols: u16, rows: u16) -> Result<(), CoreError> {
-        self.pair
-            .master
+        self.master
             .resize(PtySize {
                 rows,
                 cols,
@@ -74,25 +164,261 @@ impl PtyHandle {
     }
 
     pub fn is_alive(&mut self) -> bool {
-        if let Some(ref mut child) = self.child {
-            child.try_wait().ok().flatten().is_none()
-        } else {
-            false
+        if self.child.is_none() {
+            return false;
+        }
+        // A child that cannot be polled is not coming back.
+        match self.try_wait() {
+            Ok(status) => status.is_none(),
+            Err(e) => {
+                log::warn!("Treating unpollable child as exited: {}", e);
+                false
+            }
         }
     }
 
+    pub fn process_id(&self) -> Option<u32> {
+        self.child.as_ref().and_then(|c| c.process_id())
+    }
+
+    /// Polls the child without blocking. Returns the exit status once the
+    /// process has ended; the status is cached so later calls keep returning it.
+    pub fn try_wait(&mut self) -> Result<Option<ExitStatus>, CoreError> {
+        if self.exit_status.is_some() {
+            return Ok(self.exit_status.clone());
+        }
+
+        let Some(child) = self.child.as_mut() else {
+            return Ok(None);
+        };
+
+        let status = poll_child(child.as_mut())?;
+        if status.is_some() {
+            self.exit_status = status.clone();
+        }
+        Ok(status)
+    }
+
+    pub fn exit_status(&self) -> Option<&ExitStatus> {
+        self.exit_status.as_ref()
+    }
+
     pub fn terminate(&mut self) -> Result<(), CoreError> {
-        if let Some(mut child) = self.child.take() {
-            child.kill().map_err(|e| CoreError::Pty(e.to_string()))?;
+        self.terminate_with_policy(&ShutdownPolicy::default())
+            .map(|_| ())
+    }
+
+    /// Sends the policy's signal to the child's process group and waits up to
+    /// the grace period before escalating to SIGKILL. Blocks the caller.
+    pub fn terminate_with_policy(
+        &mut self,
+        policy: &ShutdownPolicy,
+    ) -> Result<Option<ExitStatus>, CoreError> {
+        if self.child.is_none() {
+            return Ok(self.exit_status.clone());
+        }
+
+        let mut status = self.try_wait()?;
+
+        if status.is_none() {
+            #[cfg(unix)]
+            {
+                self.signal_process_group(policy.signal.as_raw())?;
+                status = self.wait_timeout(policy.grace_period)?;
+                if status.is_none() {
+                    self.signal_process_group(libc::SIGKILL)?;
+                }
+            }
+
+            #[cfg(not(unix))]
+            if let Some(child) = self.child.as_mut() {
+                child.kill().map_err(|e| CoreError::Pty(e.to_string()))?;
+            }
+
+            if status.is_none() {
+                status = self.wait_timeout(KILL_REAP_TIMEOUT)?;
+            }
+            if status.is_none() {
+                // The kill cannot be caught or ignored, so this only waits
+                // out the kernel tearing the process down.
+                status = Some(self.wait_blocking()?);
+            }
+        }
+
+        self.child = None;
+        Ok(status)
+    }
+
+    fn wait_blocking(&mut self) -> Result<ExitStatus, CoreError> {
+        if let Some(status) = &self.exit_status {
+            return Ok(status.clone());
         }
-        Ok(())
+        let Some(child) = self.child.as_mut() else {
+            return Err(CoreError::Pty("no child to wait for".to_string()));
+        };
+        let status = wait_child(child.as_mut())?;
+        self.exit_status = Some(status.clone());
+        Ok(status)
     }
 
-    pub fn get_reader(&self) -> Arc<Mutex<Box<dyn Read + Send>>> {
-        self.reader.clone()
+    pub fn wait_timeout(&mut self, timeout: Duration) -> Result<Option<ExitStatus>, CoreError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(status) = self.try_wait()? {
+                return Ok(Some(status));
+            }
+            if Instant::now() >= deadline {
+                return Ok(None);
+            }
+            std::thread::sleep(WAIT_POLL_INTERVAL);
+        }
+    }
+
+    #[cfg(unix)]
+    fn signal_process_group(&self, signal: libc::c_int) -> Result<(), CoreError> {
+        let Some(pid) = self.process_id() else {
+            return Ok(());
+        };
+
+        // The child is spawned with setsid(), so its pid is also its process
+        // group id. Fall back to the pid alone if the group is already gone.
+        let pid = pid as libc::pid_t;
+        if unsafe { libc::kill(-pid, signal) } == 0 || unsafe { libc::kill(pid, signal) } == 0 {
+            return Ok(());
+        }
+
+        let err = std::io::Error::last_os_error();
+        if err.raw_os_error() == Some(libc::ESRCH) {
+            Ok(())
+        } else {
+            Err(CoreError::Io(err))
+        }
     }
 
     pub fn get_writer(&self) -> Arc<Mutex<Box<dyn Write + Send>>> {
         self.writer.clone()
     }
 }
+
+fn pump_output(mut reader: Box<dyn Read + Send>, sender: Sender<Vec<u8>>) {
+    let mut buf = [0u8; READ_BUFFER_SIZE];
+    loop {
+        match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => {
+                if sender.send(buf[..n].to_vec()).is_err() {
+                    break;
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => {
+                log::debug!("PTY reader stopped: {}", e);
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+fn poll_child(
+    child: &mut (dyn portable_pty::Child + Send + Sync),
+) -> Result<Option<ExitStatus>, CoreError> {
+    let Some(pid) = child.process_id() else {
+        return Ok(None);
+    };
+    reap(pid, libc::WNOHANG)
+}
+
+#[cfg(unix)]
+fn wait_child(
+    child: &mut (dyn portable_pty::Child + Send + Sync),
+) -> Result<ExitStatus, CoreError> {
+    let Some(pid) = child.process_id() else {
+        return Err(CoreError::Pty("child has no process id".to_string()));
+    };
+    Ok(reap(pid, 0)?.expect("a blocking waitpid returns a status"))
+}
+
+/// `waitpid` on the child. portable-pty only reports signals as strsignal()
+/// text, so reap the child ourselves to get the real signal number.
+#[cfg(unix)]
+fn reap(pid: u32, flags: libc::c_int) -> Result<Option<ExitStatus>, CoreError> {
+    let mut status: libc::c_int = 0;
+    loop {
+        let ret = unsafe { libc::waitpid(pid as libc::pid_t, &mut status, flags) };
+        if ret == 0 {
+            return Ok(None);
+        }
+        if ret > 0 {
+            break;
+        }
+        let err = std::io::Error::last_os_error();
+        match err.raw_os_error() {
+            Some(libc::EINTR) => continue,
+            // Someone else reaped the child, so it is gone but its status is lost.
+            Some(libc::ECHILD) => return Ok(Some(ExitStatus::unknown())),
+            _ => return Err(CoreError::Io(err)),
+        }
+    }
+
+    if libc::WIFSIGNALED(status) {
+        Ok(Some(ExitStatus::with_signal(libc::WTERMSIG(status))))
+    } else {
+        Ok(Some(ExitStatus::with_code(libc::WEXITSTATUS(status))))
+    }
+}
+
+#[cfg(not(unix))]
+fn wait_child(
+    child: &mut (dyn portable_pty::Child + Send + Sync),
+) -> Result<ExitStatus, CoreError> {
+    let status = child.wait()?;
+    Ok(ExitStatus::with_code(status.exit_code() as i32))
+}
+
+#[cfg(not(unix))]
+fn poll_child(
+    child: &mut (dyn portable_pty::Child + Send + Sync),
+) -> Result<Option<ExitStatus>, CoreError> {
+    let status = child.try_wait()?;
+    Ok(status.map(|s| ExitStatus::with_code(s.exit_code() as i32)))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::terminal::ShutdownSignal;
+
+    #[test]
+    fn child_reaped_elsewhere_counts_as_exited() {
+        let mut spec = SpawnSpec::new("/tmp").with_program("/bin/sleep", vec!["5".to_string()]);
+        spec.login_shell = false;
+        let mut pty = PtyHandle::spawn_with_spec(&spec).unwrap();
+        let pid = pty.process_id().unwrap() as libc::pid_t;
+
+        unsafe {
+            libc::kill(pid, libc::SIGKILL);
+            libc::waitpid(pid, std::ptr::null_mut(), 0);
+        }
+
+        assert!(!pty.is_alive());
+        assert_eq!(pty.try_wait().unwrap(), Some(ExitStatus::unknown()));
+    }
+
+    #[test]
+    fn terminate_reaps_a_child_that_ignores_the_signal() {
+        let mut spec = SpawnSpec::new("/tmp").with_program(
+            "/bin/sh",
+            vec!["-c".to_string(), "trap '' HUP; sleep 5".to_string()],
+        );
+        spec.login_shell = false;
+        let mut pty = PtyHandle::spawn_with_spec(&spec).unwrap();
+        std::thread::sleep(Duration::from_millis(200));
+
+        let policy = ShutdownPolicy::new(ShutdownSignal::Hangup, Duration::from_millis(100));
+        let status = pty.terminate_with_policy(&policy).unwrap();
+
+        assert_eq!(status, Some(ExitStatus::with_signal(libc::SIGKILL)));
+        assert!(!pty.is_alive());
+    }
+}
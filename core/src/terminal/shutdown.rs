@@ -0,0 +1,50 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[repr(C)]
+pub enum ShutdownSignal {
+    Hangup = 0,
+    Terminate = 1,
+}
+
+impl ShutdownSignal {
+    #[cfg(unix)]
+    pub(crate) fn as_raw(self) -> libc::c_int {
+        match self {
+            Self::Hangup => libc::SIGHUP,
+            Self::Terminate => libc::SIGTERM,
+        }
+    }
+}
+
+/// How a child process is asked to exit: `signal` goes to the whole process
+/// group, and anything still running after `grace_period` gets SIGKILL.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShutdownPolicy {
+    pub signal: ShutdownSignal,
+    pub grace_period: Duration,
+}
+
+impl ShutdownPolicy {
+    pub const DEFAULT_GRACE_PERIOD: Duration = Duration::from_secs(2);
+
+    pub fn new(signal: ShutdownSignal, grace_period: Duration) -> Self {
+        Self {
+            signal,
+            grace_period,
+        }
+    }
+
+    pub fn immediate() -> Self {
+        Self::new(ShutdownSignal::Hangup, Duration::ZERO)
+    }
+}
+
+impl Default for ShutdownPolicy {
+    fn default() -> Self {
+        // Interactive shells ignore SIGTERM but exit on SIGHUP and forward it
+        // to their jobs.
+        Self::new(ShutdownSignal::Hangup, Self::DEFAULT_GRACE_PERIOD)
+    }
+}
@@ -0,0 +1,111 @@
+use portable_pty::CommandBuilder;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpawnSpec {
+    pub working_dir: String,
+    /// Program to run. `None` starts the user's shell.
+    pub program: Option<String>,
+    pub args: Vec<String>,
+    pub env: Vec<(String, String)>,
+    pub env_remove: Vec<String>,
+    /// Run through a login shell so profile scripts set up `PATH` and friends.
+    /// With a `program`, it is launched as `$SHELL -l -c 'exec program args...'`.
+    pub login_shell: bool,
+    pub cols: u16,
+    pub rows: u16,
+}
+
+impl SpawnSpec {
+    pub const DEFAULT_COLS: u16 = 80;
+    pub const DEFAULT_ROWS: u16 = 24;
+
+    pub fn new(working_dir: impl Into<String>) -> Self {
+        Self {
+            working_dir: working_dir.into(),
+            program: None,
+            args: Vec::new(),
+            env: Vec::new(),
+            env_remove: Vec::new(),
+            login_shell: true,
+            cols: Self::DEFAULT_COLS,
+            rows: Self::DEFAULT_ROWS,
+        }
+    }
+
+    pub fn with_program(mut self, program: impl Into<String>, args: Vec<String>) -> Self {
+        self.program = Some(program.into());
+        self.args = args;
+        self
+    }
+
+    pub fn with_env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.env.push((key.into(), value.into()));
+        self
+    }
+
+    pub fn with_size(mut self, cols: u16, rows: u16) -> Self {
+        self.cols = cols;
+        self.rows = rows;
+        self
+    }
+
+    pub(crate) fn to_command(&self) -> CommandBuilder {
+        let mut cmd = match (&self.program, self.login_shell) {
+            (None, true) if self.args.is_empty() => CommandBuilder::new_default_prog(),
+            (None, login_shell) => {
+                let mut cmd = CommandBuilder::new(default_shell());
+                if login_shell {
+                    cmd.arg("-l");
+                }
+                cmd.args(&self.args);
+                cmd
+            }
+            (Some(program), false) => {
+                let mut cmd = CommandBuilder::new(program);
+                cmd.args(&self.args);
+                cmd
+            }
+            (Some(program), true) => {
+                let command_line = std::iter::once(program)
+                    .chain(&self.args)
+                    .map(|s| shell_quote(s))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                let mut cmd = CommandBuilder::new(default_shell());
+                cmd.args(["-l", "-c", &format!("exec {}", command_line)]);
+                cmd
+            }
+        };
+
+        cmd.cwd(&self.working_dir);
+        for key in &self.env_remove {
+            cmd.env_remove(key);
+        }
+        for (key, value) in &self.env {
+            cmd.env(key, value);
+        }
+        cmd
+    }
+}
+
+impl Default for SpawnSpec {
+    fn default() -> Self {
+        Self::new(String::new())
+    }
+}
+
+fn default_shell() -> String {
+    CommandBuilder::new_default_prog().get_shell()
+}
+
+fn shell_quote(s: &str) -> String {
+    if !s.is_empty()
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c))
+    {
+        s.to_string()
+    } else {
+        format!("'{}'", s.replace('\'', r"'\''"))
+    }
+}